@@ -0,0 +1,16 @@
+use fastrand::Rng;
+use mapgen::cave::{ConnectRegions, MazeBuilder, RemoveDeadEnds};
+use mapgen::{rooms::SimpleRooms, CaveMap, MapBuilder};
+
+fn main() {
+    let mut rng = Rng::with_seed(907647352);
+    let rooms = SimpleRooms::default().generate(80, 50, &mut rng);
+    let map = MapBuilder::from_map(CaveMap::from_layer(rooms.walkable_layer))
+        .with(MazeBuilder::new().keep_existing())
+        .with(ConnectRegions::new(0.05))
+        .with(RemoveDeadEnds::new())
+        .build_with_rng(&mut rng);
+
+    println!("{:}", &map);
+    println!("Doors: {:?}", map.doors);
+}
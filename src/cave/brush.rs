@@ -0,0 +1,249 @@
+//! Brushes used to paint (carve or erase) tiles on the map.
+//!
+//! The same brush is used by generators (e.g. `DrunkardsWalk`) and can be used
+//! directly by map editors.
+//!
+//! Example usage:
+//! ```
+//! use mapgen::CaveMap;
+//! use mapgen::cave::{Brush, Symmetry};
+//!
+//! let mut map = CaveMap::new(20, 20);
+//! map.paint(Symmetry::None, &Brush::circle(5), 10, 10);
+//!
+//! assert!(map.is_walkable(10, 10));
+//! assert!(map.is_blocked(8, 8));
+//! ```
+//!
+
+use crate::layer::WalkableLayer;
+
+/// Shape of the painted area
+#[derive(Clone, Debug, PartialEq)]
+pub enum BrushShape {
+    Square,
+    Circle,
+    Diamond,
+    /// Custom stamp. Walkable tiles of the layer are painted, centered on the brush position.
+    Stamp(WalkableLayer),
+}
+
+/// What the brush does with the tiles it covers
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum PaintMode {
+    /// Make tiles walkable
+    Carve,
+    /// Make tiles blocked
+    Erase,
+}
+
+/// Brush definition
+#[derive(Clone, Debug, PartialEq)]
+pub struct Brush {
+    pub shape: BrushShape,
+    pub size: u32,
+    pub mode: PaintMode,
+    /// Number of tiles near the map border which will never be painted
+    pub margin: u32,
+}
+
+impl Brush {
+    /// Create carving brush with the given shape and size.
+    /// By default the brush doesn't paint the outermost tiles of the map.
+    pub fn new(shape: BrushShape, size: u32) -> Brush {
+        Brush {
+            shape,
+            size: u32::max(size, 1),
+            mode: PaintMode::Carve,
+            margin: 1,
+        }
+    }
+
+    pub fn square(size: u32) -> Brush {
+        Self::new(BrushShape::Square, size)
+    }
+
+    pub fn circle(size: u32) -> Brush {
+        Self::new(BrushShape::Circle, size)
+    }
+
+    pub fn diamond(size: u32) -> Brush {
+        Self::new(BrushShape::Diamond, size)
+    }
+
+    /// Create brush from the custom stamp. Size of the brush is the size of the stamp.
+    pub fn stamp(layer: WalkableLayer) -> Brush {
+        let size = u32::max(layer.width, layer.height);
+        Self::new(BrushShape::Stamp(layer), size)
+    }
+
+    pub fn with_mode(mut self, mode: PaintMode) -> Brush {
+        self.mode = mode;
+        self
+    }
+
+    pub fn with_margin(mut self, margin: u32) -> Brush {
+        self.margin = margin;
+        self
+    }
+
+    /// Tile offsets (relative to the brush position) covered by this brush
+    pub fn offsets(&self) -> Vec<(i64, i64)> {
+        match &self.shape {
+            BrushShape::Stamp(layer) => {
+                let ox = (layer.width as i64 - 1) / 2;
+                let oy = (layer.height as i64 - 1) / 2;
+                let mut offsets = Vec::new();
+                for y in 0..layer.height {
+                    for x in 0..layer.width {
+                        if layer.is_walkable(x, y) {
+                            offsets.push((x as i64 - ox, y as i64 - oy));
+                        }
+                    }
+                }
+                offsets
+            }
+            shape => {
+                let size = self.size as i64;
+                let offset = (size - 1) / 2;
+                // Brush center in local coordinates. For even sizes it lies between tiles.
+                let center = (size - 1) as f32 / 2.0;
+                let radius = size as f32 / 2.0;
+                let mut offsets = Vec::new();
+                for j in 0..size {
+                    for i in 0..size {
+                        let dx = i as f32 - center;
+                        let dy = j as f32 - center;
+                        let inside = match shape {
+                            BrushShape::Circle => dx * dx + dy * dy <= radius * radius,
+                            BrushShape::Diamond => dx.abs() + dy.abs() <= center + 0.5,
+                            _ => true,
+                        };
+                        if inside {
+                            offsets.push((i - offset, j - offset));
+                        }
+                    }
+                }
+                offsets
+            }
+        }
+    }
+
+    /// Paint the layer at the given position.
+    /// Tiles outside of the map or inside the border margin are skipped.
+    /// Returns the number of tiles which changed their state.
+    pub fn apply(&self, layer: &mut WalkableLayer, x: u32, y: u32) -> u32 {
+        let walkable = self.mode == PaintMode::Carve;
+        let margin = self.margin as i64;
+        let max_x = layer.width as i64 - margin;
+        let max_y = layer.height as i64 - margin;
+        let mut changed = 0;
+        for (dx, dy) in self.offsets() {
+            let tx = x as i64 + dx;
+            let ty = y as i64 + dy;
+            if tx >= margin && tx < max_x && ty >= margin && ty < max_y {
+                let (tx, ty) = (tx as u32, ty as u32);
+                if layer.is_walkable(tx, ty) != walkable {
+                    layer.set_walkable(tx, ty, walkable);
+                    changed += 1;
+                }
+            }
+        }
+        changed
+    }
+}
+
+impl Default for Brush {
+    fn default() -> Self {
+        Self::square(1)
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_shapes() {
+        assert_eq!(Brush::square(1).offsets(), vec![(0, 0)]);
+        assert_eq!(Brush::square(3).offsets().len(), 9);
+        assert_eq!(Brush::diamond(3).offsets().len(), 5);
+        assert_eq!(Brush::circle(5).offsets().len(), 21);
+    }
+
+    #[test]
+    fn test_no_underflow_near_border() {
+        let mut layer = WalkableLayer::new(10, 10);
+        Brush::square(5).apply(&mut layer, 0, 0);
+        let expected = "
+        ##########
+        #  #######
+        #  #######
+        ##########
+        ##########
+        ##########
+        ##########
+        ##########
+        ##########
+        ##########
+        ";
+        assert_eq!(layer, WalkableLayer::from_string(expected));
+    }
+
+    #[test]
+    fn test_margin() {
+        let mut layer = WalkableLayer::new(5, 5);
+        let changed = Brush::square(5).with_margin(0).apply(&mut layer, 2, 2);
+        assert!(layer.tiles.iter().all(|&t| t));
+        assert_eq!(changed, 25);
+        assert_eq!(Brush::square(3).apply(&mut layer, 2, 2), 0);
+    }
+
+    #[test]
+    fn test_erase() {
+        let mut layer = WalkableLayer::from_string(
+            "
+            #####
+            #   #
+            #   #
+            #   #
+            #####
+            ",
+        );
+        Brush::diamond(3)
+            .with_mode(PaintMode::Erase)
+            .apply(&mut layer, 2, 2);
+        let expected = "
+        #####
+        # # #
+        #####
+        # # #
+        #####
+        ";
+        assert_eq!(layer, WalkableLayer::from_string(expected));
+    }
+
+    #[test]
+    fn test_stamp() {
+        let stamp = WalkableLayer::from_string(
+            "
+            # #
+            ###
+            # #
+            ",
+        );
+        let mut layer = WalkableLayer::new(5, 5);
+        Brush::stamp(stamp).apply(&mut layer, 2, 2);
+        let expected = "
+        #####
+        ## ##
+        #####
+        ## ##
+        #####
+        ";
+        assert_eq!(layer, WalkableLayer::from_string(expected));
+    }
+}
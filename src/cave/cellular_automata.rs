@@ -20,15 +20,132 @@
 //! assert_eq!(map.height, 50);
 //! ```
 //!
+//! Rules can be given in the B/S notation and chained into several phases:
+//! ```
+//! use mapgen::{CaveMap, MapFilter};
+//! use mapgen::cave::{CellularAutomata, Rule};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let rule: Rule = "B678/S345678".parse().unwrap();
+//! let gen = CellularAutomata::from_rule(rule, 5).then(Rule::four_five(), 2);
+//! let map = gen.modify_map(&mut rng, &CaveMap::new(80, 50));
+//!
+//! assert_eq!(map.width, 80);
+//! ```
+//!
+
+use std::str::FromStr;
 
 use fastrand::Rng;
 
 use crate::CaveMap;
 use crate::MapFilter;
 
+/// Cellular automata rule.
+/// Walls are treated as "alive" cells, so `B678/S345678` means that floor becomes a wall
+/// when it has 6, 7 or 8 wall neighbours and the wall survives with 3 to 8 wall neighbours.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Rule {
+    /// Number of wall neighbours which turns floor into the wall
+    pub birth: Vec<u32>,
+    /// Number of wall neighbours with which the wall stays a wall
+    pub survival: Vec<u32>,
+    /// Neighbourhood radius. Radius 1 is the Moore neighbourhood (8 tiles)
+    pub radius: u32,
+    /// "Open space" term: tile also becomes a wall if there are at most this many walls
+    /// in the radius 2 (counting the tile itself). Prevents large empty areas.
+    pub open_space: Option<u32>,
+}
+
+impl Rule {
+    pub fn new(birth: &[u32], survival: &[u32]) -> Rule {
+        Rule {
+            birth: birth.to_vec(),
+            survival: survival.to_vec(),
+            radius: 1,
+            open_space: None,
+        }
+    }
+
+    /// The classic 4-5 rule: tile is a wall if there are at least 5 walls in its 3x3 area.
+    pub fn four_five() -> Rule {
+        Self::new(&[5, 6, 7, 8], &[4, 5, 6, 7, 8])
+    }
+
+    pub fn with_radius(mut self, radius: u32) -> Rule {
+        self.radius = u32::max(radius, 1);
+        self
+    }
+
+    pub fn with_open_space(mut self, max_walls: u32) -> Rule {
+        self.open_space = Some(max_walls);
+        self
+    }
+
+    fn is_wall(&self, wall: bool, neighbors: u32, walls_r2: u32) -> bool {
+        let by_count = if wall {
+            self.survival.contains(&neighbors)
+        } else {
+            self.birth.contains(&neighbors)
+        };
+        by_count || self.open_space.is_some_and(|max| walls_r2 <= max)
+    }
+}
+
+impl Default for Rule {
+    /// Floor survives only with 1 to 4 wall neighbours
+    fn default() -> Self {
+        Self::new(&[0, 5, 6, 7, 8], &[0, 5, 6, 7, 8])
+    }
+}
+
+impl FromStr for Rule {
+    type Err = String;
+
+    /// Parse rule in the B/S notation, e.g. `B678/S345678`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse_digits = |part: &str, prefix: char| -> Result<Vec<u32>, String> {
+            let digits = part
+                .trim()
+                .strip_prefix(prefix)
+                .or_else(|| part.trim().strip_prefix(prefix.to_ascii_lowercase()))
+                .ok_or(format!("Expected '{}' in rule part: {}", prefix, part))?;
+            digits
+                .chars()
+                .map(|c| c.to_digit(10).ok_or(format!("Not a digit: {}", c)))
+                .collect()
+        };
+        let (birth, survival) = s
+            .split_once('/')
+            .ok_or(format!("Expected B/S notation: {}", s))?;
+        let birth = parse_digits(birth, 'B')?;
+        let survival = parse_digits(survival, 'S')?;
+        Ok(Rule::new(&birth, &survival))
+    }
+}
+
+/// How tiles outside of the map are counted when looking at neighbours.
+/// With the `Wall` mode border tiles stay unchanged, other modes update them too.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum BorderMode {
+    Wall,
+    Floor,
+    /// Wrap around to the opposite side of the map
+    Wrap,
+}
+
+/// Single step of the generation: rule applied given number of times
+#[derive(Clone, Debug, PartialEq)]
+pub struct Phase {
+    pub rule: Rule,
+    pub iterations: u32,
+}
+
 /// Map filter
 pub struct CellularAutomata {
-    num_iteraction: u32,
+    phases: Vec<Phase>,
+    border: BorderMode,
 }
 
 impl MapFilter for CellularAutomata {
@@ -40,38 +157,102 @@ impl MapFilter for CellularAutomata {
 impl CellularAutomata {
     /// Create generator which will create map with the given dimension.
     pub fn new() -> Box<CellularAutomata> {
-        Box::new(CellularAutomata { num_iteraction: 15 })
+        Self::from_rule(Rule::default(), 15)
+    }
+
+    /// Create generator which will apply given rule
+    pub fn from_rule(rule: Rule, iterations: u32) -> Box<CellularAutomata> {
+        Box::new(CellularAutomata {
+            phases: vec![Phase { rule, iterations }],
+            border: BorderMode::Wall,
+        })
+    }
+
+    /// Recipe from the roguebasin article: 4 iterations of the 4-5 rule with the open space term
+    /// followed by 3 iterations of the plain 4-5 rule to smooth the walls.
+    pub fn four_five() -> Box<CellularAutomata> {
+        Self::from_rule(Rule::four_five().with_open_space(2), 4).then(Rule::four_five(), 3)
+    }
+
+    /// Set number of iterations of the last phase
+    pub fn with_iterations(mut self: Box<Self>, iterations: u32) -> Box<CellularAutomata> {
+        if let Some(phase) = self.phases.last_mut() {
+            phase.iterations = iterations;
+        }
+        self
+    }
+
+    pub fn with_border(mut self: Box<Self>, border: BorderMode) -> Box<CellularAutomata> {
+        self.border = border;
+        self
+    }
+
+    /// Add next phase which will be applied after the previous ones
+    pub fn then(mut self: Box<Self>, rule: Rule, iterations: u32) -> Box<CellularAutomata> {
+        self.phases.push(Phase { rule, iterations });
+        self
     }
 
     /// Generate map
     fn build(&self, map: &CaveMap) -> CaveMap {
         let mut new_map = map.clone();
-        for _ in 0..self.num_iteraction {
-            new_map = apply_iteration(&new_map);
+        for phase in self.phases.iter() {
+            for _ in 0..phase.iterations {
+                new_map = apply_iteration(&new_map, &phase.rule, self.border);
+            }
         }
 
         new_map
     }
 }
 
-fn apply_iteration(map: &CaveMap) -> CaveMap {
+/// Count walls in the square of the given radius (without the center tile)
+fn count_walls(map: &CaveMap, x: u32, y: u32, radius: u32, border: BorderMode) -> u32 {
+    let r = radius as i64;
+    let (w, h) = (map.width as i64, map.height as i64);
+    let mut count = 0;
+    for dy in -r..=r {
+        for dx in -r..=r {
+            if dx == 0 && dy == 0 {
+                continue;
+            }
+            let nx = x as i64 + dx;
+            let ny = y as i64 + dy;
+            let wall = if nx >= 0 && nx < w && ny >= 0 && ny < h {
+                map.is_blocked(nx as u32, ny as u32)
+            } else {
+                match border {
+                    BorderMode::Wall => true,
+                    BorderMode::Floor => false,
+                    BorderMode::Wrap => {
+                        map.is_blocked(nx.rem_euclid(w) as u32, ny.rem_euclid(h) as u32)
+                    }
+                }
+            };
+            if wall {
+                count += 1;
+            }
+        }
+    }
+    count
+}
+
+fn apply_iteration(map: &CaveMap, rule: &Rule, border: BorderMode) -> CaveMap {
     let mut new_map = map.clone();
+    // Wall border keeps the map closed, other modes update the border tiles too
+    let margin = if border == BorderMode::Wall { 1 } else { 0 };
+
+    for y in margin..map.height - margin {
+        for x in margin..map.width - margin {
+            let wall = map.is_blocked(x, y);
+            let neighbors = count_walls(map, x, y, rule.radius, border);
+            let walls_r2 = if rule.open_space.is_some() {
+                count_walls(map, x, y, 2, border) + wall as u32
+            } else {
+                0
+            };
 
-    for y in 1..map.height - 1 {
-        for x in 1..map.width - 1 {
-            let idxs = [
-                (x - 1, y - 1),
-                (x, y - 1),
-                (x + 1, y - 1),
-                (x - 1, y),
-                (x + 1, y),
-                (x - 1, y + 1),
-                (x, y + 1),
-                (x + 1, y + 1),
-            ];
-            let neighbors = idxs.iter().filter(|(x, y)| map.is_blocked(*x, *y)).count();
-
-            let walkable = neighbors < 5 && neighbors > 0;
+            let walkable = !rule.is_wall(wall, neighbors, walls_r2);
             new_map.set_walkable(x, y, walkable);
         }
     }
@@ -89,7 +270,7 @@ mod tests {
     #[test]
     fn test_iteration_wal() {
         let map = CaveMap::new(3, 3);
-        let new_map = apply_iteration(&map);
+        let new_map = apply_iteration(&map, &Rule::default(), BorderMode::Wall);
         assert!(new_map.is_blocked(1, 1));
     }
 
@@ -101,7 +282,61 @@ mod tests {
                 map.set_walkable(i, j, true);
             }
         }
-        let new_map = apply_iteration(&map);
+        let new_map = apply_iteration(&map, &Rule::default(), BorderMode::Wall);
         assert!(new_map.is_walkable(1, 1));
     }
+
+    #[test]
+    fn test_parse_rule() {
+        let rule: Rule = "B678/S345678".parse().unwrap();
+        assert_eq!(rule, Rule::new(&[6, 7, 8], &[3, 4, 5, 6, 7, 8]));
+        assert!("B678".parse::<Rule>().is_err());
+        assert!("B6x/S3".parse::<Rule>().is_err());
+    }
+
+    #[test]
+    fn test_open_space() {
+        let mut map = CaveMap::new(7, 7);
+        for x in 1..6 {
+            for y in 1..6 {
+                map.set_walkable(x, y, true);
+            }
+        }
+        let new_map = apply_iteration(&map, &Rule::four_five(), BorderMode::Wall);
+        assert!(new_map.is_walkable(3, 3));
+        let rule = Rule::four_five().with_open_space(2);
+        let new_map = apply_iteration(&map, &rule, BorderMode::Wall);
+        assert!(new_map.is_blocked(3, 3));
+    }
+
+    #[test]
+    fn test_border_mode() {
+        let mut map = CaveMap::new(3, 3);
+        map.set_walkable(1, 1, true);
+        let rule = Rule::new(&[24], &[]).with_radius(2);
+        let new_map = apply_iteration(&map, &rule, BorderMode::Floor);
+        assert!(new_map.is_walkable(1, 1));
+        let new_map = apply_iteration(&map, &rule, BorderMode::Wall);
+        assert!(new_map.is_blocked(1, 1));
+        let new_map = apply_iteration(&map, &rule, BorderMode::Wrap);
+        assert!(new_map.is_blocked(1, 1));
+    }
+
+    #[test]
+    fn test_border_mode_radius_1() {
+        // Right column is the wall
+        let mut map = CaveMap::new(4, 4);
+        for x in 0..3 {
+            for y in 0..4 {
+                map.set_walkable(x, y, true);
+            }
+        }
+        let rule = Rule::new(&[3], &[3]);
+        let wall = apply_iteration(&map, &rule, BorderMode::Wall);
+        let floor = apply_iteration(&map, &rule, BorderMode::Floor);
+        let wrap = apply_iteration(&map, &rule, BorderMode::Wrap);
+        assert!(wall.is_blocked(3, 1) && wall.is_walkable(0, 1));
+        assert!(floor.is_walkable(3, 1) && floor.is_walkable(0, 1));
+        assert!(wrap.is_walkable(3, 1) && wrap.is_blocked(0, 1));
+    }
 }
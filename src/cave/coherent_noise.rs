@@ -0,0 +1,108 @@
+//! Create cave shape from the coherent noise.
+//! Each cell with the noise value above the threshold will be set to Floor.
+//!
+//! Example usage:
+//! ```
+//! use mapgen::{CaveMap, MapFilter};
+//! use mapgen::cave::CoherentNoise;
+//! use mapgen::noise::{Fbm, NoiseKind};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let fbm = Fbm::new(NoiseKind::Simplex).with_octaves(3).with_warp(8.0);
+//! let gen = CoherentNoise::new(fbm, 0.5).keep_noise();
+//! let map = gen.modify_map(&mut rng, &CaveMap::new(80, 50));
+//!
+//! assert_eq!(map.width, 80);
+//! assert_eq!(map.height, 50);
+//! assert!(map.noise_layer.is_some());
+//! ```
+//!
+
+use fastrand::Rng;
+
+use crate::noise::Fbm;
+use crate::CaveMap;
+use crate::MapFilter;
+
+/// Map coherent noise generator
+pub struct CoherentNoise {
+    fbm: Fbm,
+    threshold: f32,
+    keep_noise: bool,
+}
+
+impl MapFilter for CoherentNoise {
+    fn modify_map(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        self.build(map, rng)
+    }
+}
+
+impl CoherentNoise {
+    /// Create generator. Tiles with noise value (in the range [0, 1]) above the threshold
+    /// will be walkable.
+    pub fn new(fbm: Fbm, threshold: f32) -> Box<CoherentNoise> {
+        Box::new(CoherentNoise {
+            fbm,
+            threshold,
+            keep_noise: false,
+        })
+    }
+
+    /// Store raw noise values in the map `noise_layer`
+    pub fn keep_noise(mut self: Box<Self>) -> Box<CoherentNoise> {
+        self.keep_noise = true;
+        self
+    }
+
+    /// Generate map
+    fn build(&self, map: &CaveMap, rng: &mut Rng) -> CaveMap {
+        let mut new_map = map.clone();
+        let noise = self.fbm.generate(rng, map.width, map.height);
+        for y in 1..new_map.height - 1 {
+            for x in 1..new_map.width - 1 {
+                new_map.set_walkable(x, y, noise.get(x, y) > self.threshold);
+            }
+        }
+
+        if self.keep_noise {
+            new_map.noise_layer = Some(noise);
+        }
+        new_map
+    }
+}
+
+impl Default for CoherentNoise {
+    fn default() -> Self {
+        Self {
+            fbm: Fbm::default(),
+            threshold: 0.5,
+            keep_noise: false,
+        }
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold() {
+        let mut rng = Rng::with_seed(100);
+        let gen = CoherentNoise::new(Fbm::default(), 0.5).keep_noise();
+        let map = gen.modify_map(&mut rng, &CaveMap::new(40, 30));
+        let noise = map.noise_layer.as_ref().unwrap();
+        for y in 1..29 {
+            for x in 1..39 {
+                assert_eq!(map.is_walkable(x, y), noise.get(x, y) > 0.5);
+            }
+        }
+        for x in 0..40 {
+            assert!(map.is_blocked(x, 0));
+            assert!(map.is_blocked(x, 29));
+        }
+    }
+}
@@ -0,0 +1,222 @@
+//! Connect separate regions of the map with doors.
+//!
+//! Used for the "rooms and mazes" layout. Regions (rooms and maze parts) are joined
+//! with a spanning tree, so every region is reachable. Then a few extra connectors are
+//! added to create loops.
+//!
+//! Example usage:
+//! ```
+//! use mapgen::{CaveMap, MapBuilder};
+//! use mapgen::cave::{ConnectRegions, MazeBuilder, RemoveDeadEnds};
+//! use mapgen::rooms::SimpleRooms;
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let rooms = SimpleRooms::default().generate(80, 50, &mut rng);
+//! let map = MapBuilder::from_map(CaveMap::from_layer(rooms.walkable_layer))
+//!     .with(MazeBuilder::new().keep_existing())
+//!     .with(ConnectRegions::new(0.05))
+//!     .with(RemoveDeadEnds::new())
+//!     .build_with_rng(&mut rng);
+//!
+//! assert_eq!(map.width, 80);
+//! assert!(!map.doors.is_empty());
+//! ```
+//!
+
+use fastrand::Rng;
+use glam::UVec2;
+
+use crate::graph::DisjointSet;
+use crate::MapFilter;
+
+use super::CaveMap;
+
+const NO_REGION: usize = usize::MAX;
+
+/// Wall tiles which can be opened to join 2 regions
+struct Connector {
+    tiles: Vec<UVec2>,
+    regions: (usize, usize),
+}
+
+pub struct ConnectRegions {
+    extra_connectors: f32,
+    max_gap: u32,
+}
+
+impl MapFilter for ConnectRegions {
+    fn modify_map(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        self.build(rng, map)
+    }
+}
+
+impl ConnectRegions {
+    /// Create filter. `extra_connectors` is the probability (0..1) that the connector
+    /// between already connected regions will be opened too.
+    pub fn new(extra_connectors: f32) -> Box<ConnectRegions> {
+        Box::new(ConnectRegions {
+            extra_connectors,
+            max_gap: 3,
+        })
+    }
+
+    /// Maximum number of wall tiles which can be carved to join regions
+    pub fn with_max_gap(mut self: Box<Self>, max_gap: u32) -> Box<ConnectRegions> {
+        self.max_gap = u32::max(max_gap, 1);
+        self
+    }
+
+    fn build(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        let mut new_map = map.clone();
+        let (regions, num_regions) = find_regions(map);
+        let mut connectors = self.find_connectors(map, &regions);
+        // Prefer short connectors, but keep random order between connectors of the same length
+        rng.shuffle(&mut connectors);
+        connectors.sort_by_key(|c| c.tiles.len());
+
+        let mut sets = DisjointSet::new(num_regions);
+        for connector in connectors {
+            let (a, b) = connector.regions;
+            let open = if sets.union(a, b) {
+                true
+            } else {
+                rng.f32() < self.extra_connectors && !is_near_door(&new_map, &connector)
+            };
+            if open {
+                for tile in connector.tiles.iter() {
+                    new_map.set_walkable(tile.x, tile.y, true);
+                }
+                new_map.doors.push(connector.tiles[0]);
+            }
+        }
+
+        new_map
+    }
+
+    /// Find straight lines of wall tiles with different regions on both ends
+    fn find_connectors(&self, map: &CaveMap, regions: &[usize]) -> Vec<Connector> {
+        let mut connectors = Vec::new();
+        for y in 1..map.height.saturating_sub(1) {
+            for x in 1..map.width.saturating_sub(1) {
+                let region = regions[map.xy_idx(x, y)];
+                if region == NO_REGION {
+                    continue;
+                }
+                for (dx, dy) in [(1, 0), (0, 1)] {
+                    let mut tiles = Vec::new();
+                    for k in 1..=self.max_gap + 1 {
+                        let (tx, ty) = (x + k * dx, y + k * dy);
+                        if tx >= map.width - 1 || ty >= map.height - 1 {
+                            break;
+                        }
+                        let other = regions[map.xy_idx(tx, ty)];
+                        if other == NO_REGION {
+                            tiles.push(UVec2::new(tx, ty));
+                            continue;
+                        }
+                        if other != region && !tiles.is_empty() {
+                            connectors.push(Connector {
+                                tiles,
+                                regions: (region, other),
+                            });
+                        }
+                        break;
+                    }
+                }
+            }
+        }
+        connectors
+    }
+}
+
+/// Label each walkable tile with the id of its (4-connected) region
+fn find_regions(map: &CaveMap) -> (Vec<usize>, usize) {
+    let mut regions = vec![NO_REGION; map.walkable_layer.tiles.len()];
+    let mut num_regions = 0;
+    for idx in 0..regions.len() {
+        if regions[idx] != NO_REGION || !map.walkable_layer.tiles[idx] {
+            continue;
+        }
+        regions[idx] = num_regions;
+        let mut stack = vec![map.idx_point(idx)];
+        while let Some(p) = stack.pop() {
+            let neighbors = [
+                (p.x.wrapping_sub(1), p.y),
+                (p.x + 1, p.y),
+                (p.x, p.y.wrapping_sub(1)),
+                (p.x, p.y + 1),
+            ];
+            for (nx, ny) in neighbors {
+                if map.is_walkable(nx, ny) {
+                    let n_idx = map.xy_idx(nx, ny);
+                    if regions[n_idx] == NO_REGION {
+                        regions[n_idx] = num_regions;
+                        stack.push(UVec2::new(nx, ny));
+                    }
+                }
+            }
+        }
+        num_regions += 1;
+    }
+    (regions, num_regions)
+}
+
+/// Don't open extra connectors right next to the existing doors
+fn is_near_door(map: &CaveMap, connector: &Connector) -> bool {
+    connector.tiles.iter().any(|t| {
+        map.doors
+            .iter()
+            .any(|d| d.x.abs_diff(t.x) <= 1 && d.y.abs_diff(t.y) <= 1)
+    })
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_connect_regions() {
+        let map = CaveMap::from_string(
+            "
+            ###########
+            #   #     #
+            #   #######
+            ######   ##
+            #    #   ##
+            ###########
+            ",
+        );
+        let mut rng = Rng::with_seed(100);
+        let new_map = ConnectRegions::new(0.0).modify_map(&mut rng, &map);
+        let (_, num_regions) = find_regions(&new_map);
+
+        assert_eq!(find_regions(&map).1, 4);
+        assert_eq!(num_regions, 1);
+        assert_eq!(new_map.doors.len(), 3);
+    }
+
+    #[test]
+    fn test_max_gap() {
+        let map = CaveMap::from_string(
+            "
+            ##########
+            #  ####  #
+            ##########
+            ",
+        );
+        let mut rng = Rng::with_seed(100);
+        let new_map = ConnectRegions::new(0.0)
+            .with_max_gap(3)
+            .modify_map(&mut rng, &map);
+        assert!(new_map.doors.is_empty());
+        let new_map = ConnectRegions::new(0.0)
+            .with_max_gap(4)
+            .modify_map(&mut rng, &map);
+        assert_eq!(new_map.doors, vec![UVec2::new(3, 1)]);
+        assert_eq!(find_regions(&new_map).1, 1);
+    }
+}
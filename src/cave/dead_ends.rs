@@ -0,0 +1,108 @@
+//! Remove dead-ends from the map.
+//! Floor tiles with only one walkable neighbor are filled with rock until
+//! there are no dead-ends left. Doors leading to removed corridors are removed too.
+//!
+//! Example usage:
+//! ```
+//! use mapgen::{CaveMap, MapFilter};
+//! use mapgen::cave::{MazeBuilder, RemoveDeadEnds};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let maze = MazeBuilder::new().with_braid(0.5).modify_map(&mut rng, &CaveMap::new(80, 50));
+//! let map = RemoveDeadEnds::new().modify_map(&mut rng, &maze);
+//!
+//! assert_eq!(map.width, 80);
+//! ```
+//!
+
+use fastrand::Rng;
+use glam::UVec2;
+
+use crate::MapFilter;
+
+use super::CaveMap;
+
+pub struct RemoveDeadEnds;
+
+impl MapFilter for RemoveDeadEnds {
+    fn modify_map(&self, _rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        self.build(map)
+    }
+}
+
+impl RemoveDeadEnds {
+    pub fn new() -> Box<RemoveDeadEnds> {
+        Box::new(RemoveDeadEnds {})
+    }
+
+    fn build(&self, map: &CaveMap) -> CaveMap {
+        let mut new_map = map.clone();
+        let mut stack: Vec<UVec2> = (0..map.walkable_layer.tiles.len())
+            .filter(|&i| map.walkable_layer.tiles[i])
+            .map(|i| map.idx_point(i))
+            .collect();
+
+        while let Some(p) = stack.pop() {
+            if new_map.is_blocked(p.x, p.y) {
+                continue;
+            }
+            let neighbors: Vec<UVec2> = [
+                (p.x.wrapping_sub(1), p.y),
+                (p.x + 1, p.y),
+                (p.x, p.y.wrapping_sub(1)),
+                (p.x, p.y + 1),
+            ]
+            .iter()
+            .filter(|(x, y)| new_map.is_walkable(*x, *y))
+            .map(|&(x, y)| UVec2::new(x, y))
+            .collect();
+            if neighbors.len() <= 1 {
+                new_map.set_walkable(p.x, p.y, false);
+                stack.extend(neighbors);
+            }
+        }
+
+        let doors = new_map.doors.clone();
+        new_map.doors = doors
+            .into_iter()
+            .filter(|d| new_map.is_walkable(d.x, d.y))
+            .collect();
+        new_map
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_dead_ends() {
+        let mut map = CaveMap::from_string(
+            "
+            ##########
+            #   #    #
+            #   #### #
+            #      # #
+            ##########
+            ",
+        );
+        map.doors.push(UVec2::new(8, 2));
+        let mut rng = Rng::with_seed(100);
+        let new_map = RemoveDeadEnds::new().modify_map(&mut rng, &map);
+        let expected = CaveMap::from_string(
+            "
+            ##########
+            #   ######
+            #   ######
+            #   ######
+            ##########
+            ",
+        );
+        assert_eq!(new_map.walkable_layer, expected.walkable_layer);
+        assert!(new_map.doors.is_empty());
+    }
+}
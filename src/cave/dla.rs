@@ -0,0 +1,264 @@
+//! Diffusion-Limited Aggregation.
+//!
+//! Particles walk randomly until they stick to the existing floor.
+//! This creates branching, coral-like caves.
+//!
+//! Check this [article](http://www.roguebasin.com/index.php?title=Diffusion-limited_aggregation)
+//! for more information about the algorithm.
+//!
+//! Example generator usage:
+//! ```
+//! use fastrand::Rng;
+//! use mapgen::{CaveMap, MapFilter};
+//! use mapgen::cave::DiffusionLimitedAggregation;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let gen = DiffusionLimitedAggregation::walk_inwards();
+//! let map = gen.modify_map(&mut rng, &CaveMap::new(80, 50));
+//!
+//! assert_eq!(map.width, 80);
+//! assert_eq!(map.height, 50);
+//! ```
+//!
+
+use fastrand::Rng;
+use glam::UVec2;
+
+use crate::geometry::line;
+use crate::MapFilter;
+
+use super::brush::{Brush, PaintMode};
+use super::tile_map::Symmetry;
+use super::CaveMap;
+
+/// Average number of the random walk steps per map tile
+const STEPS_PER_TILE: u64 = 1000;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum DlaMode {
+    /// Particles start at the map edge and walk until they hit the floor
+    WalkInwards,
+    /// Particles start at the center and walk until they hit the wall
+    WalkOutwards,
+    /// Particles start at random position and move straight to the center
+    CentralAttractor,
+}
+
+pub struct DiffusionLimitedAggregation {
+    mode: DlaMode,
+    brush: Brush,
+    symmetry: Symmetry,
+    floor_percent: f32,
+}
+
+impl MapFilter for DiffusionLimitedAggregation {
+    fn modify_map(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        self.build(rng, map)
+    }
+}
+
+impl DiffusionLimitedAggregation {
+    pub fn new(
+        mode: DlaMode,
+        brush_size: u32,
+        symmetry: Symmetry,
+        floor_percent: f32,
+    ) -> Box<DiffusionLimitedAggregation> {
+        Box::new(DiffusionLimitedAggregation {
+            mode,
+            brush: Brush::square(brush_size),
+            symmetry,
+            floor_percent,
+        })
+    }
+
+    pub fn walk_inwards() -> Box<DiffusionLimitedAggregation> {
+        Self::new(DlaMode::WalkInwards, 1, Symmetry::None, 0.25)
+    }
+
+    pub fn walk_outwards() -> Box<DiffusionLimitedAggregation> {
+        Self::new(DlaMode::WalkOutwards, 2, Symmetry::None, 0.25)
+    }
+
+    pub fn central_attractor() -> Box<DiffusionLimitedAggregation> {
+        Self::new(DlaMode::CentralAttractor, 2, Symmetry::None, 0.25)
+    }
+
+    pub fn insectoid() -> Box<DiffusionLimitedAggregation> {
+        Self::new(DlaMode::CentralAttractor, 2, Symmetry::Horizontal, 0.25)
+    }
+
+    /// Paint with the custom brush (e.g. circle) instead of the square one.
+    pub fn with_brush(mut self: Box<Self>, brush: Brush) -> Box<DiffusionLimitedAggregation> {
+        self.brush = brush.with_mode(PaintMode::Carve);
+        self
+    }
+
+    fn build(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        let mut new_map = map.clone();
+        if new_map.width < 3 || new_map.height < 3 {
+            return new_map;
+        }
+        let start = map
+            .starting_point
+            .unwrap_or(UVec2::new(new_map.width / 2, new_map.height / 2));
+
+        // Carve a starting seed
+        new_map.paint(Symmetry::None, &Brush::diamond(3), start.x, start.y);
+
+        let total_tiles = new_map.width * new_map.height;
+        let desired_floor_tiles = (self.floor_percent * total_tiles as f32) as usize;
+        let mut floor_tile_count = new_map.walkable_layer.tiles.iter().filter(|&&a| a).count();
+        // Safeguard in case the floor percent can't be reached: steps of all the walks
+        // together are limited, so the run takes at most linear time in the map size.
+        let mut steps_left = STEPS_PER_TILE * total_tiles as u64;
+
+        while floor_tile_count < desired_floor_tiles && steps_left > 0 {
+            let stick = match self.mode {
+                DlaMode::WalkInwards => {
+                    let mut digger = edge_position(rng, &new_map);
+                    let mut prev = digger;
+                    while new_map.is_blocked(digger.x, digger.y) && steps_left > 0 {
+                        prev = digger;
+                        digger = random_step(rng, &new_map, digger);
+                        steps_left -= 1;
+                    }
+                    new_map.is_walkable(digger.x, digger.y).then_some(prev)
+                }
+                DlaMode::WalkOutwards => {
+                    let mut digger = start;
+                    while new_map.is_walkable(digger.x, digger.y) && steps_left > 0 {
+                        digger = random_step(rng, &new_map, digger);
+                        steps_left -= 1;
+                    }
+                    new_map.is_blocked(digger.x, digger.y).then_some(digger)
+                }
+                DlaMode::CentralAttractor => {
+                    let digger = random_position(rng, &new_map);
+                    let mut prev = digger;
+                    let path = line(digger, start);
+                    steps_left = steps_left.saturating_sub(path.len() as u64);
+                    for p in path {
+                        if new_map.is_walkable(p.x, p.y) {
+                            break;
+                        }
+                        prev = p;
+                    }
+                    Some(prev)
+                }
+            };
+
+            if let Some(stick) = stick {
+                let changed = new_map.paint(self.symmetry, &self.brush, stick.x, stick.y);
+                floor_tile_count += changed as usize;
+            }
+        }
+
+        new_map
+    }
+}
+
+/// Random position inside the map border
+fn random_position(rng: &mut Rng, map: &CaveMap) -> UVec2 {
+    UVec2::new(rng.u32(1..map.width - 1), rng.u32(1..map.height - 1))
+}
+
+/// Random position on the map edge, just inside of the border
+fn edge_position(rng: &mut Rng, map: &CaveMap) -> UVec2 {
+    match rng.u32(0..4) {
+        0 => UVec2::new(1, rng.u32(1..map.height - 1)),
+        1 => UVec2::new(map.width - 2, rng.u32(1..map.height - 1)),
+        2 => UVec2::new(rng.u32(1..map.width - 1), 1),
+        _ => UVec2::new(rng.u32(1..map.width - 1), map.height - 2),
+    }
+}
+
+/// Move in random direction, without leaving the map border
+fn random_step(rng: &mut Rng, map: &CaveMap, p: UVec2) -> UVec2 {
+    let mut p = p;
+    match rng.u32(0..4) {
+        0 if p.x > 1 => p.x -= 1,
+        1 if p.x < map.width - 2 => p.x += 1,
+        2 if p.y > 1 => p.y -= 1,
+        3 if p.y < map.height - 2 => p.y += 1,
+        _ => (),
+    }
+    p
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{metric::density, path::DijkstraMap};
+
+    #[test]
+    fn test_modes() {
+        for mode in [
+            DlaMode::WalkInwards,
+            DlaMode::WalkOutwards,
+            DlaMode::CentralAttractor,
+        ] {
+            let mut rng = Rng::with_seed(100);
+            let gen = DiffusionLimitedAggregation::new(mode, 1, Symmetry::None, 0.2);
+            let map = gen.modify_map(&mut rng, &CaveMap::new(60, 40));
+            assert!(density(&map.walkable_layer) >= 0.2);
+
+            // All the floor is connected with the center
+            let dijkstra = DijkstraMap::new(&map.walkable_layer, &UVec2::new(30, 20));
+            for (i, &walkable) in map.walkable_layer.tiles.iter().enumerate() {
+                if walkable {
+                    assert!(dijkstra.tiles[i] < f32::MAX, "{:?}", mode);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_symmetry() {
+        let mut rng = Rng::with_seed(100);
+        let gen = DiffusionLimitedAggregation::new(
+            DlaMode::CentralAttractor,
+            1,
+            Symmetry::Horizontal,
+            0.25,
+        );
+        let map = gen.modify_map(&mut rng, &CaveMap::new(61, 40));
+        for y in 0..40 {
+            for x in 1..30 {
+                assert_eq!(map.is_walkable(30 - x, y), map.is_walkable(30 + x, y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_edge_walkers() {
+        let mut rng = Rng::with_seed(100);
+        for _ in 0..100 {
+            let p = edge_position(&mut rng, &CaveMap::new(20, 10));
+            assert!(p.x == 1 || p.x == 18 || p.y == 1 || p.y == 8);
+            assert!(p.x >= 1 && p.x <= 18 && p.y >= 1 && p.y <= 8);
+        }
+    }
+
+    #[test]
+    fn test_unreachable_floor_percent() {
+        // Walls can't be carved, so the particles never stick
+        let mut rng = Rng::with_seed(100);
+        let gen = DiffusionLimitedAggregation::new(DlaMode::WalkInwards, 1, Symmetry::None, 1.0)
+            .with_brush(Brush::square(1).with_margin(100));
+        let map = gen.modify_map(&mut rng, &CaveMap::new(30, 20));
+        assert_eq!(map.width, 30);
+    }
+
+    #[test]
+    fn test_walk_outwards_full_map() {
+        // Whole interior becomes floor and the walker can't find any wall
+        let mut rng = Rng::with_seed(100);
+        let gen = DiffusionLimitedAggregation::new(DlaMode::WalkOutwards, 2, Symmetry::None, 1.0);
+        let map = gen.modify_map(&mut rng, &CaveMap::new(12, 10));
+        assert_eq!(density(&map.walkable_layer), 80.0 / 120.0);
+    }
+}
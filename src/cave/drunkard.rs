@@ -14,25 +14,50 @@
 //!
 
 use fastrand::Rng;
-use glam::UVec2;
+use glam::{IVec2, UVec2, Vec2};
 
 use crate::MapFilter;
 
+use super::brush::{Brush, PaintMode};
 use super::tile_map::Symmetry;
 use super::CaveMap;
 
+const DIRECTIONS: [IVec2; 8] = [
+    IVec2::new(-1, 0),
+    IVec2::new(1, 0),
+    IVec2::new(0, -1),
+    IVec2::new(0, 1),
+    IVec2::new(-1, -1),
+    IVec2::new(1, -1),
+    IVec2::new(-1, 1),
+    IVec2::new(1, 1),
+];
+
+/// Number of random tries before falling back to the full map scan when looking for the floor
+const FLOOR_SPAWN_TRIES: u32 = 100;
+
 #[derive(PartialEq, Copy, Clone)]
 pub enum DrunkSpawnMode {
     StartingPoint,
     Random,
+    /// Spawn on the random tile which is already a floor
+    ExistingFloor,
+    /// Spawn at the random position next to the map border
+    Edge,
 }
 
 pub struct DrunkardsWalk {
     spawn_mode: DrunkSpawnMode,
     drunken_lifetime: i32,
     floor_percent: f32,
-    brush_size: u32,
+    brush: Brush,
     symmetry: Symmetry,
+    start: Option<UVec2>,
+    bias: Vec2,
+    momentum: f32,
+    diagonals: bool,
+    stop_on_floor: bool,
+    max_walkers: Option<u32>,
 }
 
 impl MapFilter for DrunkardsWalk {
@@ -53,8 +78,14 @@ impl DrunkardsWalk {
             spawn_mode,
             drunken_lifetime,
             floor_percent,
-            brush_size,
+            brush: Brush::square(brush_size),
             symmetry,
+            start: None,
+            bias: Vec2::ZERO,
+            momentum: 0.0,
+            diagonals: false,
+            stop_on_floor: false,
+            max_walkers: None,
         })
     }
 
@@ -78,71 +109,258 @@ impl DrunkardsWalk {
         Self::new(DrunkSpawnMode::Random, 400, 0.4, 1, Symmetry::Both)
     }
 
+    /// Walkers start at the edge of the map and dig until they reach the existing cave
+    pub fn inwards_tunnels() -> Box<DrunkardsWalk> {
+        Self::new(DrunkSpawnMode::Edge, 400, 0.4, 1, Symmetry::None)
+            .with_momentum(0.5)
+            .stop_on_floor()
+    }
+
+    /// Paint with the custom brush (e.g. circle) instead of the square one.
+    pub fn with_brush(mut self: Box<Self>, brush: Brush) -> Box<DrunkardsWalk> {
+        self.brush = brush.with_mode(PaintMode::Carve);
+        self
+    }
+
+    /// Position of the first walker. By default the map starting point or the map center.
+    pub fn with_start(mut self: Box<Self>, start: UVec2) -> Box<DrunkardsWalk> {
+        self.start = Some(start);
+        self
+    }
+
+    /// Make walkers prefer moving in the given direction.
+    /// The longer the vector, the stronger the preference.
+    pub fn with_bias(mut self: Box<Self>, bias: Vec2) -> Box<DrunkardsWalk> {
+        self.bias = bias;
+        self
+    }
+
+    /// Probability (0..1) that the walker will repeat its previous step
+    pub fn with_momentum(mut self: Box<Self>, momentum: f32) -> Box<DrunkardsWalk> {
+        self.momentum = momentum.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Allow diagonal steps
+    pub fn with_diagonals(mut self: Box<Self>) -> Box<DrunkardsWalk> {
+        self.diagonals = true;
+        self
+    }
+
+    /// Walker dies when, after digging, it steps onto the existing floor
+    pub fn stop_on_floor(mut self: Box<Self>) -> Box<DrunkardsWalk> {
+        self.stop_on_floor = true;
+        self
+    }
+
+    /// Limit the number of walkers, in case the desired floor percent can't be reached.
+    /// By default there can be as many walkers as there are tiles on the map.
+    pub fn with_max_walkers(mut self: Box<Self>, max_walkers: u32) -> Box<DrunkardsWalk> {
+        self.max_walkers = Some(max_walkers);
+        self
+    }
+
     fn build(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
         let mut new_map = map.clone();
-        // Set a central starting point
-        let starting_position = UVec2::new(new_map.width / 2, new_map.height / 2);
-        new_map.set_walkable(starting_position.x, starting_position.y, true);
+        if new_map.width < 3 || new_map.height < 3 {
+            return new_map;
+        }
+        let starting_position = self
+            .start
+            .or(map.starting_point)
+            .unwrap_or(UVec2::new(new_map.width / 2, new_map.height / 2));
 
         let total_tiles = new_map.width * new_map.height;
         let desired_floor_tiles = (self.floor_percent * total_tiles as f32) as usize;
+        let max_walkers = self.max_walkers.unwrap_or(total_tiles);
         let mut floor_tile_count = new_map.walkable_layer.tiles.iter().filter(|&&a| a).count();
         let mut digger_count = 0;
-        while floor_tile_count < desired_floor_tiles {
-            let mut drunk_x;
-            let mut drunk_y;
-            match self.spawn_mode {
-                DrunkSpawnMode::StartingPoint => {
-                    drunk_x = starting_position.x;
-                    drunk_y = starting_position.y;
-                }
-                DrunkSpawnMode::Random => {
-                    if digger_count == 0 {
-                        drunk_x = starting_position.x;
-                        drunk_y = starting_position.y;
-                    } else {
-                        drunk_x = rng.choice(1..(new_map.width + 1) - 3).unwrap() + 1;
-                        drunk_y = rng.choice(1..(new_map.height + 1) - 3).unwrap() + 1;
-                    }
-                }
-            }
+        // Number of the walker which carved each tile, so walkers don't stop on their own floor
+        let mut carved_by: Vec<u32> = if self.stop_on_floor {
+            vec![0; total_tiles as usize]
+        } else {
+            Vec::new()
+        };
+        while floor_tile_count < desired_floor_tiles && digger_count < max_walkers {
+            let mut drunk = if digger_count == 0 {
+                starting_position
+            } else {
+                self.spawn_position(rng, &new_map, starting_position)
+            };
             let mut drunk_life = self.drunken_lifetime;
+            let mut last_step = None;
+            let mut dug = false;
+            let walker = digger_count + 1;
 
             while drunk_life > 0 {
-                new_map.set_walkable(drunk_x, drunk_y, false);
-                new_map.paint(self.symmetry, self.brush_size, drunk_x, drunk_y);
-
-                let stagger_direction = rng.choice(1..5).unwrap();
-                match stagger_direction {
-                    1 => {
-                        if drunk_x > 1 {
-                            drunk_x -= 1;
-                        }
-                    }
-                    2 => {
-                        if drunk_x < new_map.width - 2 {
-                            drunk_x += 1;
-                        }
-                    }
-                    3 => {
-                        if drunk_y > 1 {
-                            drunk_y -= 1;
-                        }
-                    }
-                    _ => {
-                        if drunk_y < new_map.height - 2 {
-                            drunk_y += 1;
-                        }
-                    }
+                if self.stop_on_floor {
+                    self.mark_carved(&new_map, &mut carved_by, drunk, walker);
+                }
+                let changed = new_map.paint(self.symmetry, &self.brush, drunk.x, drunk.y);
+                floor_tile_count += changed as usize;
+                dug |= changed > 0;
+
+                let step = self.next_step(rng, last_step);
+                last_step = Some(step);
+                let next = IVec2::new(
+                    (drunk.x as i32 + step.x).clamp(1, new_map.width as i32 - 2),
+                    (drunk.y as i32 + step.y).clamp(1, new_map.height as i32 - 2),
+                )
+                .as_uvec2();
+                let on_floor = self.stop_on_floor
+                    && new_map.is_walkable(next.x, next.y)
+                    && carved_by[new_map.xy_idx(next.x, next.y)] != walker;
+                if dug && next != drunk && on_floor {
+                    break;
                 }
+                drunk = next;
 
                 drunk_life -= 1;
             }
 
             digger_count += 1;
-            floor_tile_count = new_map.walkable_layer.tiles.iter().filter(|&&a| a).count();
         }
 
         new_map
     }
+
+    /// Mark the walls which will be carved by the walker at the given position
+    fn mark_carved(&self, map: &CaveMap, carved_by: &mut [u32], pos: UVec2, walker: u32) {
+        let offsets = self.brush.offsets();
+        for p in map.paint_positions(self.symmetry, pos.x, pos.y) {
+            for &(dx, dy) in offsets.iter() {
+                let (x, y) = (p.x as i64 + dx, p.y as i64 + dy);
+                if x >= 0 && y >= 0 && x < map.width as i64 && y < map.height as i64 {
+                    let (x, y) = (x as u32, y as u32);
+                    if map.is_blocked(x, y) {
+                        carved_by[map.xy_idx(x, y)] = walker;
+                    }
+                }
+            }
+        }
+    }
+
+    fn spawn_position(&self, rng: &mut Rng, map: &CaveMap, start: UVec2) -> UVec2 {
+        match self.spawn_mode {
+            DrunkSpawnMode::StartingPoint => start,
+            DrunkSpawnMode::Random => UVec2::new(
+                rng.choice(1..(map.width + 1) - 3).unwrap() + 1,
+                rng.choice(1..(map.height + 1) - 3).unwrap() + 1,
+            ),
+            DrunkSpawnMode::ExistingFloor => {
+                for _ in 0..FLOOR_SPAWN_TRIES {
+                    let x = rng.u32(1..map.width - 1);
+                    let y = rng.u32(1..map.height - 1);
+                    if map.is_walkable(x, y) {
+                        return UVec2::new(x, y);
+                    }
+                }
+                let floors: Vec<usize> = (0..map.walkable_layer.tiles.len())
+                    .filter(|&i| map.walkable_layer.tiles[i])
+                    .collect();
+                rng.choice(floors)
+                    .map(|idx| map.idx_point(idx))
+                    .unwrap_or(start)
+            }
+            DrunkSpawnMode::Edge => match rng.u32(0..4) {
+                0 => UVec2::new(1, rng.u32(1..map.height - 1)),
+                1 => UVec2::new(map.width - 2, rng.u32(1..map.height - 1)),
+                2 => UVec2::new(rng.u32(1..map.width - 1), 1),
+                _ => UVec2::new(rng.u32(1..map.width - 1), map.height - 2),
+            },
+        }
+    }
+
+    fn next_step(&self, rng: &mut Rng, last_step: Option<IVec2>) -> IVec2 {
+        if let Some(step) = last_step {
+            if self.momentum > 0.0 && rng.f32() < self.momentum {
+                return step;
+            }
+        }
+
+        let directions = if self.diagonals {
+            &DIRECTIONS[..]
+        } else {
+            &DIRECTIONS[..4]
+        };
+        if self.bias == Vec2::ZERO {
+            return directions[rng.usize(0..directions.len())];
+        }
+
+        let weights: Vec<f32> = directions
+            .iter()
+            .map(|d| 1.0 + f32::max(0.0, d.as_vec2().normalize().dot(self.bias)))
+            .collect();
+        let mut roll = rng.f32() * weights.iter().sum::<f32>();
+        for (direction, weight) in directions.iter().zip(weights.iter()) {
+            if roll < *weight {
+                return *direction;
+            }
+            roll -= weight;
+        }
+        directions[directions.len() - 1]
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metric::density;
+
+    #[test]
+    fn test_floor_percent() {
+        let mut rng = Rng::with_seed(100);
+        let gen = DrunkardsWalk::open_halls();
+        let map = gen.modify_map(&mut rng, &CaveMap::new(80, 50));
+        let floor = density(&map.walkable_layer);
+        assert!(floor >= 0.5);
+        assert!(floor < 0.6);
+    }
+
+    #[test]
+    fn test_max_walkers() {
+        let mut rng = Rng::with_seed(100);
+        let gen = DrunkardsWalk::new(DrunkSpawnMode::StartingPoint, 1, 1.0, 1, Symmetry::None)
+            .with_max_walkers(3);
+        let map = gen.modify_map(&mut rng, &CaveMap::new(20, 20));
+        assert_eq!(density(&map.walkable_layer), 1.0 / 400.0);
+    }
+
+    #[test]
+    fn test_spawn_modes() {
+        for mode in [DrunkSpawnMode::ExistingFloor, DrunkSpawnMode::Edge] {
+            let mut rng = Rng::with_seed(100);
+            let gen = DrunkardsWalk::new(mode, 100, 0.3, 1, Symmetry::None)
+                .with_diagonals()
+                .with_bias(Vec2::new(1.0, 0.0));
+            let map = gen.modify_map(&mut rng, &CaveMap::new(40, 30));
+            assert!(density(&map.walkable_layer) >= 0.3);
+            for x in 0..40 {
+                assert!(map.is_blocked(x, 0));
+                assert!(map.is_blocked(x, 29));
+            }
+        }
+    }
+
+    #[test]
+    fn test_stop_on_floor() {
+        let mut rng = Rng::with_seed(100);
+        let gen = DrunkardsWalk::inwards_tunnels();
+        let map = gen.modify_map(&mut rng, &CaveMap::new(40, 30));
+        assert!(density(&map.walkable_layer) >= 0.4);
+    }
+
+    #[test]
+    fn test_stop_only_on_existing_floor() {
+        let mut rng = Rng::with_seed(100);
+        let gen = DrunkardsWalk::new(DrunkSpawnMode::StartingPoint, 50, 1.0, 3, Symmetry::None)
+            .with_max_walkers(1)
+            .stop_on_floor();
+        let map = gen.modify_map(&mut rng, &CaveMap::new(40, 30));
+        // Wide brush walks over its own floor all the time
+        assert!(density(&map.walkable_layer) > 30.0 / 1200.0);
+    }
 }
@@ -0,0 +1,710 @@
+//! Herringbone Wang tiles generator
+//! (based on [stb_herringbone_wang_tile](https://nothings.org/gamedev/herringbone/)).
+//!
+//! Map is covered with 2x1 rectangular tiles laid in the herringbone pattern.
+//! Horizontal tiles are `2*size` wide and `size` high, vertical tiles are `size` wide and
+//! `2*size` high. The herringbone pattern hides the grid, so the map doesn't look repetitive
+//! even with the small tileset.
+//!
+//! Each side of the square `size x size` part of the tile is the edge with the color.
+//! The color is the pattern of the floor tiles along the edge, so the touching edges match
+//! only when their openings are at the same positions. Tiles are chosen so the colors
+//! of the touching edges match. If the tileset doesn't have the matching tile,
+//! the one with the most matching edges is used, so the map can have disconnected areas
+//! (use [crate::cave::ConnectRegions] to fix it).
+//!
+//! Tileset is written as ASCII tiles separated with the empty line:
+//!   * `#` - wall
+//!   * `.` or space - floor
+//!
+//! Lines are trimmed, so use `.` for the floor at the beginning or end of the line.
+//! Orientation of the tile is found from its size. The `size` can be at most 64.
+//!
+//! Tileset can also use the stb template layout (see [HerringboneWang::from_stb_layout]):
+//! the block of horizontal tiles above the block of vertical tiles, with the tiles
+//! in each block separated by the grid lines. Image tilesets from stb can be converted
+//! to this layout pixel by pixel. Edge colors are always found from the floor tiles,
+//! colors painted into the stb image are not used.
+//!
+//! Example generator usage:
+//! ```
+//! use mapgen::{MapFilter, MapBuilder};
+//! use mapgen::cave::HerringboneWang;
+//!
+//! let map = MapBuilder::new(80, 50)
+//!             .with(HerringboneWang::new())
+//!             .build(100);
+//!
+//! assert_eq!(map.width, 80);
+//! assert_eq!(map.height, 50);
+//! ```
+//!
+
+use std::collections::HashMap;
+
+use fastrand::Rng;
+use glam::IVec2;
+
+use crate::MapFilter;
+
+use super::CaveMap;
+
+const DEFAULT_TILESET: &str = "
+    ##..####..##
+    #..........#
+    ............
+    ............
+    #..........#
+    ##..####..##
+
+    ############
+    ############
+    ............
+    ............
+    ############
+    ############
+
+    ########..##
+    #####.....##
+    .......#####
+    .......#####
+    ##...#######
+    ##..########
+
+    ############
+    ##.......###
+    ##..........
+    ##..........
+    ##.......###
+    ############
+
+    ##..########
+    ##...#######
+    ###......###
+    ###......###
+    #######...##
+    ########..##
+
+    ########..##
+    ######....##
+    ######.#..##
+    ######.#..##
+    ######....##
+    ########..##
+
+    ##..##
+    #....#
+    ......
+    ......
+    #....#
+    #.##.#
+    #.##.#
+    #....#
+    ......
+    ......
+    #....#
+    ##..##
+
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+
+    ######
+    ######
+    ....##
+    .....#
+    ##...#
+    ##...#
+    ##...#
+    ##...#
+    ##....
+    ##....
+    ######
+    ######
+
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ....##
+    ....##
+    ######
+    ######
+
+    ######
+    ######
+    ######
+    #....#
+    #....#
+    #....#
+    #....#
+    #....#
+    #....#
+    #....#
+    ##..##
+    ##..##
+
+    ######
+    ######
+    ......
+    ......
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+    ##..##
+";
+
+/// Floor tiles along the edge (from the left or top), one bit per tile
+type EdgeColor = u64;
+
+#[derive(Clone, Debug, PartialEq)]
+struct WangTile {
+    width: u32,
+    height: u32,
+    /// Walkable tiles
+    tiles: Vec<bool>,
+    /// Edge colors. Horizontal tile: top left, top right, bottom left, bottom right, left, right.
+    /// Vertical tile: left top, left bottom, right top, right bottom, top, bottom.
+    edges: [EdgeColor; 6],
+}
+
+impl WangTile {
+    fn new(width: u32, height: u32, tiles: Vec<bool>) -> WangTile {
+        let mut tile = WangTile {
+            width,
+            height,
+            tiles,
+            edges: [0; 6],
+        };
+        let size = u32::min(width, height);
+        let row = |x: u32, y: u32| tile.edge_color(size, |i| (x + i, y));
+        let column = |x: u32, y: u32| tile.edge_color(size, |i| (x, y + i));
+        tile.edges = if width > height {
+            [
+                row(0, 0),
+                row(size, 0),
+                row(0, height - 1),
+                row(size, height - 1),
+                column(0, 0),
+                column(width - 1, 0),
+            ]
+        } else {
+            [
+                column(0, 0),
+                column(0, size),
+                column(width - 1, 0),
+                column(width - 1, size),
+                row(0, 0),
+                row(0, height - 1),
+            ]
+        };
+        tile
+    }
+
+    /// Color of the edge with the given length. Position of the i-th edge tile is given by `pos`.
+    fn edge_color(&self, length: u32, pos: impl Fn(u32) -> (u32, u32)) -> EdgeColor {
+        (0..length)
+            .filter(|&i| {
+                let (x, y) = pos(i);
+                self.is_floor(x, y)
+            })
+            .fold(0, |color, i| color | (1 << i))
+    }
+
+    fn is_horizontal(&self) -> bool {
+        self.width > self.height
+    }
+
+    fn is_floor(&self, x: u32, y: u32) -> bool {
+        self.tiles[(y * self.width + x) as usize]
+    }
+
+    /// Tile flipped horizontally
+    fn mirrored(&self) -> WangTile {
+        let tiles = (0..self.width * self.height)
+            .map(|i| self.is_floor(self.width - 1 - i % self.width, i / self.width))
+            .collect();
+        WangTile::new(self.width, self.height, tiles)
+    }
+
+    /// Tile flipped vertically
+    fn flipped(&self) -> WangTile {
+        let tiles = (0..self.width * self.height)
+            .map(|i| self.is_floor(i % self.width, self.height - 1 - i / self.width))
+            .collect();
+        WangTile::new(self.width, self.height, tiles)
+    }
+}
+
+pub struct HerringboneWang {
+    size: u32,
+    horizontal: Vec<WangTile>,
+    vertical: Vec<WangTile>,
+}
+
+impl MapFilter for HerringboneWang {
+    fn modify_map(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        self.build(rng, map)
+    }
+}
+
+impl HerringboneWang {
+    /// Create generator with the default tileset
+    pub fn new() -> Box<HerringboneWang> {
+        HerringboneWang::from_string(DEFAULT_TILESET).expect("Default tileset is valid")
+    }
+
+    /// Create generator from the ASCII tileset.
+    /// Returns error if there are no tiles of both orientations, tiles have different sizes
+    /// or are too large.
+    pub fn from_string(tileset: &str) -> Result<Box<HerringboneWang>, String> {
+        let mut tiles = Vec::new();
+        let mut lines: Vec<Vec<char>> = Vec::new();
+        for line in tileset.split('\n').map(|l| l.trim()).chain([""]) {
+            if !line.is_empty() {
+                lines.push(line.chars().collect());
+            } else if !lines.is_empty() {
+                tiles.push(parse_tile(&lines)?);
+                lines.clear();
+            }
+        }
+        HerringboneWang::from_tiles(tiles)
+    }
+
+    /// Create generator from the ASCII tileset in the stb template layout.
+    /// The first block has the horizontal tiles and the second one the vertical tiles.
+    /// Tiles are separated with the grid lines (`+` in the corners), e.g.:
+    /// ```text
+    /// +------+------+
+    /// |######|......|
+    /// |......|......|
+    /// |##.###|##.###|
+    /// +------+------+
+    ///
+    /// +---+
+    /// |#.#|
+    /// |#..|
+    /// |###|
+    /// |#.#|
+    /// |#.#|
+    /// |#.#|
+    /// +---+
+    /// ```
+    pub fn from_stb_layout(tileset: &str) -> Result<Box<HerringboneWang>, String> {
+        let mut blocks: Vec<Vec<&str>> = vec![Vec::new()];
+        for line in tileset.split('\n').map(|l| l.trim()) {
+            match blocks.last_mut() {
+                Some(block) if !line.is_empty() => block.push(line),
+                Some(block) if !block.is_empty() => blocks.push(Vec::new()),
+                _ => (),
+            }
+        }
+        blocks.retain(|b| !b.is_empty());
+        if blocks.len() != 2 {
+            return Err("Tileset needs the horizontal and the vertical block".to_string());
+        }
+        let horizontal = parse_block(&blocks[0])?;
+        let vertical = parse_block(&blocks[1])?;
+        if !horizontal.iter().all(|t| t.is_horizontal()) {
+            return Err("First block needs to have the horizontal tiles".to_string());
+        }
+        if vertical.iter().any(|t| t.is_horizontal()) {
+            return Err("Second block needs to have the vertical tiles".to_string());
+        }
+        HerringboneWang::from_tiles(horizontal.into_iter().chain(vertical).collect())
+    }
+
+    fn from_tiles(tiles: Vec<WangTile>) -> Result<Box<HerringboneWang>, String> {
+        let (horizontal, vertical): (Vec<WangTile>, Vec<WangTile>) =
+            tiles.into_iter().partition(|t| t.is_horizontal());
+        if horizontal.is_empty() || vertical.is_empty() {
+            return Err("Tileset needs both horizontal and vertical tiles".to_string());
+        }
+        let size = horizontal[0].height;
+        let valid = |t: &WangTile| {
+            u32::min(t.width, t.height) == size && u32::max(t.width, t.height) == 2 * size
+        };
+        if !horizontal.iter().chain(vertical.iter()).all(valid) {
+            return Err("Tiles need to have size 2N x N or N x 2N".to_string());
+        }
+        Ok(Box::new(HerringboneWang {
+            size,
+            horizontal,
+            vertical,
+        }))
+    }
+
+    /// Add flipped copies of all tiles to the tileset
+    pub fn with_mirroring(mut self: Box<Self>) -> Box<Self> {
+        for tiles in [&mut self.horizontal, &mut self.vertical] {
+            let mirrored: Vec<WangTile> = tiles.iter().map(|t| t.mirrored()).collect();
+            tiles.extend(mirrored);
+            let flipped: Vec<WangTile> = tiles.iter().map(|t| t.flipped()).collect();
+            tiles.extend(flipped);
+        }
+        self
+    }
+
+    fn build(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        let mut new_map = CaveMap::new(map.width, map.height);
+        let cols = map.width.div_ceil(self.size) as i32;
+        let rows = map.height.div_ceil(self.size) as i32;
+        // Colors of the edges: top edge of the cell (false) or left edge of the cell (true)
+        let mut edges: HashMap<(IVec2, bool), EdgeColor> = HashMap::new();
+
+        for (pos, horizontal) in herringbone(cols, rows) {
+            let keys = edge_keys(pos, horizontal);
+            let tiles = if horizontal {
+                &self.horizontal
+            } else {
+                &self.vertical
+            };
+            let constraints: Vec<Option<EdgeColor>> =
+                keys.iter().map(|k| edges.get(k).copied()).collect();
+            let tile = choose_tile(rng, tiles, &constraints);
+            for (key, &color) in keys.iter().zip(tile.edges.iter()) {
+                edges.insert(*key, color);
+            }
+
+            let origin = pos * self.size as i32;
+            for y in 0..tile.height {
+                for x in 0..tile.width {
+                    let (mx, my) = (origin.x + x as i32, origin.y + y as i32);
+                    if mx > 0 && my > 0 && mx < map.width as i32 - 1 && my < map.height as i32 - 1 {
+                        new_map.set_walkable(mx as u32, my as u32, tile.is_floor(x, y));
+                    }
+                }
+            }
+        }
+
+        new_map
+    }
+}
+
+/// Parse the tile from its lines. Shorter lines are padded with the wall.
+fn parse_tile(lines: &[Vec<char>]) -> Result<WangTile, String> {
+    let width = lines.iter().map(|l| l.len() as u32).max().unwrap_or(0);
+    let height = lines.len() as u32;
+    if width == 0 || height == 0 {
+        return Err("Tiles can't be empty".to_string());
+    }
+    if u32::min(width, height) > EdgeColor::BITS {
+        return Err(format!("Tiles can't be larger than {}", EdgeColor::BITS));
+    }
+    let mut floor = vec![false; (width * height) as usize];
+    for (y, l) in lines.iter().enumerate() {
+        for (x, &c) in l.iter().enumerate() {
+            floor[y * width as usize + x] = c == '.' || c == ' ';
+        }
+    }
+    Ok(WangTile::new(width, height, floor))
+}
+
+/// Parse the tiles from the block in the stb layout, row by row
+fn parse_block(lines: &[&str]) -> Result<Vec<WangTile>, String> {
+    let lines: Vec<Vec<char>> = lines.iter().map(|l| l.chars().collect()).collect();
+    let width = lines[0].len();
+    if lines.iter().any(|l| l.len() != width) {
+        return Err("Lines of the tile block need to have the same length".to_string());
+    }
+    // Grid lines are found from the corners in the first column and the first row
+    let columns: Vec<usize> = (0..width).filter(|&x| lines[0][x] == '+').collect();
+    let rows: Vec<usize> = (0..lines.len()).filter(|&y| lines[y][0] == '+').collect();
+    if columns.len() < 2 || rows.len() < 2 || columns[0] != 0 || rows[0] != 0 {
+        return Err("Tile block needs to be surrounded by the grid lines".to_string());
+    }
+    if columns[columns.len() - 1] != width - 1 || rows[rows.len() - 1] != lines.len() - 1 {
+        return Err("Tile block needs to be surrounded by the grid lines".to_string());
+    }
+
+    let mut tiles = Vec::new();
+    for r in rows.windows(2) {
+        for c in columns.windows(2) {
+            let tile: Vec<Vec<char>> = lines[r[0] + 1..r[1]]
+                .iter()
+                .map(|l| l[c[0] + 1..c[1]].to_vec())
+                .collect();
+            tiles.push(parse_tile(&tile)?);
+        }
+    }
+    Ok(tiles)
+}
+
+/// Position (in cells) and orientation of the tiles covering the grid.
+/// Cell (x, y) is covered by the horizontal tile if (x - y) mod 4 is 0 or 1,
+/// and by vertical one otherwise.
+fn herringbone(cols: i32, rows: i32) -> Vec<(IVec2, bool)> {
+    let mut tiles = Vec::new();
+    for y in -1..rows {
+        for x in -1..cols {
+            match (x - y).rem_euclid(4) {
+                0 => tiles.push((IVec2::new(x, y), true)),
+                3 => tiles.push((IVec2::new(x, y), false)),
+                _ => (),
+            }
+        }
+    }
+    tiles
+}
+
+/// Keys of the tile edges in the same order as [WangTile::edges]
+fn edge_keys(pos: IVec2, horizontal: bool) -> [(IVec2, bool); 6] {
+    let (x, y) = (pos.x, pos.y);
+    if horizontal {
+        [
+            (IVec2::new(x, y), false),
+            (IVec2::new(x + 1, y), false),
+            (IVec2::new(x, y + 1), false),
+            (IVec2::new(x + 1, y + 1), false),
+            (IVec2::new(x, y), true),
+            (IVec2::new(x + 2, y), true),
+        ]
+    } else {
+        [
+            (IVec2::new(x, y), true),
+            (IVec2::new(x, y + 1), true),
+            (IVec2::new(x + 1, y), true),
+            (IVec2::new(x + 1, y + 1), true),
+            (IVec2::new(x, y), false),
+            (IVec2::new(x, y + 2), false),
+        ]
+    }
+}
+
+/// Random tile with the most edges matching the constraints
+fn choose_tile<'a>(
+    rng: &mut Rng,
+    tiles: &'a [WangTile],
+    constraints: &[Option<EdgeColor>],
+) -> &'a WangTile {
+    let matches = |t: &WangTile| {
+        t.edges
+            .iter()
+            .zip(constraints.iter())
+            .filter(|(e, c)| c.is_none_or(|c| c == **e))
+            .count()
+    };
+    let best = tiles.iter().map(matches).max().unwrap_or(0);
+    let candidates: Vec<&WangTile> = tiles.iter().filter(|t| matches(t) == best).collect();
+    candidates[rng.usize(0..candidates.len())]
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_herringbone_cover() {
+        let (cols, rows) = (12, 9);
+        let mut covered = vec![0; (cols * rows) as usize];
+        for (pos, horizontal) in herringbone(cols, rows) {
+            let cells = if horizontal {
+                [pos, pos + IVec2::X]
+            } else {
+                [pos, pos + IVec2::Y]
+            };
+            for c in cells {
+                if c.x >= 0 && c.y >= 0 && c.x < cols && c.y < rows {
+                    covered[(c.y * cols + c.x) as usize] += 1;
+                }
+            }
+        }
+        assert!(covered.iter().all(|&c| c == 1));
+    }
+
+    #[test]
+    fn test_edges() {
+        let gen = HerringboneWang::from_string(
+            "
+            ######
+            ......
+            #.####
+
+            #.#
+            #..
+            ###
+            #.#
+            #.#
+            #.#
+            ",
+        )
+        .unwrap();
+        assert_eq!(gen.size, 3);
+        let h = &gen.horizontal[0];
+        assert_eq!(h.edges, [0, 0, 0b010, 0, 0b010, 0b010]);
+        assert_eq!(h.mirrored().edges, [0, 0, 0, 0b010, 0b010, 0b010]);
+        assert_eq!(h.flipped().edges, [0b010, 0, 0, 0, 0b010, 0b010]);
+        let v = &gen.vertical[0];
+        assert_eq!(v.edges, [0, 0, 0b010, 0, 0b010, 0b010]);
+    }
+
+    #[test]
+    fn test_choose_tile() {
+        let gen = HerringboneWang::new();
+        let mut rng = Rng::with_seed(100);
+        let open = 0b001100;
+        let constraints = [Some(0), None, None, None, Some(open), Some(open)];
+        for _ in 0..10 {
+            let tile = choose_tile(&mut rng, &gen.horizontal, &constraints);
+            assert_eq!(tile.edges[0], 0);
+            assert_eq!(tile.edges[4..], [open, open]);
+        }
+    }
+
+    #[test]
+    fn test_edge_colors() {
+        // Both horizontal tiles have open left edge, but the openings are in different rows
+        let gen = HerringboneWang::from_string(
+            "
+            ......
+            ######
+            ######
+
+            ######
+            ......
+            ######
+
+            #.#
+            #.#
+            #.#
+            #.#
+            #.#
+            #.#
+            ",
+        )
+        .unwrap();
+        assert_eq!(gen.horizontal[0].edges[4], 0b001);
+        assert_eq!(gen.horizontal[1].edges[4], 0b010);
+        let mut rng = Rng::with_seed(100);
+        let constraints = [None, None, None, None, Some(0b010), None];
+        for _ in 0..10 {
+            let tile = choose_tile(&mut rng, &gen.horizontal, &constraints);
+            assert_eq!(tile, &gen.horizontal[1]);
+        }
+    }
+
+    #[test]
+    fn test_map() {
+        let mut rng = Rng::with_seed(100);
+        let gen = HerringboneWang::new().with_mirroring();
+        let map = gen.modify_map(&mut rng, &CaveMap::new(80, 50));
+        for x in 0..80 {
+            assert!(map.is_blocked(x, 0));
+            assert!(map.is_blocked(x, 49));
+        }
+        let floor = map.walkable_layer.tiles.iter().filter(|&&w| w).count();
+        assert!(floor > 80 * 50 / 4);
+    }
+
+    #[test]
+    fn test_invalid_tileset() {
+        assert_eq!(
+            HerringboneWang::from_string("####\n....").err(),
+            Some("Tileset needs both horizontal and vertical tiles".to_string())
+        );
+        assert_eq!(
+            HerringboneWang::from_string("####\n....\n\n#.#\n#.#\n#.#\n#.#\n#.#\n#.#").err(),
+            Some("Tiles need to have size 2N x N or N x 2N".to_string())
+        );
+        let row = ".".repeat(130);
+        let large = vec![row.as_str(); 65].join("\n");
+        assert_eq!(
+            HerringboneWang::from_string(&large).err(),
+            Some("Tiles can't be larger than 64".to_string())
+        );
+    }
+
+    #[test]
+    fn test_stb_layout() {
+        let gen = HerringboneWang::from_stb_layout(
+            "
+            +------+------+
+            |######|......|
+            |......|......|
+            |##.###|##.###|
+            +------+------+
+
+            +---+---+
+            |#.#|#.#|
+            |#..|#.#|
+            |###|#.#|
+            |#.#|#.#|
+            |#.#|#.#|
+            |#.#|#.#|
+            +---+---+
+            ",
+        )
+        .unwrap();
+        assert_eq!(gen.size, 3);
+        assert_eq!(gen.horizontal.len(), 2);
+        assert_eq!(gen.vertical.len(), 2);
+        assert_eq!(gen.horizontal[0].edges, [0, 0, 0b100, 0, 0b010, 0b010]);
+        assert_eq!(
+            gen.horizontal[1].edges,
+            [0b111, 0b111, 0b100, 0, 0b011, 0b011]
+        );
+        assert_eq!(gen.vertical[0].edges, [0, 0, 0b010, 0, 0b010, 0b010]);
+    }
+
+    #[test]
+    fn test_invalid_stb_layout() {
+        let horizontal = "
+            +------+
+            |######|
+            |......|
+            |##.###|
+            +------+
+            ";
+        let vertical = "
+            +---+
+            |#.#|
+            |#..|
+            |###|
+            |#.#|
+            |#.#|
+            |#.#|
+            +---+
+            ";
+        assert!(HerringboneWang::from_stb_layout(&format!("{horizontal}\n{vertical}")).is_ok());
+        assert_eq!(
+            HerringboneWang::from_stb_layout(horizontal).err(),
+            Some("Tileset needs the horizontal and the vertical block".to_string())
+        );
+        assert_eq!(
+            HerringboneWang::from_stb_layout(&format!("{vertical}\n{horizontal}")).err(),
+            Some("First block needs to have the horizontal tiles".to_string())
+        );
+        let broken = "
+            +------+
+            |######|
+            |......
+            |##.###|
+            +------+
+            ";
+        assert_eq!(
+            HerringboneWang::from_stb_layout(&format!("{broken}\n{vertical}")).err(),
+            Some("Lines of the tile block need to have the same length".to_string())
+        );
+        assert!(HerringboneWang::from_stb_layout("######\n\n######").is_err());
+    }
+}
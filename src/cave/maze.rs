@@ -12,12 +12,57 @@
 //! assert_eq!(map.height, 50);
 //! ```
 //!
+//! Braided maze with 2 tiles wide corridors:
+//! ```
+//! use mapgen::{CaveMap, MapFilter};
+//! use mapgen::cave::{MazeAlgorithm, MazeBuilder};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let gen = MazeBuilder::new()
+//!     .with_algorithm(MazeAlgorithm::Prim)
+//!     .with_braid(0.5)
+//!     .with_cell_size(2, 1);
+//! let map = gen.modify_map(&mut rng, &CaveMap::new(80, 50));
+//!
+//! assert_eq!(map.width, 80);
+//! ```
+//!
 
 use super::CaveMap;
+use crate::graph::DisjointSet;
 use crate::MapFilter;
 use fastrand::Rng;
 
-pub struct MazeBuilder {}
+/// Algorithm used to carve the maze. Each one gives the maze a different texture.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum MazeAlgorithm {
+    /// Long, winding corridors with few dead-ends
+    RecursiveBacktracker,
+    /// Lots of short dead-ends radiating from the start
+    Prim,
+    /// Uniform looking maze with many short dead-ends
+    Kruskal,
+    /// Uniform spanning tree (unbiased)
+    Wilson,
+    /// Row by row generation. Works in constant memory per row
+    Eller,
+    /// Similar to backtracker but with less river-like corridors
+    HuntAndKill,
+    /// Strong diagonal bias with long corridors along the top and right side
+    BinaryTree,
+    /// Long straight walls dividing the map into chambers
+    RecursiveDivision,
+}
+
+pub struct MazeBuilder {
+    algorithm: MazeAlgorithm,
+    braid: f32,
+    sparseness: u32,
+    cell_width: u32,
+    wall_width: u32,
+    keep_existing: bool,
+}
 
 impl MapFilter for MazeBuilder {
     fn modify_map(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
@@ -27,14 +72,69 @@ impl MapFilter for MazeBuilder {
 
 impl MazeBuilder {
     pub fn new() -> Box<MazeBuilder> {
-        Box::new(MazeBuilder {})
+        Box::new(MazeBuilder {
+            algorithm: MazeAlgorithm::RecursiveBacktracker,
+            braid: 0.0,
+            sparseness: 0,
+            cell_width: 1,
+            wall_width: 1,
+            keep_existing: false,
+        })
+    }
+
+    pub fn with_algorithm(mut self: Box<Self>, algorithm: MazeAlgorithm) -> Box<MazeBuilder> {
+        self.algorithm = algorithm;
+        self
+    }
+
+    /// Fraction (0..1) of dead-ends which will be removed by knocking out a wall.
+    /// Braided maze has loops, so there are less dead-ends to run into.
+    pub fn with_braid(mut self: Box<Self>, braid: f32) -> Box<MazeBuilder> {
+        self.braid = braid.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Number of passes which fill dead-end cells with rock, making the maze more sparse.
+    pub fn with_sparseness(mut self: Box<Self>, sparseness: u32) -> Box<MazeBuilder> {
+        self.sparseness = sparseness;
+        self
+    }
+
+    /// Width (in tiles) of the corridors and walls between them
+    pub fn with_cell_size(
+        mut self: Box<Self>,
+        cell_width: u32,
+        wall_width: u32,
+    ) -> Box<MazeBuilder> {
+        self.cell_width = u32::max(cell_width, 1);
+        self.wall_width = u32::max(wall_width, 1);
+        self
+    }
+
+    /// Keep the existing floor (e.g. rooms) and fill only the remaining rock with the maze.
+    /// Maze is always separated from the existing floor by the wall.
+    pub fn keep_existing(mut self: Box<Self>) -> Box<MazeBuilder> {
+        self.keep_existing = true;
+        self
     }
 
-    #[allow(clippy::map_entry)]
     fn build(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
         let mut new_map = map.clone();
-        let mut maze = Grid::new((map.width as i32 / 2) - 2, (map.height as i32 / 2) - 2, rng);
-        maze.generate_maze(&mut new_map);
+        // Leave the map border and one wall on each side of the maze
+        let stride = (self.cell_width + self.wall_width) as i32;
+        let margin = 2 * (1 + self.wall_width) as i32;
+        let width = (map.width as i32 - margin) / stride;
+        let height = (map.height as i32 - margin) / stride;
+        let mut maze = Grid::new(width, height, rng);
+        if self.keep_existing {
+            maze.mask_existing(map, self.cell_width, self.wall_width);
+        } else {
+            new_map.walkable_layer.tiles.fill(false);
+        }
+        maze.generate_maze(self.algorithm);
+        maze.sparsify(self.sparseness);
+        maze.braid(self.braid);
+        maze.copy_to_map(&mut new_map, self.cell_width, self.wall_width);
         new_map
     }
 }
@@ -52,6 +152,7 @@ struct Cell {
     column: i32,
     walls: [bool; 4],
     visited: bool,
+    removed: bool,
 }
 
 impl Cell {
@@ -61,25 +162,30 @@ impl Cell {
             column,
             walls: [true, true, true, true],
             visited: false,
+            removed: false,
         }
     }
 
-    fn remove_walls(&mut self, next: &mut Cell) {
+    fn is_dead_end(&self) -> bool {
+        !self.removed && self.walls.iter().filter(|&&w| !w).count() == 1
+    }
+
+    fn set_walls(&mut self, next: &mut Cell, wall: bool) {
         let x = self.column - next.column;
         let y = self.row - next.row;
 
         if x == 1 {
-            self.walls[LEFT] = false;
-            next.walls[RIGHT] = false;
+            self.walls[LEFT] = wall;
+            next.walls[RIGHT] = wall;
         } else if x == -1 {
-            self.walls[RIGHT] = false;
-            next.walls[LEFT] = false;
+            self.walls[RIGHT] = wall;
+            next.walls[LEFT] = wall;
         } else if y == 1 {
-            self.walls[TOP] = false;
-            next.walls[BOTTOM] = false;
+            self.walls[TOP] = wall;
+            next.walls[BOTTOM] = wall;
         } else if y == -1 {
-            self.walls[BOTTOM] = false;
-            next.walls[TOP] = false;
+            self.walls[BOTTOM] = wall;
+            next.walls[TOP] = wall;
         }
     }
 }
@@ -88,24 +194,20 @@ struct Grid<'a> {
     width: i32,
     height: i32,
     cells: Vec<Cell>,
-    backtrace: Vec<usize>,
-    current: usize,
     rng: &'a mut Rng,
 }
 
 impl<'a> Grid<'a> {
-    fn new(width: i32, height: i32, rng: &mut Rng) -> Grid {
+    fn new(width: i32, height: i32, rng: &mut Rng) -> Grid<'_> {
         let mut grid = Grid {
-            width,
-            height,
+            width: i32::max(width, 0),
+            height: i32::max(height, 0),
             cells: Vec::new(),
-            backtrace: Vec::new(),
-            current: 0,
             rng,
         };
 
-        for row in 0..height {
-            for column in 0..width {
+        for row in 0..grid.height {
+            for column in 0..grid.width {
                 grid.cells.push(Cell::new(row, column));
             }
         }
@@ -121,101 +223,604 @@ impl<'a> Grid<'a> {
         }
     }
 
-    fn get_available_neighbors(&self) -> Vec<usize> {
-        let mut neighbors: Vec<usize> = Vec::new();
+    /// Remove cells which are too close to the floor already existing on the map
+    fn mask_existing(&mut self, map: &CaveMap, cell_width: u32, wall_width: u32) {
+        let stride = cell_width + wall_width;
+        for cell in self.cells.iter_mut() {
+            // Cell area together with the walls around it
+            let x = 1 + cell.column as u32 * stride;
+            let y = 1 + cell.row as u32 * stride;
+            let size = cell_width + 2 * wall_width;
+            cell.removed = (y..y + size).any(|ty| (x..x + size).any(|tx| map.is_walkable(tx, ty)));
+        }
+    }
+
+    /// Neighbors of the cell which are part of the maze (in order: top, right, bottom, left)
+    fn get_neighbors(&self, idx: usize) -> Vec<usize> {
+        let row = self.cells[idx].row;
+        let column = self.cells[idx].column;
+
+        [
+            self.calculate_index(row - 1, column),
+            self.calculate_index(row, column + 1),
+            self.calculate_index(row + 1, column),
+            self.calculate_index(row, column - 1),
+        ]
+        .iter()
+        .filter(|&&i| i != -1 && !self.cells[i as usize].removed)
+        .map(|&i| i as usize)
+        .collect()
+    }
+
+    /// First cell which is part of the maze but wasn't visited yet.
+    /// Maze area can be split by the existing floor, so algorithms need to start in each part.
+    fn next_unvisited(&self) -> Option<usize> {
+        self.cells.iter().position(|c| !c.visited && !c.removed)
+    }
+
+    fn get_available_neighbors(&self, idx: usize) -> Vec<usize> {
+        self.get_neighbors(idx)
+            .into_iter()
+            .filter(|&i| !self.cells[i].visited)
+            .collect()
+    }
+
+    fn random_item(&mut self, items: &[usize]) -> Option<usize> {
+        if items.is_empty() {
+            None
+        } else {
+            Some(items[self.rng.usize(0..items.len())])
+        }
+    }
+
+    fn set_walls(&mut self, a: usize, b: usize, wall: bool) {
+        //   __lower_part__      __higher_part_
+        //   /            \      /            \
+        // --------cell1------ | cell2-----------
+        let (lower_part, higher_part) = self.cells.split_at_mut(std::cmp::max(a, b));
+        let cell1 = &mut lower_part[std::cmp::min(a, b)];
+        let cell2 = &mut higher_part[0];
+        cell1.set_walls(cell2, wall);
+    }
+
+    fn remove_walls(&mut self, a: usize, b: usize) {
+        self.set_walls(a, b, false);
+    }
 
-        let current_row = self.cells[self.current].row;
-        let current_column = self.cells[self.current].column;
+    fn generate_maze(&mut self, algorithm: MazeAlgorithm) {
+        if self.next_unvisited().is_none() {
+            return;
+        }
+        match algorithm {
+            MazeAlgorithm::RecursiveBacktracker => self.recursive_backtracker(),
+            MazeAlgorithm::Prim => self.prim(),
+            MazeAlgorithm::Kruskal => self.kruskal(),
+            MazeAlgorithm::Wilson => self.wilson(),
+            MazeAlgorithm::Eller => self.eller(),
+            MazeAlgorithm::HuntAndKill => self.hunt_and_kill(),
+            MazeAlgorithm::BinaryTree => self.binary_tree(),
+            MazeAlgorithm::RecursiveDivision => self.recursive_division(),
+        }
+    }
 
-        let neighbor_indices: [i32; 4] = [
-            self.calculate_index(current_row - 1, current_column),
-            self.calculate_index(current_row, current_column + 1),
-            self.calculate_index(current_row + 1, current_column),
-            self.calculate_index(current_row, current_column - 1),
-        ];
+    fn recursive_backtracker(&mut self) {
+        while let Some(start) = self.next_unvisited() {
+            let mut backtrace: Vec<usize> = Vec::new();
+            let mut current = start;
+            loop {
+                self.cells[current].visited = true;
+                let neighbors = self.get_available_neighbors(current);
 
-        for i in neighbor_indices.iter() {
-            if *i != -1 && !self.cells[*i as usize].visited {
-                neighbors.push(*i as usize);
+                match self.random_item(&neighbors) {
+                    Some(next) => {
+                        self.cells[next].visited = true;
+                        backtrace.push(current);
+                        self.remove_walls(current, next);
+                        current = next;
+                    }
+                    None => match backtrace.pop() {
+                        Some(prev) => current = prev,
+                        None => break,
+                    },
+                }
             }
         }
+    }
 
-        neighbors
+    fn prim(&mut self) {
+        let mut in_frontier = vec![false; self.cells.len()];
+        let mut frontier = Vec::new();
+        let mut start = self.random_cell();
+        while let Some(cell) = start {
+            self.cells[cell].visited = true;
+            for n in self.get_neighbors(cell) {
+                in_frontier[n] = true;
+                frontier.push(n);
+            }
+            self.prim_frontier(&mut frontier, &mut in_frontier);
+            start = self.next_unvisited();
+        }
     }
 
-    fn find_next_cell(&mut self) -> Option<usize> {
-        let neighbors = self.get_available_neighbors();
-        if !neighbors.is_empty() {
-            if neighbors.len() == 1 {
-                return Some(neighbors[0]);
-            } else {
-                let len = self.rng.choice(1..(neighbors.len() + 1)).unwrap() - 1;
-                return Some(neighbors[len]);
+    fn prim_frontier(&mut self, frontier: &mut Vec<usize>, in_frontier: &mut [bool]) {
+        while !frontier.is_empty() {
+            let cell = frontier.swap_remove(self.rng.usize(0..frontier.len()));
+            let visited: Vec<usize> = self
+                .get_neighbors(cell)
+                .into_iter()
+                .filter(|&n| self.cells[n].visited)
+                .collect();
+            if let Some(other) = self.random_item(&visited) {
+                self.remove_walls(cell, other);
+            }
+            self.cells[cell].visited = true;
+            for n in self.get_available_neighbors(cell) {
+                if !in_frontier[n] {
+                    in_frontier[n] = true;
+                    frontier.push(n);
+                }
             }
         }
-        None
     }
 
-    fn generate_maze(&mut self, map: &mut CaveMap) {
-        let mut i = 0;
-        loop {
-            self.cells[self.current].visited = true;
-            let next = self.find_next_cell();
+    /// Random cell which is part of the maze
+    fn random_cell(&mut self) -> Option<usize> {
+        let cells: Vec<usize> = (0..self.cells.len())
+            .filter(|&i| !self.cells[i].removed)
+            .collect();
+        self.random_item(&cells)
+    }
+
+    fn kruskal(&mut self) {
+        let mut edges = Vec::new();
+        for i in 0..self.cells.len() {
+            for n in self.get_neighbors(i) {
+                if n > i {
+                    edges.push((i, n));
+                }
+            }
+        }
+        self.rng.shuffle(&mut edges);
 
-            match next {
+        let mut sets = DisjointSet::new(self.cells.len());
+        for (a, b) in edges {
+            if sets.union(a, b) {
+                self.remove_walls(a, b);
+            }
+        }
+    }
+
+    fn wilson(&mut self) {
+        let mut order: Vec<usize> = (0..self.cells.len())
+            .filter(|&i| !self.cells[i].removed)
+            .collect();
+        self.rng.shuffle(&mut order);
+        // Each separate part of the maze needs its own root, otherwise the walk will never end
+        let mut sets = DisjointSet::new(self.cells.len());
+        for &i in order.iter() {
+            for n in self.get_neighbors(i) {
+                sets.union(i, n);
+            }
+        }
+        let mut roots = Vec::new();
+        for &i in order.iter() {
+            let root = sets.find(i);
+            if !roots.contains(&root) {
+                roots.push(root);
+                self.cells[i].visited = true;
+            }
+        }
+        let mut next_cell = vec![0; self.cells.len()];
+
+        for &start in order.iter() {
+            // Loop-erased random walk: keep only the last exit from each cell
+            let mut current = start;
+            while !self.cells[current].visited {
+                let neighbors = self.get_neighbors(current);
+                let next = self.random_item(&neighbors).unwrap();
+                next_cell[current] = next;
+                current = next;
+            }
+
+            let mut current = start;
+            while !self.cells[current].visited {
+                self.cells[current].visited = true;
+                let next = next_cell[current];
+                self.remove_walls(current, next);
+                current = next;
+            }
+        }
+    }
+
+    fn eller(&mut self) {
+        let width = self.width as usize;
+        let mut next_set = 0;
+        let mut row_sets: Vec<Option<usize>> = vec![None; width];
+
+        for row in 0..self.height as usize {
+            let last_row = row == self.height as usize - 1;
+            for (column, set) in row_sets.iter_mut().enumerate() {
+                if set.is_none() && !self.cells[row * width + column].removed {
+                    *set = Some(next_set);
+                    next_set += 1;
+                }
+            }
+
+            // Join adjacent cells from different sets
+            for column in 0..width - 1 {
+                let (Some(a), Some(b)) = (row_sets[column], row_sets[column + 1]) else {
+                    continue;
+                };
+                if a != b && (last_row || self.rng.bool()) {
+                    let idx = row * width + column;
+                    self.remove_walls(idx, idx + 1);
+                    for set in row_sets.iter_mut() {
+                        if *set == Some(b) {
+                            *set = Some(a);
+                        }
+                    }
+                }
+            }
+
+            if last_row {
+                break;
+            }
+
+            // Each set needs at least one connection to the next row
+            let mut next_row: Vec<Option<usize>> = vec![None; width];
+            let mut columns: Vec<usize> = (0..width).collect();
+            self.rng.shuffle(&mut columns);
+            let mut connected_sets = Vec::new();
+            for column in columns {
+                let Some(set) = row_sets[column] else {
+                    continue;
+                };
+                if self.cells[(row + 1) * width + column].removed {
+                    continue;
+                }
+                if !connected_sets.contains(&set) || self.rng.u32(0..3) == 0 {
+                    connected_sets.push(set);
+                    let idx = row * width + column;
+                    self.remove_walls(idx, idx + width);
+                    next_row[column] = Some(set);
+                }
+            }
+            row_sets = next_row;
+        }
+    }
+
+    fn hunt_and_kill(&mut self) {
+        let mut current = self.random_cell();
+        while let Some(cell) = current {
+            self.cells[cell].visited = true;
+            let neighbors = self.get_available_neighbors(cell);
+            current = match self.random_item(&neighbors) {
                 Some(next) => {
-                    self.cells[next].visited = true;
-                    self.backtrace.push(self.current);
-                    //   __lower_part__      __higher_part_
-                    //   /            \      /            \
-                    // --------cell1------ | cell2-----------
-                    let (lower_part, higher_part) =
-                        self.cells.split_at_mut(std::cmp::max(self.current, next));
-                    let cell1 = &mut lower_part[std::cmp::min(self.current, next)];
-                    let cell2 = &mut higher_part[0];
-                    cell1.remove_walls(cell2);
-                    self.current = next;
+                    self.remove_walls(cell, next);
+                    Some(next)
                 }
-                None => {
-                    if !self.backtrace.is_empty() {
-                        self.current = self.backtrace[0];
-                        self.backtrace.remove(0);
-                    } else {
-                        break;
+                None => self.hunt().or_else(|| self.next_unvisited()),
+            };
+        }
+    }
+
+    /// Find first unvisited cell next to the visited one and connect them
+    fn hunt(&mut self) -> Option<usize> {
+        for idx in 0..self.cells.len() {
+            if self.cells[idx].visited || self.cells[idx].removed {
+                continue;
+            }
+            let visited: Vec<usize> = self
+                .get_neighbors(idx)
+                .into_iter()
+                .filter(|&n| self.cells[n].visited)
+                .collect();
+            if let Some(other) = self.random_item(&visited) {
+                self.remove_walls(idx, other);
+                return Some(idx);
+            }
+        }
+        None
+    }
+
+    fn binary_tree(&mut self) {
+        for idx in 0..self.cells.len() {
+            if self.cells[idx].removed {
+                continue;
+            }
+            let row = self.cells[idx].row;
+            let column = self.cells[idx].column;
+            let candidates: Vec<usize> = [
+                self.calculate_index(row - 1, column),
+                self.calculate_index(row, column + 1),
+            ]
+            .iter()
+            .filter(|&&i| i != -1 && !self.cells[i as usize].removed)
+            .map(|&i| i as usize)
+            .collect();
+            if let Some(next) = self.random_item(&candidates) {
+                self.remove_walls(idx, next);
+            }
+        }
+    }
+
+    fn recursive_division(&mut self) {
+        // Start with an empty area and only keep the outer walls
+        for idx in 0..self.cells.len() {
+            if self.cells[idx].removed {
+                continue;
+            }
+            for n in self.get_neighbors(idx) {
+                self.remove_walls(idx, n);
+            }
+        }
+
+        // Chambers given as (column, row, width, height)
+        let mut chambers = vec![(0, 0, self.width, self.height)];
+        while let Some((x, y, w, h)) = chambers.pop() {
+            if w < 2 || h < 2 {
+                continue;
+            }
+            let horizontal = if w == h { self.rng.bool() } else { h > w };
+            if horizontal {
+                let wall_row = y + self.rng.i32(0..h - 1);
+                let passage = x + self.rng.i32(0..w);
+                for column in x..x + w {
+                    if column != passage {
+                        let a = self.calculate_index(wall_row, column) as usize;
+                        let b = self.calculate_index(wall_row + 1, column) as usize;
+                        self.set_walls(a, b, true);
+                    }
+                }
+                chambers.push((x, y, w, wall_row - y + 1));
+                chambers.push((x, wall_row + 1, w, y + h - wall_row - 1));
+            } else {
+                let wall_column = x + self.rng.i32(0..w - 1);
+                let passage = y + self.rng.i32(0..h);
+                for row in y..y + h {
+                    if row != passage {
+                        let a = self.calculate_index(row, wall_column) as usize;
+                        let b = self.calculate_index(row, wall_column + 1) as usize;
+                        self.set_walls(a, b, true);
                     }
                 }
+                chambers.push((x, y, wall_column - x + 1, h));
+                chambers.push((wall_column + 1, y, x + w - wall_column - 1, h));
             }
+        }
+    }
 
-            if i % 50 == 0 {
-                self.copy_to_map(map);
+    /// Fill dead-end cells with rock. Each pass shortens every dead-end by one cell.
+    fn sparsify(&mut self, passes: u32) {
+        for _ in 0..passes {
+            let dead_ends: Vec<usize> = (0..self.cells.len())
+                .filter(|&i| self.cells[i].is_dead_end())
+                .collect();
+            if dead_ends.is_empty() {
+                break;
+            }
+            for idx in dead_ends {
+                // Neighbor could be removed in this pass already
+                if !self.cells[idx].is_dead_end() {
+                    continue;
+                }
+                let open = self
+                    .get_neighbors(idx)
+                    .into_iter()
+                    .find(|&n| !self.has_wall(idx, n));
+                if let Some(n) = open {
+                    self.set_walls(idx, n, true);
+                }
+                self.cells[idx].removed = true;
             }
-            i += 1;
         }
     }
 
-    fn copy_to_map(&self, map: &mut CaveMap) {
-        // Clear the map
-        for i in map.walkable_layer.tiles.iter_mut() {
-            *i = false;
+    /// Remove given fraction of the dead-ends by connecting them with the neighbor cell.
+    /// Neighbors which are dead-ends too are preferred.
+    fn braid(&mut self, braid: f32) {
+        if braid <= 0.0 {
+            return;
+        }
+        let mut dead_ends: Vec<usize> = (0..self.cells.len())
+            .filter(|&i| self.cells[i].is_dead_end())
+            .collect();
+        self.rng.shuffle(&mut dead_ends);
+        for idx in dead_ends {
+            if !self.cells[idx].is_dead_end() || self.rng.f32() >= braid {
+                continue;
+            }
+            let closed: Vec<usize> = self
+                .get_neighbors(idx)
+                .into_iter()
+                .filter(|&n| !self.cells[n].removed && self.has_wall(idx, n))
+                .collect();
+            let preferred: Vec<usize> = closed
+                .iter()
+                .copied()
+                .filter(|&n| self.cells[n].is_dead_end())
+                .collect();
+            let next = if preferred.is_empty() {
+                self.random_item(&closed)
+            } else {
+                self.random_item(&preferred)
+            };
+            if let Some(next) = next {
+                self.remove_walls(idx, next);
+            }
         }
+    }
+
+    fn has_wall(&self, a: usize, b: usize) -> bool {
+        let x = self.cells[a].column - self.cells[b].column;
+        let y = self.cells[a].row - self.cells[b].row;
+        let side = match (x, y) {
+            (1, _) => LEFT,
+            (-1, _) => RIGHT,
+            (_, 1) => TOP,
+            _ => BOTTOM,
+        };
+        self.cells[a].walls[side]
+    }
+
+    fn copy_to_map(&self, map: &mut CaveMap, cell_width: u32, wall_width: u32) {
+        let stride = cell_width + wall_width;
+        let mut carve = |x: u32, y: u32, width: u32, height: u32| {
+            for ty in y..y + height {
+                for tx in x..x + width {
+                    map.set_walkable(tx, ty, true);
+                }
+            }
+        };
 
-        for cell in self.cells.iter() {
-            let x = (cell.column as u32 + 1) * 2;
-            let y = (cell.row as u32 + 1) * 2;
+        for cell in self.cells.iter().filter(|c| !c.removed) {
+            let x = 1 + wall_width + cell.column as u32 * stride;
+            let y = 1 + wall_width + cell.row as u32 * stride;
 
-            map.set_walkable(x, y, true);
+            carve(x, y, cell_width, cell_width);
             if !cell.walls[TOP] {
-                map.set_walkable(x, y - 1, true)
+                carve(x, y - wall_width, cell_width, wall_width);
             }
             if !cell.walls[RIGHT] {
-                map.set_walkable(x + 1, y, true)
+                carve(x + cell_width, y, wall_width, cell_width);
             }
             if !cell.walls[BOTTOM] {
-                map.set_walkable(x, y + 1, true)
+                carve(x, y + cell_width, cell_width, wall_width);
             }
             if !cell.walls[LEFT] {
-                map.set_walkable(x - 1, y, true)
+                carve(x - wall_width, y, wall_width, cell_width);
+            }
+        }
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use glam::UVec2;
+
+    use super::*;
+    use crate::path::DijkstraMap;
+
+    const ALGORITHMS: [MazeAlgorithm; 8] = [
+        MazeAlgorithm::RecursiveBacktracker,
+        MazeAlgorithm::Prim,
+        MazeAlgorithm::Kruskal,
+        MazeAlgorithm::Wilson,
+        MazeAlgorithm::Eller,
+        MazeAlgorithm::HuntAndKill,
+        MazeAlgorithm::BinaryTree,
+        MazeAlgorithm::RecursiveDivision,
+    ];
+
+    #[test]
+    fn test_all_cells_connected() {
+        for algorithm in ALGORITHMS {
+            let mut rng = Rng::with_seed(100);
+            let gen = MazeBuilder::new().with_algorithm(algorithm);
+            let map = gen.modify_map(&mut rng, &CaveMap::new(41, 31));
+            let dijkstra = DijkstraMap::new(&map.walkable_layer, &UVec2::new(2, 2));
+            for y in 0..map.height {
+                for x in 0..map.width {
+                    if map.is_walkable(x, y) {
+                        assert!(dijkstra.tiles[map.xy_idx(x, y)] < f32::MAX);
+                    }
+                    if x % 2 == 0 && y % 2 == 0 && (2..38).contains(&x) && (2..28).contains(&y) {
+                        assert!(map.is_walkable(x, y), "{:?}", algorithm);
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_perfect_maze() {
+        // Spanning tree over the cells has exactly (cells - 1) passages
+        for algorithm in ALGORITHMS {
+            let mut rng = Rng::with_seed(200);
+            let mut grid = Grid::new(10, 8, &mut rng);
+            grid.generate_maze(algorithm);
+            let passages: usize = grid
+                .cells
+                .iter()
+                .map(|c| c.walls.iter().filter(|&&w| !w).count())
+                .sum();
+            assert_eq!(passages / 2, 79, "{:?}", algorithm);
+        }
+    }
+
+    fn count_dead_ends(grid: &Grid) -> usize {
+        grid.cells.iter().filter(|c| c.is_dead_end()).count()
+    }
+
+    #[test]
+    fn test_braid() {
+        let mut rng = Rng::with_seed(100);
+        let mut grid = Grid::new(20, 20, &mut rng);
+        grid.generate_maze(MazeAlgorithm::Kruskal);
+        assert!(count_dead_ends(&grid) > 0);
+        grid.braid(1.0);
+        assert_eq!(count_dead_ends(&grid), 0);
+    }
+
+    #[test]
+    fn test_sparseness() {
+        let mut rng = Rng::with_seed(100);
+        let mut grid = Grid::new(20, 20, &mut rng);
+        grid.generate_maze(MazeAlgorithm::Prim);
+        grid.sparsify(3);
+        let removed = grid.cells.iter().filter(|c| c.removed).count();
+        assert!(removed > 0);
+        // Remaining cells are still connected as a tree
+        let cells = grid.cells.len() - removed;
+        let passages: usize = grid
+            .cells
+            .iter()
+            .map(|c| c.walls.iter().filter(|&&w| !w).count())
+            .sum();
+        assert_eq!(passages / 2, cells - 1);
+    }
+
+    #[test]
+    fn test_cell_size() {
+        let mut rng = Rng::with_seed(100);
+        let gen = MazeBuilder::new().with_cell_size(3, 2);
+        let map = gen.modify_map(&mut rng, &CaveMap::new(80, 50));
+        // First cell starts after the border and the wall
+        for i in 0..3 {
+            for j in 0..3 {
+                assert!(map.is_walkable(3 + i, 3 + j));
+            }
+        }
+        assert!(map.is_blocked(2, 3));
+        for x in 0..80 {
+            assert!(map.is_blocked(x, 0));
+            assert!(map.is_blocked(x, 49));
+        }
+    }
+
+    #[test]
+    fn test_keep_existing() {
+        let mut map = CaveMap::new(41, 31);
+        for x in 10..20 {
+            for y in 10..20 {
+                map.set_walkable(x, y, true);
+            }
+        }
+        for algorithm in ALGORITHMS {
+            let mut rng = Rng::with_seed(100);
+            let gen = MazeBuilder::new().keep_existing().with_algorithm(algorithm);
+            let new_map = gen.modify_map(&mut rng, &map);
+            for x in 9..21 {
+                for y in 9..21 {
+                    // Room is kept and separated from the maze by the wall
+                    let in_room = (10..20).contains(&x) && (10..20).contains(&y);
+                    assert_eq!(new_map.is_walkable(x, y), in_room, "{:?}", algorithm);
+                }
             }
+            assert!(new_map.is_walkable(2, 2));
         }
     }
 }
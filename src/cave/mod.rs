@@ -2,16 +2,34 @@
 //! Those maps are generating by carving out space from not walkable map
 //!
 
+pub mod brush;
 pub mod cellular_automata;
+pub mod coherent_noise;
+pub mod connect_regions;
+pub mod dead_ends;
+pub mod dla;
 pub mod drunkard;
+pub mod herringbone;
 pub mod maze;
 pub mod noise_generator;
+pub mod prefab;
+pub mod template_grid;
 pub mod tile_map;
 pub mod voronoi;
+pub mod wfc;
 
-pub use cellular_automata::CellularAutomata;
+pub use brush::{Brush, BrushShape, PaintMode};
+pub use cellular_automata::{BorderMode, CellularAutomata, Rule};
+pub use coherent_noise::CoherentNoise;
+pub use connect_regions::ConnectRegions;
+pub use dead_ends::RemoveDeadEnds;
+pub use dla::{DiffusionLimitedAggregation, DlaMode};
 pub use drunkard::DrunkardsWalk;
-pub use maze::MazeBuilder;
+pub use herringbone::HerringboneWang;
+pub use maze::{MazeAlgorithm, MazeBuilder};
 pub use noise_generator::NoiseGenerator;
+pub use prefab::{Placement, Prefab, PrefabBuilder, PrefabTile};
+pub use template_grid::TemplateGrid;
 pub use tile_map::{CaveMap, Symmetry};
 pub use voronoi::VoronoiHive;
+pub use wfc::WaveFunctionCollapse;
@@ -0,0 +1,422 @@
+//! Stamp hand-designed set pieces (vaults, shrines, arenas) into the map.
+//!
+//! Prefabs are written as ASCII templates:
+//!   * `#` - wall
+//!   * `.` or space - floor
+//!   * `?` - keep the existing tile
+//!   * any other character - floor with the spawn marker (e.g. `@` for the player, `g` for goblin)
+//!
+//! Lines are trimmed, so use `.` for the floor at the beginning or end of the line.
+//! Shorter lines are padded with `?`.
+//!
+//! Example usage:
+//! ```
+//! use mapgen::{CaveMap, MapFilter};
+//! use mapgen::cave::{Placement, Prefab, PrefabBuilder};
+//! use fastrand::Rng;
+//!
+//! let shrine = Prefab::from_string("
+//!     ?###?
+//!     #...#
+//!     #.!.#
+//!     #...#
+//!     ##.##
+//!     ");
+//! let mut rng = Rng::with_seed(100);
+//! let gen = PrefabBuilder::new()
+//!     .with_prefab(shrine, Placement::InsideRock)
+//!     .with_rotation(true);
+//! let map = gen.modify_map(&mut rng, &CaveMap::new(80, 50));
+//!
+//! assert_eq!(map.prefabs.len(), 1);
+//! assert_eq!(map.spawns[0].0, '!');
+//! ```
+//!
+
+use fastrand::Rng;
+use glam::UVec2;
+
+use crate::geometry::Rect;
+use crate::MapFilter;
+
+use super::CaveMap;
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum PrefabTile {
+    Wall,
+    Floor,
+    /// Don't change the map tile
+    Keep,
+}
+
+/// Hand-designed map fragment
+#[derive(PartialEq, Clone, Debug)]
+pub struct Prefab {
+    pub width: u32,
+    pub height: u32,
+    tiles: Vec<PrefabTile>,
+    spawns: Vec<(char, UVec2)>,
+}
+
+impl Prefab {
+    /// Parse ASCII template
+    pub fn from_string(template: &str) -> Prefab {
+        let lines: Vec<Vec<char>> = template
+            .split('\n')
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .map(|l| l.chars().collect())
+            .collect();
+        let width = lines.iter().map(|l| l.len() as u32).max().unwrap_or(0);
+        let height = lines.len() as u32;
+        let mut tiles = vec![PrefabTile::Keep; (width * height) as usize];
+        let mut spawns = Vec::new();
+
+        for (y, line) in lines.iter().enumerate() {
+            for (x, &c) in line.iter().enumerate() {
+                let idx = y * width as usize + x;
+                tiles[idx] = match c {
+                    '#' => PrefabTile::Wall,
+                    '.' | ' ' => PrefabTile::Floor,
+                    '?' => PrefabTile::Keep,
+                    _ => {
+                        spawns.push((c, UVec2::new(x as u32, y as u32)));
+                        PrefabTile::Floor
+                    }
+                };
+            }
+        }
+
+        Prefab {
+            width,
+            height,
+            tiles,
+            spawns,
+        }
+    }
+
+    pub fn tile(&self, x: u32, y: u32) -> PrefabTile {
+        self.tiles[(y * self.width + x) as usize]
+    }
+
+    /// Spawn markers with their position inside the prefab
+    pub fn spawns(&self) -> &[(char, UVec2)] {
+        &self.spawns
+    }
+
+    /// Prefab rotated 90 degrees clockwise
+    pub fn rotated(&self) -> Prefab {
+        let (width, height) = (self.height, self.width);
+        let mut tiles = vec![PrefabTile::Keep; self.tiles.len()];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                tiles[(x * width + self.height - 1 - y) as usize] = self.tile(x, y);
+            }
+        }
+        let spawns = self
+            .spawns
+            .iter()
+            .map(|&(c, p)| (c, UVec2::new(self.height - 1 - p.y, p.x)))
+            .collect();
+        Prefab {
+            width,
+            height,
+            tiles,
+            spawns,
+        }
+    }
+
+    /// Prefab flipped horizontally
+    pub fn mirrored(&self) -> Prefab {
+        let mut tiles = self.tiles.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                tiles[(y * self.width + self.width - 1 - x) as usize] = self.tile(x, y);
+            }
+        }
+        let spawns = self
+            .spawns
+            .iter()
+            .map(|&(c, p)| (c, UVec2::new(self.width - 1 - p.x, p.y)))
+            .collect();
+        Prefab {
+            width: self.width,
+            height: self.height,
+            tiles,
+            spawns,
+        }
+    }
+}
+
+/// Where the prefab can be stamped
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum Placement {
+    /// Random position inside the map
+    Random,
+    /// Top left corner of the prefab
+    Fixed(UVec2),
+    /// Random position where the whole prefab area is floor
+    InsideFloor,
+    /// Random position where the whole prefab area is rock
+    InsideRock,
+}
+
+struct PrefabEntry {
+    prefab: Prefab,
+    placement: Placement,
+    count: u32,
+}
+
+pub struct PrefabBuilder {
+    entries: Vec<PrefabEntry>,
+    rotation: bool,
+    mirroring: bool,
+    max_attempts: u32,
+}
+
+impl MapFilter for PrefabBuilder {
+    fn modify_map(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        self.build(rng, map)
+    }
+}
+
+impl PrefabBuilder {
+    pub fn new() -> Box<PrefabBuilder> {
+        Box::new(PrefabBuilder {
+            entries: Vec::new(),
+            rotation: false,
+            mirroring: false,
+            max_attempts: 100,
+        })
+    }
+
+    /// Stamp single instance of the prefab
+    pub fn with_prefab(
+        self: Box<Self>,
+        prefab: Prefab,
+        placement: Placement,
+    ) -> Box<PrefabBuilder> {
+        self.with_prefabs(prefab, placement, 1)
+    }
+
+    /// Stamp up to `count` instances of the prefab
+    pub fn with_prefabs(
+        mut self: Box<Self>,
+        prefab: Prefab,
+        placement: Placement,
+        count: u32,
+    ) -> Box<PrefabBuilder> {
+        self.entries.push(PrefabEntry {
+            prefab,
+            placement,
+            count,
+        });
+        self
+    }
+
+    /// Randomly rotate prefabs by 90, 180 or 270 degrees
+    pub fn with_rotation(mut self: Box<Self>, rotation: bool) -> Box<PrefabBuilder> {
+        self.rotation = rotation;
+        self
+    }
+
+    /// Randomly flip prefabs
+    pub fn with_mirroring(mut self: Box<Self>, mirroring: bool) -> Box<PrefabBuilder> {
+        self.mirroring = mirroring;
+        self
+    }
+
+    /// Number of random positions checked before the prefab is skipped
+    pub fn with_max_attempts(mut self: Box<Self>, max_attempts: u32) -> Box<PrefabBuilder> {
+        self.max_attempts = max_attempts;
+        self
+    }
+
+    fn build(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        let mut new_map = map.clone();
+        for entry in self.entries.iter() {
+            for _ in 0..entry.count {
+                let prefab = self.transform(rng, &entry.prefab);
+                if let Some(pos) = self.find_position(rng, &new_map, &prefab, entry.placement) {
+                    stamp(&mut new_map, &prefab, pos);
+                }
+            }
+        }
+        new_map
+    }
+
+    fn transform(&self, rng: &mut Rng, prefab: &Prefab) -> Prefab {
+        let mut prefab = prefab.clone();
+        if self.rotation {
+            for _ in 0..rng.u32(0..4) {
+                prefab = prefab.rotated();
+            }
+        }
+        if self.mirroring && rng.bool() {
+            prefab = prefab.mirrored();
+        }
+        prefab
+    }
+
+    fn find_position(
+        &self,
+        rng: &mut Rng,
+        map: &CaveMap,
+        prefab: &Prefab,
+        placement: Placement,
+    ) -> Option<UVec2> {
+        if let Placement::Fixed(pos) = placement {
+            return Some(pos).filter(|&p| can_place(map, prefab, p, placement));
+        }
+        // Keep the map border intact
+        if prefab.width + 2 > map.width || prefab.height + 2 > map.height {
+            return None;
+        }
+        (0..self.max_attempts)
+            .map(|_| {
+                UVec2::new(
+                    rng.u32(1..=map.width - 1 - prefab.width),
+                    rng.u32(1..=map.height - 1 - prefab.height),
+                )
+            })
+            .find(|&p| can_place(map, prefab, p, placement))
+    }
+}
+
+/// Check map bounds, collisions with other prefabs and placement constraints
+fn can_place(map: &CaveMap, prefab: &Prefab, pos: UVec2, placement: Placement) -> bool {
+    if pos.x + prefab.width > map.width || pos.y + prefab.height > map.height {
+        return false;
+    }
+    let rect = Rect::new(pos.x, pos.y, prefab.width, prefab.height);
+    if map.prefabs.iter().any(|r| r.intersect(&rect)) {
+        return false;
+    }
+    let required = match placement {
+        Placement::InsideFloor => true,
+        Placement::InsideRock => false,
+        _ => return true,
+    };
+    (pos.y..pos.y + prefab.height)
+        .all(|y| (pos.x..pos.x + prefab.width).all(|x| map.is_walkable(x, y) == required))
+}
+
+fn stamp(map: &mut CaveMap, prefab: &Prefab, pos: UVec2) {
+    for y in 0..prefab.height {
+        for x in 0..prefab.width {
+            match prefab.tile(x, y) {
+                PrefabTile::Wall => map.set_walkable(pos.x + x, pos.y + y, false),
+                PrefabTile::Floor => map.set_walkable(pos.x + x, pos.y + y, true),
+                PrefabTile::Keep => (),
+            }
+        }
+    }
+    map.prefabs
+        .push(Rect::new(pos.x, pos.y, prefab.width, prefab.height));
+    map.spawns
+        .extend(prefab.spawns.iter().map(|&(c, p)| (c, pos + p)));
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let prefab = Prefab::from_string(
+            "
+            ##?
+            #.@
+            #
+            ",
+        );
+        assert_eq!((prefab.width, prefab.height), (3, 3));
+        assert_eq!(prefab.tile(0, 0), PrefabTile::Wall);
+        assert_eq!(prefab.tile(2, 0), PrefabTile::Keep);
+        assert_eq!(prefab.tile(1, 1), PrefabTile::Floor);
+        assert_eq!(prefab.tile(2, 1), PrefabTile::Floor);
+        assert_eq!(prefab.tile(2, 2), PrefabTile::Keep);
+        assert_eq!(prefab.spawns(), &[('@', UVec2::new(2, 1))]);
+    }
+
+    #[test]
+    fn test_transform() {
+        let prefab = Prefab::from_string(
+            "
+            #.@
+            ",
+        );
+        let rotated = prefab.rotated();
+        assert_eq!((rotated.width, rotated.height), (1, 3));
+        assert_eq!(rotated.tile(0, 0), PrefabTile::Wall);
+        assert_eq!(rotated.spawns(), &[('@', UVec2::new(0, 2))]);
+        let mirrored = prefab.mirrored();
+        assert_eq!(mirrored.tile(2, 0), PrefabTile::Wall);
+        assert_eq!(mirrored.spawns(), &[('@', UVec2::new(0, 0))]);
+        let full_turn = prefab.rotated().rotated().rotated().rotated();
+        assert_eq!(full_turn, prefab);
+    }
+
+    #[test]
+    fn test_fixed_placement() {
+        let map = CaveMap::from_string(
+            "
+            ######
+            #    #
+            ######
+            ",
+        );
+        let prefab = Prefab::from_string("#?.");
+        let mut rng = Rng::with_seed(100);
+        let new_map = PrefabBuilder::new()
+            .with_prefab(prefab, Placement::Fixed(UVec2::new(1, 1)))
+            .modify_map(&mut rng, &map);
+        let expected = CaveMap::from_string(
+            "
+            ######
+            ##   #
+            ######
+            ",
+        );
+        assert_eq!(new_map.walkable_layer, expected.walkable_layer);
+        assert_eq!(new_map.prefabs, vec![Rect::new(1, 1, 3, 1)]);
+    }
+
+    #[test]
+    fn test_constraints_and_collisions() {
+        let mut map = CaveMap::new(30, 20);
+        for x in 1..15 {
+            for y in 1..19 {
+                map.set_walkable(x, y, true);
+            }
+        }
+        let prefab = Prefab::from_string(
+            "
+            ###
+            #a#
+            ###
+            ",
+        );
+        let mut rng = Rng::with_seed(100);
+        let new_map = PrefabBuilder::new()
+            .with_prefabs(prefab.clone(), Placement::InsideRock, 5)
+            .with_prefabs(prefab, Placement::InsideFloor, 5)
+            .with_rotation(true)
+            .with_mirroring(true)
+            .modify_map(&mut rng, &map);
+
+        assert_eq!(new_map.prefabs.len(), 10);
+        assert_eq!(new_map.spawns.len(), 10);
+        for (i, a) in new_map.prefabs.iter().enumerate() {
+            for b in new_map.prefabs.iter().skip(i + 1) {
+                assert!(!a.intersect(b));
+            }
+        }
+        for (_, p) in new_map.spawns.iter() {
+            assert!(new_map.is_walkable(p.x, p.y));
+        }
+    }
+}
@@ -0,0 +1,616 @@
+//! Room template grid (Spelunky style) generator.
+//!
+//! Map is divided into the grid of cells with the same size. The solution path goes from
+//! the random cell in the top row to the cell in the bottom row, moving left, right and down.
+//! Each cell is filled with the random template which has all the openings required by the path.
+//! Cells outside of the path can get any template.
+//!
+//! Templates use the same format as [crate::layer::WalkableLayer::from_string]
+//! (space is the floor, any other character is the wall), with `?` as the random tile
+//! (floor or wall). Lines are trimmed, so each template is surrounded by the wall frame
+//! which is not part of the cell. Floor tiles on the cell edge, just inside the frame,
+//! are the template openings.
+//!
+//! [TemplateGrid::from_templates] returns an error if the openings on the opposite sides
+//! of the templates don't match, or if the template set doesn't have the template for
+//! every combination of the openings which the solution path can require.
+//!
+//! Example generator usage:
+//! ```
+//! use mapgen::{MapFilter, MapBuilder};
+//! use mapgen::cave::TemplateGrid;
+//!
+//! let map = MapBuilder::new(80, 50)
+//!             .with(TemplateGrid::new())
+//!             .build(100);
+//!
+//! assert_eq!(map.width, 80);
+//! assert!(map.starting_point.is_some());
+//! assert!(map.exit_point.is_some());
+//! ```
+//!
+
+use fastrand::Rng;
+use glam::UVec2;
+
+use crate::layer::WalkableLayer;
+use crate::MapFilter;
+
+use super::CaveMap;
+
+const LEFT: u8 = 1;
+const RIGHT: u8 = 2;
+const UP: u8 = 4;
+const DOWN: u8 = 8;
+
+/// Default templates. Side openings are in the rows 3-4, and top and bottom openings
+/// in the columns 4-5
+const DEFAULT_TEMPLATES: [&str; 7] = [
+    "
+    ############
+    ############
+    ##   ??   ##
+    ##  ####  ##
+    #          #
+    #          #
+    ##  #??#  ##
+    ##        ##
+    ############
+    ############
+    ",
+    "
+    ############
+    ############
+    ##        ##
+    ## ?    ? ##
+    #          #
+    #          #
+    ##  #  #  ##
+    ##        ##
+    #####  #####
+    ############
+    ",
+    "
+    ############
+    #####  #####
+    ##        ##
+    ##  #  #  ##
+    #          #
+    #          #
+    ## ?    ? ##
+    ##        ##
+    ############
+    ############
+    ",
+    "
+    ############
+    #####  #####
+    ##        ##
+    ## #    # ##
+    #          #
+    #          #
+    ## #    # ##
+    ##        ##
+    #####  #####
+    ############
+    ",
+    "
+    ############
+    #####  #####
+    ##        ##
+    ##  #  #  ##
+    ##?       ##
+    ##       ?##
+    ##  #  #  ##
+    ##        ##
+    #####  #####
+    ############
+    ",
+    "
+    ############
+    ############
+    ####    ####
+    ##?  ##  ?##
+    #   #  #   #
+    #   #  #   #
+    ##?  ##  ?##
+    ####    ####
+    ############
+    ############
+    ",
+    "
+    ############
+    ############
+    ##?      ?##
+    ##  ####  ##
+    ##  #  #  ##
+    ##  #  #  ##
+    ##  ## #  ##
+    ##?      ?##
+    ############
+    ############
+    ",
+];
+
+#[derive(PartialEq, Copy, Clone, Debug)]
+enum TemplateTile {
+    Wall,
+    Floor,
+    Random,
+}
+
+#[derive(Clone, Debug)]
+struct Template {
+    width: u32,
+    height: u32,
+    tiles: Vec<TemplateTile>,
+    /// Sides with floor tiles on the edge
+    openings: u8,
+}
+
+impl Template {
+    /// Parse the template with its wall frame
+    fn from_string(template: &str) -> Result<Template, String> {
+        let layer = WalkableLayer::from_string(template);
+        if layer.width < 3 || layer.height < 3 {
+            return Err("Template needs to be at least 3x3 tiles with the frame".to_string());
+        }
+        // Random tiles are the walls in the layer, so they are marked separately
+        let random: Vec<UVec2> = template
+            .split('\n')
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .enumerate()
+            .flat_map(|(y, l)| {
+                l.chars()
+                    .enumerate()
+                    .filter(|&(_, c)| c == '?')
+                    .map(move |(x, _)| UVec2::new(x as u32, y as u32))
+            })
+            .collect();
+
+        let (width, height) = (layer.width - 2, layer.height - 2);
+        let on_frame = |x: u32, y: u32| x == 0 || y == 0 || x == width + 1 || y == height + 1;
+        if layer.tiles.iter().enumerate().any(|(i, &floor)| {
+            let p = layer.idx_point(i);
+            on_frame(p.x, p.y) && floor
+        }) || random.iter().any(|p| on_frame(p.x, p.y))
+        {
+            return Err("Template frame needs to be the wall".to_string());
+        }
+
+        let mut tiles = vec![TemplateTile::Wall; (width * height) as usize];
+        for y in 0..height {
+            for x in 0..width {
+                if layer.is_walkable(x + 1, y + 1) {
+                    tiles[(y * width + x) as usize] = TemplateTile::Floor;
+                }
+            }
+        }
+        for p in random {
+            tiles[((p.y - 1) * width + p.x - 1) as usize] = TemplateTile::Random;
+        }
+        let mut template = Template {
+            width,
+            height,
+            tiles,
+            openings: 0,
+        };
+        template.openings = template.find_openings();
+        Ok(template)
+    }
+
+    fn tile(&self, x: u32, y: u32) -> TemplateTile {
+        self.tiles[(y * self.width + x) as usize]
+    }
+
+    fn find_openings(&self) -> u8 {
+        [LEFT, RIGHT, UP, DOWN]
+            .into_iter()
+            .filter(|&side| self.edge(side).contains(&true))
+            .fold(0, |openings, side| openings | side)
+    }
+
+    /// Floor tiles on the given edge, from the left or from the top
+    fn edge(&self, side: u8) -> Vec<bool> {
+        let is_floor = |x: u32, y: u32| self.tile(x, y) == TemplateTile::Floor;
+        match side {
+            LEFT => (0..self.height).map(|y| is_floor(0, y)).collect(),
+            RIGHT => (0..self.height)
+                .map(|y| is_floor(self.width - 1, y))
+                .collect(),
+            UP => (0..self.width).map(|x| is_floor(x, 0)).collect(),
+            _ => (0..self.width)
+                .map(|x| is_floor(x, self.height - 1))
+                .collect(),
+        }
+    }
+
+    /// Template flipped horizontally
+    fn mirrored(&self) -> Template {
+        let mut tiles = self.tiles.clone();
+        for y in 0..self.height {
+            for x in 0..self.width {
+                tiles[(y * self.width + self.width - 1 - x) as usize] = self.tile(x, y);
+            }
+        }
+        let mut template = Template {
+            tiles,
+            ..self.clone()
+        };
+        template.openings = template.find_openings();
+        template
+    }
+}
+
+pub struct TemplateGrid {
+    cell_width: u32,
+    cell_height: u32,
+    templates: Vec<Template>,
+    down_chance: f32,
+    random_floor: f32,
+}
+
+impl MapFilter for TemplateGrid {
+    fn modify_map(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        self.build(rng, map)
+    }
+}
+
+impl TemplateGrid {
+    /// Create generator with the default set of 10x8 templates
+    pub fn new() -> Box<TemplateGrid> {
+        TemplateGrid::from_templates(&DEFAULT_TEMPLATES).expect("Default templates are valid")
+    }
+
+    /// Create generator with the given templates. All templates need to have the same size.
+    /// Mirrored templates are added automatically.
+    /// Returns error if the openings of the neighbouring templates don't match,
+    /// or if the templates don't cover all openings required by the solution path.
+    pub fn from_templates(templates: &[&str]) -> Result<Box<TemplateGrid>, String> {
+        let templates = templates
+            .iter()
+            .map(|t| Template::from_string(t))
+            .collect::<Result<Vec<Template>, String>>()?;
+        if templates.is_empty() {
+            return Err("Template grid needs at least one template".to_string());
+        }
+        let (cell_width, cell_height) = (templates[0].width, templates[0].height);
+        if templates
+            .iter()
+            .any(|t| t.width != cell_width || t.height != cell_height)
+        {
+            return Err("All templates need to have the same size".to_string());
+        }
+        let mirrored: Vec<Template> = templates.iter().map(|t| t.mirrored()).collect();
+        let templates: Vec<Template> = templates.into_iter().chain(mirrored).collect();
+        // Any 2 templates can be neighbours, so all openings on the opposite sides
+        // need to have the same floor tiles
+        for (side, opposite) in [(LEFT, RIGHT), (UP, DOWN)] {
+            let mut edges = templates
+                .iter()
+                .flat_map(|t| [(t, side), (t, opposite)])
+                .filter(|&(t, s)| t.openings & s != 0)
+                .map(|(t, s)| t.edge(s));
+            if let Some(first) = edges.next() {
+                if edges.any(|edge| edge != first) {
+                    return Err(format!(
+                        "Template openings don't match: {}",
+                        openings_name(side | opposite)
+                    ));
+                }
+            }
+        }
+        for required in required_openings() {
+            if !templates.iter().any(|t| t.openings & required == required) {
+                return Err(format!(
+                    "No template with the required openings: {}",
+                    openings_name(required)
+                ));
+            }
+        }
+        Ok(Box::new(TemplateGrid {
+            cell_width,
+            cell_height,
+            templates,
+            down_chance: 0.25,
+            random_floor: 0.5,
+        }))
+    }
+
+    /// Probability of moving the path down to the next row
+    pub fn with_down_chance(mut self: Box<Self>, down_chance: f32) -> Box<Self> {
+        self.down_chance = down_chance;
+        self
+    }
+
+    /// Probability that random tile (`?`) becomes floor
+    pub fn with_random_floor(mut self: Box<Self>, random_floor: f32) -> Box<Self> {
+        self.random_floor = random_floor;
+        self
+    }
+
+    fn build(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        let cols = map.width.saturating_sub(2) / self.cell_width;
+        let rows = map.height.saturating_sub(2) / self.cell_height;
+        if cols == 0 || rows == 0 {
+            return map.clone();
+        }
+        let mut new_map = CaveMap::new(map.width, map.height);
+        // Center the grid on the map
+        let x0 = 1 + (map.width - 2 - cols * self.cell_width) / 2;
+        let y0 = 1 + (map.height - 2 - rows * self.cell_height) / 2;
+
+        let (path, openings) = self.solution_path(rng, cols, rows);
+        for row in 0..rows {
+            for col in 0..cols {
+                let required = openings[(row * cols + col) as usize];
+                let template = self.choose_template(rng, required);
+                let origin = UVec2::new(x0 + col * self.cell_width, y0 + row * self.cell_height);
+                self.stamp(rng, &mut new_map, template, origin);
+            }
+        }
+
+        let cell_center = |cell: UVec2| {
+            UVec2::new(
+                x0 + cell.x * self.cell_width + self.cell_width / 2,
+                y0 + cell.y * self.cell_height + self.cell_height / 2,
+            )
+        };
+        new_map.starting_point = nearest_floor(&new_map, cell_center(path[0]));
+        new_map.exit_point = nearest_floor(&new_map, cell_center(path[path.len() - 1]));
+        new_map
+    }
+
+    /// Path from the top row to the bottom row, and the openings required in each cell
+    fn solution_path(&self, rng: &mut Rng, cols: u32, rows: u32) -> (Vec<UVec2>, Vec<u8>) {
+        let mut openings = vec![0; (cols * rows) as usize];
+        let mut cell = UVec2::new(rng.u32(0..cols), 0);
+        let mut path = vec![cell];
+        let mut dir: i32 = if rng.bool() { 1 } else { -1 };
+        loop {
+            let at_edge = (dir < 0 && cell.x == 0) || (dir > 0 && cell.x == cols - 1);
+            let idx = (cell.y * cols + cell.x) as usize;
+            if at_edge || rng.f32() < self.down_chance {
+                if cell.y + 1 == rows {
+                    break;
+                }
+                openings[idx] |= DOWN;
+                cell.y += 1;
+                openings[idx + cols as usize] |= UP;
+                dir = if rng.bool() { 1 } else { -1 };
+            } else {
+                cell.x = (cell.x as i32 + dir) as u32;
+                let (from, to) = if dir > 0 {
+                    (RIGHT, LEFT)
+                } else {
+                    (LEFT, RIGHT)
+                };
+                openings[idx] |= from;
+                openings[(cell.y * cols + cell.x) as usize] |= to;
+            }
+            path.push(cell);
+        }
+        (path, openings)
+    }
+
+    fn choose_template(&self, rng: &mut Rng, required: u8) -> &Template {
+        let candidates: Vec<&Template> = self
+            .templates
+            .iter()
+            .filter(|t| t.openings & required == required)
+            .collect();
+        // Checked in from_templates for all openings which the solution path can require
+        debug_assert!(
+            !candidates.is_empty(),
+            "No template with the required openings"
+        );
+        candidates[rng.usize(0..candidates.len())]
+    }
+
+    fn stamp(&self, rng: &mut Rng, map: &mut CaveMap, template: &Template, origin: UVec2) {
+        for y in 0..template.height {
+            for x in 0..template.width {
+                let floor = match template.tile(x, y) {
+                    TemplateTile::Wall => false,
+                    TemplateTile::Floor => true,
+                    TemplateTile::Random => rng.f32() < self.random_floor,
+                };
+                map.set_walkable(origin.x + x, origin.y + y, floor);
+            }
+        }
+    }
+}
+
+/// Openings which the solution path can require in a single cell:
+/// entered from the top or the side and left through the other side or the bottom.
+fn required_openings() -> Vec<u8> {
+    let mut openings = Vec::new();
+    for entry in [0, UP, LEFT, RIGHT] {
+        for exit in [0, LEFT, RIGHT, DOWN] {
+            if entry == 0 || entry != exit {
+                openings.push(entry | exit);
+            }
+        }
+    }
+    openings
+}
+
+fn openings_name(openings: u8) -> String {
+    let names: Vec<&str> = [(LEFT, "left"), (RIGHT, "right"), (UP, "up"), (DOWN, "down")]
+        .iter()
+        .filter(|(side, _)| openings & side != 0)
+        .map(|&(_, name)| name)
+        .collect();
+    if names.is_empty() {
+        "none".to_string()
+    } else {
+        names.join(", ")
+    }
+}
+
+/// Floor tile closest to the given point
+fn nearest_floor(map: &CaveMap, point: UVec2) -> Option<UVec2> {
+    let mut best: Option<(UVec2, f32)> = None;
+    for y in 0..map.height {
+        for x in 0..map.width {
+            if map.is_walkable(x, y) {
+                let p = UVec2::new(x, y);
+                let distance = p.as_vec2().distance(point.as_vec2());
+                if best.is_none_or(|(_, d)| distance < d) {
+                    best = Some((p, distance));
+                }
+            }
+        }
+    }
+    best.map(|(p, _)| p)
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::DijkstraMap;
+
+    #[test]
+    fn test_openings() {
+        let template = Template::from_string(
+            "
+            ########
+            #####  #
+            #      #
+            ##    ##
+            ########
+            ########
+            ",
+        )
+        .unwrap();
+        assert_eq!(template.width, 6);
+        assert_eq!(template.height, 4);
+        assert_eq!(template.openings, LEFT | RIGHT | UP);
+        let template = Template::from_string(
+            "
+            ########
+            ########
+            #     ##
+            ###?####
+            ########
+            ",
+        )
+        .unwrap();
+        assert_eq!(template.tile(2, 2), TemplateTile::Random);
+        assert_eq!(template.openings, LEFT);
+        assert_eq!(template.mirrored().openings, RIGHT);
+    }
+
+    #[test]
+    fn test_frame() {
+        let open_frame = "
+            ## ##
+            #   #
+            #####
+            ";
+        assert_eq!(
+            Template::from_string(open_frame).err(),
+            Some("Template frame needs to be the wall".to_string())
+        );
+        assert!(Template::from_string("##\n##").is_err());
+    }
+
+    #[test]
+    fn test_solution_path() {
+        let mut rng = Rng::with_seed(100);
+        let map = TemplateGrid::new().modify_map(&mut rng, &CaveMap::new(80, 50));
+        let start = map.starting_point.unwrap();
+        let exit = map.exit_point.unwrap();
+        assert!(start.y < 1 + 8);
+        assert!(exit.y >= 50 - 1 - 8);
+        for seed in 0..20 {
+            let mut rng = Rng::with_seed(seed);
+            let map = TemplateGrid::new().modify_map(&mut rng, &CaveMap::new(80, 50));
+            let start = map.starting_point.unwrap();
+            let exit = map.exit_point.unwrap();
+            let dijkstra = DijkstraMap::new(&map.walkable_layer, &start);
+            assert!(dijkstra.tiles[map.xy_idx(exit.x, exit.y)] < f32::MAX);
+        }
+    }
+
+    #[test]
+    fn test_border() {
+        let mut rng = Rng::with_seed(100);
+        let map = TemplateGrid::new().modify_map(&mut rng, &CaveMap::new(80, 50));
+        for x in 0..80 {
+            assert!(map.is_blocked(x, 0));
+            assert!(map.is_blocked(x, 49));
+        }
+        for y in 0..50 {
+            assert!(map.is_blocked(0, y));
+            assert!(map.is_blocked(79, y));
+        }
+    }
+
+    #[test]
+    fn test_template_size() {
+        assert!(TemplateGrid::from_templates(&["###\n###\n###", "####\n####\n####"]).is_err());
+    }
+
+    #[test]
+    fn test_missing_openings() {
+        // Only the side openings, the path can't go down
+        let sides = "
+            ######
+            ######
+            #    #
+            ######
+            ######
+            ";
+        let result = TemplateGrid::from_templates(&[sides]);
+        assert_eq!(
+            result.err(),
+            Some("No template with the required openings: down".to_string())
+        );
+        let all = "
+            ######
+            ##  ##
+            #    #
+            ##  ##
+            ######
+            ";
+        assert!(TemplateGrid::from_templates(&[sides, all]).is_ok());
+    }
+
+    #[test]
+    fn test_misaligned_openings() {
+        let all = "
+            ########
+            ###  ###
+            #      #
+            ###  ###
+            ########
+            ";
+        // Down opening is shifted to the left
+        let shifted = "
+            ########
+            ###  ###
+            #      #
+            ##  ####
+            ########
+            ";
+        let result = TemplateGrid::from_templates(&[all, shifted]);
+        assert_eq!(
+            result.err(),
+            Some("Template openings don't match: up, down".to_string())
+        );
+        // Template mirrored horizontally has the left opening in the different row
+        let side = "
+            ######
+            ######
+            #   ##
+            ##   #
+            ######
+            ";
+        assert!(TemplateGrid::from_templates(&[side]).is_err());
+    }
+}
@@ -9,7 +9,9 @@
 
 use glam::UVec2;
 
-use crate::layer::WalkableLayer;
+use super::brush::Brush;
+use crate::geometry::Rect;
+use crate::layer::{DataLayer, WalkableLayer};
 use std::fmt;
 
 #[derive(PartialEq, Copy, Clone)]
@@ -28,6 +30,14 @@ pub struct CaveMap {
     pub height: u32,
     pub starting_point: Option<UVec2>,
     pub exit_point: Option<UVec2>,
+    /// Raw noise values used to create this map (if the generator keeps them)
+    pub noise_layer: Option<DataLayer<f32>>,
+    /// Doors connecting separate regions of the map
+    pub doors: Vec<UVec2>,
+    /// Areas occupied by the stamped prefabs
+    pub prefabs: Vec<Rect>,
+    /// Spawn markers placed by prefabs
+    pub spawns: Vec<(char, UVec2)>,
 }
 
 impl CaveMap {
@@ -39,18 +49,30 @@ impl CaveMap {
             height,
             starting_point: None,
             exit_point: None,
+            noise_layer: None,
+            doors: Vec::new(),
+            prefabs: Vec::new(),
+            spawns: Vec::new(),
         }
     }
 
     /// Create map from given string
     pub fn from_string(map_string: &str) -> Self {
-        let walkable_layer = WalkableLayer::from_string(map_string);
+        Self::from_layer(WalkableLayer::from_string(map_string))
+    }
+
+    /// Create map from the existing layer (e.g. generated by the rooms generator)
+    pub fn from_layer(walkable_layer: WalkableLayer) -> Self {
         Self {
             width: walkable_layer.width,
             height: walkable_layer.height,
             walkable_layer,
             starting_point: None,
             exit_point: None,
+            noise_layer: None,
+            doors: Vec::new(),
+            prefabs: Vec::new(),
+            spawns: Vec::new(),
         }
     }
 
@@ -81,66 +103,61 @@ impl CaveMap {
         }
     }
 
-    pub fn paint(&mut self, mode: Symmetry, brush_size: u32, x: u32, y: u32) {
+    /// Paint the map with the given brush.
+    /// With symmetry enabled the brush is also applied at the mirrored positions.
+    /// Returns the number of tiles which changed their state.
+    pub fn paint(&mut self, mode: Symmetry, brush: &Brush, x: u32, y: u32) -> u32 {
+        self.paint_positions(mode, x, y)
+            .iter()
+            .map(|p| self.apply_paint(brush, p.x, p.y))
+            .sum()
+    }
+
+    /// Positions where the brush is applied when painting at the given point
+    pub(crate) fn paint_positions(&self, mode: Symmetry, x: u32, y: u32) -> Vec<UVec2> {
+        let center_x = self.width / 2;
+        let center_y = self.height / 2;
+        let dist_x = center_x.abs_diff(x);
+        let dist_y = center_y.abs_diff(y);
         match mode {
-            Symmetry::None => self.apply_paint(brush_size, x, y),
+            Symmetry::None => vec![UVec2::new(x, y)],
             Symmetry::Horizontal => {
-                let center_x = self.width / 2;
                 if x == center_x {
-                    self.apply_paint(brush_size, x, y);
+                    vec![UVec2::new(x, y)]
                 } else {
-                    let dist_x = center_x.abs_diff(x);
-                    self.apply_paint(brush_size, center_x + dist_x, y);
-                    self.apply_paint(brush_size, center_x - dist_x, y);
+                    vec![
+                        UVec2::new(center_x + dist_x, y),
+                        UVec2::new(center_x.saturating_sub(dist_x), y),
+                    ]
                 }
             }
             Symmetry::Vertical => {
-                let center_y = self.height / 2;
                 if y == center_y {
-                    self.apply_paint(brush_size, x, y);
+                    vec![UVec2::new(x, y)]
                 } else {
-                    let dist_y = center_y.abs_diff(y);
-                    self.apply_paint(brush_size, x, center_y + dist_y);
-                    self.apply_paint(brush_size, x, center_y - dist_y);
+                    vec![
+                        UVec2::new(x, center_y + dist_y),
+                        UVec2::new(x, center_y.saturating_sub(dist_y)),
+                    ]
                 }
             }
             Symmetry::Both => {
-                let center_x = self.width / 2;
-                let center_y = self.height / 2;
                 if x == center_x && y == center_y {
-                    self.apply_paint(brush_size, x, y);
+                    vec![UVec2::new(x, y)]
                 } else {
-                    let dist_x = center_x.abs_diff(x);
-                    self.apply_paint(brush_size, center_x + dist_x, y);
-                    self.apply_paint(brush_size, center_x - dist_x, y);
-                    let dist_y = center_y.abs_diff(y);
-                    self.apply_paint(brush_size, x, center_y + dist_y);
-                    self.apply_paint(brush_size, x, center_y - dist_y);
+                    vec![
+                        UVec2::new(center_x + dist_x, y),
+                        UVec2::new(center_x.saturating_sub(dist_x), y),
+                        UVec2::new(x, center_y + dist_y),
+                        UVec2::new(x, center_y.saturating_sub(dist_y)),
+                    ]
                 }
             }
         }
     }
 
-    fn apply_paint(&mut self, brush_size: u32, x: u32, y: u32) {
-        match brush_size {
-            1 => {
-                self.set_walkable(x, y, true);
-            }
-            _ => {
-                let half_brush_size = brush_size / 2;
-                for brush_y in y - half_brush_size..y + half_brush_size {
-                    for brush_x in x - half_brush_size..x + half_brush_size {
-                        if brush_x > 1
-                            && brush_x < self.width - 1
-                            && brush_y > 1
-                            && brush_y < self.height - 1
-                        {
-                            self.set_walkable(brush_x, brush_y, true);
-                        }
-                    }
-                }
-            }
-        }
+    fn apply_paint(&mut self, brush: &Brush, x: u32, y: u32) -> u32 {
+        brush.apply(&mut self.walkable_layer, x, y)
     }
 }
 
@@ -210,4 +227,14 @@ mod tests {
         assert_eq!(x, x2);
         assert_eq!(y, y2);
     }
+
+    #[test]
+    fn test_paint_symmetry() {
+        let mut map = CaveMap::new(11, 5);
+        let changed = map.paint(Symmetry::Horizontal, &Brush::square(1), 2, 2);
+        assert_eq!(changed, 2);
+        assert!(map.is_walkable(2, 2));
+        assert!(map.is_walkable(8, 2));
+        assert_eq!(map.walkable_layer.tiles.iter().filter(|&&t| t).count(), 2);
+    }
 }
@@ -12,16 +12,42 @@
 //! assert_eq!(map.height, 50);
 //! ```
 //!
+//! Configured hive with more regular cells:
+//! ```
+//! use mapgen::{CaveMap, MapFilter};
+//! use mapgen::cave::VoronoiHive;
+//! use mapgen::geometry::DistanceMetric;
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let gen = VoronoiHive::new()
+//!     .with_seeds(32)
+//!     .with_metric(DistanceMetric::Manhattan)
+//!     .with_relaxation(2)
+//!     .with_wall_thickness(2);
+//! let map = gen.modify_map(&mut rng, &CaveMap::new(80, 50));
+//!
+//! assert_eq!(map.width, 80);
+//! ```
+//!
+
+use std::collections::HashSet;
 
 use fastrand::Rng;
-use glam::UVec2;
+use glam::{UVec2, Vec2};
 
+use crate::geometry::DistanceMetric;
 use crate::MapFilter;
 
 use super::CaveMap;
 
+const NO_SEED: usize = usize::MAX;
+
 pub struct VoronoiHive {
     n_seeds: u32,
+    metric: DistanceMetric,
+    relaxation: u32,
+    wall_thickness: u32,
 }
 
 impl MapFilter for VoronoiHive {
@@ -32,26 +58,56 @@ impl MapFilter for VoronoiHive {
 
 impl VoronoiHive {
     pub fn new() -> Box<VoronoiHive> {
-        Box::new(VoronoiHive { n_seeds: 64 })
+        Box::new(VoronoiHive {
+            n_seeds: 64,
+            metric: DistanceMetric::Euclidean,
+            relaxation: 0,
+            wall_thickness: 1,
+        })
     }
 
-    fn build(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
-        let mut new_map = map.clone();
-        let seeds = self.generate_seeds(rng, map.width, map.height);
-
-        let mut voronoi_distance = vec![(0, 0.0f32); self.n_seeds as usize];
-        let mut voronoi_membership: Vec<i32> = vec![0; (map.width * map.height) as usize];
-        for (i, vid) in voronoi_membership.iter_mut().enumerate() {
-            for (seed, pos) in seeds.iter().enumerate() {
-                let distance = (pos.as_vec2() - map.idx_point(i).as_vec2()).length();
-                voronoi_distance[seed] = (seed, distance);
-            }
+    /// Number of the Voronoi cells
+    pub fn with_seeds(mut self: Box<Self>, n_seeds: u32) -> Box<VoronoiHive> {
+        self.n_seeds = u32::max(n_seeds, 1);
+        self
+    }
+
+    pub fn with_metric(mut self: Box<Self>, metric: DistanceMetric) -> Box<VoronoiHive> {
+        self.metric = metric;
+        self
+    }
+
+    /// Number of Lloyd relaxation iterations. Each iteration moves seeds to the center
+    /// of their cells, which makes cells more even in size.
+    pub fn with_relaxation(mut self: Box<Self>, iterations: u32) -> Box<VoronoiHive> {
+        self.relaxation = iterations;
+        self
+    }
 
-            voronoi_distance.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+    /// Width of the walls between the cells (in tiles)
+    pub fn with_wall_thickness(mut self: Box<Self>, thickness: u32) -> Box<VoronoiHive> {
+        self.wall_thickness = u32::max(thickness, 1);
+        self
+    }
 
-            *vid = voronoi_distance[0].0 as i32;
+    fn build(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        if map.width < 3 || map.height < 3 {
+            return map.clone();
+        }
+        let mut seeds = self.generate_seeds(rng, map.width, map.height);
+        let mut voronoi_membership = self.assign_seeds(&seeds, map.width, map.height);
+        for _ in 0..self.relaxation {
+            seeds = relax_seeds(&seeds, &voronoi_membership, map);
+            voronoi_membership = self.assign_seeds(&seeds, map.width, map.height);
         }
 
+        self.carve_cells(map, &voronoi_membership)
+    }
+
+    /// Floor inside of the cells, with walls on the cell borders
+    fn carve_cells(&self, map: &CaveMap, voronoi_membership: &[usize]) -> CaveMap {
+        let mut new_map = map.clone();
+        let mut walls = vec![false; voronoi_membership.len()];
         for y in 1..new_map.height - 1 {
             for x in 1..new_map.width - 1 {
                 let mut neighbors = 0;
@@ -70,7 +126,27 @@ impl VoronoiHive {
                     neighbors += 1;
                 }
 
-                if neighbors < 2 {
+                walls[my_idx] = neighbors >= 2;
+            }
+        }
+
+        // Make walls thicker. Walls grow only to the right and down,
+        // so the wall 1 tile wide becomes exactly `wall_thickness` tiles wide.
+        let radius = self.wall_thickness as i64 - 1;
+        for y in 1..new_map.height - 1 {
+            for x in 1..new_map.width - 1 {
+                let is_wall = (-radius..=0).any(|dy| {
+                    (-radius..=0).any(|dx| {
+                        let nx = x as i64 + dx;
+                        let ny = y as i64 + dy;
+                        nx >= 0
+                            && ny >= 0
+                            && nx < map.width as i64
+                            && ny < map.height as i64
+                            && walls[new_map.xy_idx(nx as u32, ny as u32)]
+                    })
+                });
+                if !is_wall {
                     new_map.set_walkable(x, y, true);
                 }
             }
@@ -82,16 +158,189 @@ impl VoronoiHive {
     /// Generate random seeds
     fn generate_seeds(&self, rng: &mut Rng, width: u32, height: u32) -> Vec<UVec2> {
         let mut seeds: Vec<UVec2> = Vec::new();
+        let mut used: HashSet<UVec2> = HashSet::new();
+        let n_seeds = u32::min(self.n_seeds, (width - 1) * (height - 1));
 
-        while (seeds.len() as u32) < self.n_seeds {
+        while (seeds.len() as u32) < n_seeds {
             let vx = rng.u32(1..width);
             let vy = rng.u32(1..height);
             let candidate = UVec2::new(vx, vy);
-            if !seeds.contains(&candidate) {
+            if used.insert(candidate) {
                 seeds.push(candidate);
             }
         }
 
         seeds
     }
+
+    /// Find the nearest seed for each tile using the Jump Flooding Algorithm.
+    /// Each pass propagates the best known seed from tiles `step` away, halving the step
+    /// each time. This needs O(tiles * log(size)) distance calculations instead of
+    /// O(tiles * seeds) for the brute force approach.
+    fn assign_seeds(&self, seeds: &[UVec2], width: u32, height: u32) -> Vec<usize> {
+        let mut nearest = vec![NO_SEED; (width * height) as usize];
+        for (i, seed) in seeds.iter().enumerate() {
+            nearest[(seed.y * width + seed.x) as usize] = i;
+        }
+
+        let distance = |idx: usize, seed: usize| {
+            let p = Vec2::new((idx as u32 % width) as f32, (idx as u32 / width) as f32);
+            self.metric.distance(p, seeds[seed].as_vec2())
+        };
+        let is_better = |idx: usize, candidate: usize, best: usize| {
+            if best == NO_SEED {
+                return true;
+            }
+            let (dc, db) = (distance(idx, candidate), distance(idx, best));
+            dc < db || (dc == db && candidate < best)
+        };
+
+        let mut steps = Vec::new();
+        let mut step = u32::max(width, height).next_power_of_two() / 2;
+        while step > 0 {
+            steps.push(step as i64);
+            step /= 2;
+        }
+        // Additional pass with step 1 fixes most of the remaining errors
+        steps.push(1);
+
+        for step in steps {
+            let previous = nearest.clone();
+            for y in 0..height as i64 {
+                for x in 0..width as i64 {
+                    let idx = (y * width as i64 + x) as usize;
+                    let mut best = previous[idx];
+                    for dy in [-step, 0, step] {
+                        for dx in [-step, 0, step] {
+                            let (nx, ny) = (x + dx, y + dy);
+                            if nx < 0 || ny < 0 || nx >= width as i64 || ny >= height as i64 {
+                                continue;
+                            }
+                            let candidate = previous[(ny * width as i64 + nx) as usize];
+                            if candidate != NO_SEED
+                                && candidate != best
+                                && is_better(idx, candidate, best)
+                            {
+                                best = candidate;
+                            }
+                        }
+                    }
+                    nearest[idx] = best;
+                }
+            }
+        }
+
+        nearest
+    }
+}
+
+/// Move each seed to the centroid of its cell
+fn relax_seeds(seeds: &[UVec2], membership: &[usize], map: &CaveMap) -> Vec<UVec2> {
+    let mut sums = vec![(Vec2::ZERO, 0u32); seeds.len()];
+    for (idx, &seed) in membership.iter().enumerate() {
+        if seed != NO_SEED {
+            sums[seed].0 += map.idx_point(idx).as_vec2();
+            sums[seed].1 += 1;
+        }
+    }
+    seeds
+        .iter()
+        .zip(sums)
+        .map(|(&seed, (sum, count))| {
+            if count == 0 {
+                seed
+            } else {
+                (sum / count as f32).round().as_uvec2()
+            }
+        })
+        .collect()
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn brute_force(seeds: &[UVec2], metric: DistanceMetric, width: u32, height: u32) -> Vec<f32> {
+        (0..width * height)
+            .map(|i| {
+                let p = Vec2::new((i % width) as f32, (i / width) as f32);
+                seeds
+                    .iter()
+                    .map(|s| metric.distance(p, s.as_vec2()))
+                    .fold(f32::MAX, f32::min)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_jump_flooding() {
+        for metric in [
+            DistanceMetric::Euclidean,
+            DistanceMetric::Manhattan,
+            DistanceMetric::Chebyshev,
+        ] {
+            let mut rng = Rng::with_seed(100);
+            let hive = VoronoiHive::new().with_seeds(30).with_metric(metric);
+            let seeds = hive.generate_seeds(&mut rng, 80, 50);
+            let membership = hive.assign_seeds(&seeds, 80, 50);
+            let expected = brute_force(&seeds, metric, 80, 50);
+            // Jump flooding is approximate, but only very few tiles can be assigned wrong
+            let errors = membership
+                .iter()
+                .enumerate()
+                .filter(|(i, &s)| {
+                    let p = Vec2::new((*i as u32 % 80) as f32, (*i as u32 / 80) as f32);
+                    metric.distance(p, seeds[s].as_vec2()) > expected[*i]
+                })
+                .count();
+            assert!(errors <= 10, "{:?}: {}", metric, errors);
+        }
+    }
+
+    #[test]
+    fn test_relaxation() {
+        let mut rng = Rng::with_seed(100);
+        let gen = VoronoiHive::new().with_seeds(20).with_relaxation(3);
+        let map = gen.modify_map(&mut rng, &CaveMap::new(80, 50));
+        assert!(map.walkable_layer.tiles.iter().any(|&t| t));
+    }
+
+    #[test]
+    fn test_wall_thickness() {
+        let mut rng = Rng::with_seed(100);
+        let thin = VoronoiHive::new().modify_map(&mut rng, &CaveMap::new(80, 50));
+        let mut rng = Rng::with_seed(100);
+        let thick = VoronoiHive::new()
+            .with_wall_thickness(2)
+            .modify_map(&mut rng, &CaveMap::new(80, 50));
+        let floor = |m: &CaveMap| m.walkable_layer.tiles.iter().filter(|&&t| t).count();
+        assert!(floor(&thick) < floor(&thin));
+        // Thick walls only cover tiles which were floor
+        let tiles = thin.walkable_layer.tiles.iter();
+        for (t1, t2) in tiles.zip(thick.walkable_layer.tiles.iter()) {
+            assert!(*t1 || !*t2);
+        }
+    }
+
+    #[test]
+    fn test_wall_width() {
+        // Cell 1 tile wide between 2 other cells creates the vertical wall
+        let map = CaveMap::new(21, 9);
+        let membership: Vec<usize> = (0..21 * 9)
+            .map(|i| match i % 21 {
+                x if x < 10 => 0,
+                10 => 1,
+                _ => 2,
+            })
+            .collect();
+        for thickness in 1..4 {
+            let hive = VoronoiHive::new().with_wall_thickness(thickness);
+            let new_map = hive.carve_cells(&map, &membership);
+            let walls = (1..20).filter(|&x| new_map.is_blocked(x, 4)).count();
+            assert_eq!(walls, thickness as usize);
+        }
+    }
 }
@@ -0,0 +1,719 @@
+//! Wave Function Collapse (overlapping model).
+//!
+//! The generator learns which N×N patterns exist in the sample map and how they can
+//! overlap. Then it synthesises the new map where every N×N window comes from the sample.
+//!
+//! When the algorithm runs into the contradiction it backtracks to the previous decisions.
+//! If that doesn't help, it restarts from scratch. When all restarts fail, the map is returned
+//! unchanged. Use [`WaveFunctionCollapse::generate`] to detect this case.
+//!
+//! Check this [repository](https://github.com/mxgmn/WaveFunctionCollapse)
+//! for more information about the algorithm.
+//!
+//! Example generator usage:
+//! ```
+//! use fastrand::Rng;
+//! use mapgen::{CaveMap, MapFilter};
+//! use mapgen::cave::WaveFunctionCollapse;
+//! use mapgen::layer::WalkableLayer;
+//!
+//! let sample = WalkableLayer::from_string("
+//!     ############
+//!     #    #     #
+//!     #    #  #  #
+//!     ##  ##  #  #
+//!     #       #  #
+//!     #   ########
+//!     #          #
+//!     ############
+//!     ");
+//! let mut rng = Rng::with_seed(100);
+//! let gen = WaveFunctionCollapse::new(&sample);
+//! let map = gen.modify_map(&mut rng, &CaveMap::new(40, 30));
+//!
+//! assert_eq!(map.width, 40);
+//! assert_eq!(map.height, 30);
+//! ```
+//!
+
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, VecDeque};
+
+use fastrand::Rng;
+use glam::UVec2;
+
+use crate::layer::WalkableLayer;
+use crate::MapFilter;
+
+use super::CaveMap;
+
+const DIRECTIONS: [(i64, i64); 4] = [(1, 0), (0, 1), (-1, 0), (0, -1)];
+
+/// Decision which can be undone when backtracking
+struct Decision {
+    /// Length of the trail before the decision
+    trail_len: usize,
+    cell: usize,
+    pattern: usize,
+}
+
+/// Undo log: previous value of each changed wave word and the entropy of its cell
+#[derive(Default)]
+struct Trail {
+    changes: VecDeque<(usize, u64, f32)>,
+    /// Number of the oldest changes which were forgotten
+    forgotten: usize,
+}
+
+impl Trail {
+    fn len(&self) -> usize {
+        self.forgotten + self.changes.len()
+    }
+
+    fn record(&mut self, word: usize, bits: u64, entropy: f32) {
+        self.changes.push_back((word, bits, entropy));
+    }
+
+    /// Changes made after the trail had the given length
+    fn since(&self, len: usize) -> impl Iterator<Item = &(usize, u64, f32)> + '_ {
+        self.changes.range(len.saturating_sub(self.forgotten)..)
+    }
+
+    /// Forget changes which can't be undone anymore
+    fn forget(&mut self, len: usize) {
+        while self.forgotten < len && self.changes.pop_front().is_some() {
+            self.forgotten += 1;
+        }
+    }
+}
+
+/// Cell waiting for the collapse. Ordered so the lowest entropy is on top of the heap.
+#[derive(PartialEq)]
+struct Candidate {
+    priority: f32,
+    entropy: f32,
+    cell: usize,
+}
+
+impl Eq for Candidate {}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Candidate {
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .priority
+            .total_cmp(&self.priority)
+            .then(other.cell.cmp(&self.cell))
+    }
+}
+
+pub struct WaveFunctionCollapse {
+    sample: WalkableLayer,
+    pattern_size: u32,
+    patterns: Vec<Vec<bool>>,
+    weights: Vec<f32>,
+    /// For each direction and pattern: bitset of patterns which can be placed next to it
+    propagator: Vec<Vec<Vec<u64>>>,
+    periodic: bool,
+    wall_border: bool,
+    fixed_tiles: Vec<(UVec2, bool)>,
+    max_restarts: u32,
+    max_backtrack_depth: usize,
+}
+
+impl MapFilter for WaveFunctionCollapse {
+    fn modify_map(&self, rng: &mut Rng, map: &CaveMap) -> CaveMap {
+        match self.generate(rng, map.width, map.height) {
+            Some(layer) => {
+                let mut new_map = map.clone();
+                new_map.walkable_layer = layer;
+                new_map
+            }
+            None => map.clone(),
+        }
+    }
+}
+
+impl WaveFunctionCollapse {
+    /// Learn 3x3 patterns from the sample
+    pub fn new(sample: &WalkableLayer) -> Box<WaveFunctionCollapse> {
+        let mut wfc = WaveFunctionCollapse {
+            sample: sample.clone(),
+            pattern_size: 3,
+            patterns: Vec::new(),
+            weights: Vec::new(),
+            propagator: Vec::new(),
+            periodic: false,
+            wall_border: true,
+            fixed_tiles: Vec::new(),
+            max_restarts: 10,
+            max_backtrack_depth: 100,
+        };
+        wfc.learn();
+        Box::new(wfc)
+    }
+
+    /// Learn N×N patterns instead of the default 3x3
+    pub fn with_pattern_size(mut self: Box<Self>, pattern_size: u32) -> Box<WaveFunctionCollapse> {
+        self.pattern_size = u32::max(pattern_size, 1);
+        self.learn();
+        self
+    }
+
+    /// Wrap the output around the map edges, so it can be tiled
+    pub fn with_periodic(mut self: Box<Self>, periodic: bool) -> Box<WaveFunctionCollapse> {
+        self.periodic = periodic;
+        self
+    }
+
+    /// Force walls on the map border (enabled by default)
+    pub fn with_wall_border(mut self: Box<Self>, wall_border: bool) -> Box<WaveFunctionCollapse> {
+        self.wall_border = wall_border;
+        self
+    }
+
+    /// Pre-set the given tile
+    pub fn with_fixed_tile(
+        mut self: Box<Self>,
+        pos: UVec2,
+        walkable: bool,
+    ) -> Box<WaveFunctionCollapse> {
+        self.fixed_tiles.push((pos, walkable));
+        self
+    }
+
+    /// Number of restarts after the backtracking fails
+    pub fn with_max_restarts(mut self: Box<Self>, max_restarts: u32) -> Box<WaveFunctionCollapse> {
+        self.max_restarts = max_restarts;
+        self
+    }
+
+    /// Number of patterns learned from the sample
+    pub fn num_patterns(&self) -> usize {
+        self.patterns.len()
+    }
+
+    /// Generate new layer. Returns None if the contradiction can't be resolved.
+    pub fn generate(&self, rng: &mut Rng, width: u32, height: u32) -> Option<WalkableLayer> {
+        let n = self.pattern_size;
+        if self.patterns.is_empty() || width < n || height < n {
+            return None;
+        }
+        let grid = Grid::new(width, height, n, self.periodic);
+        let (wave, entropy) = self.initial_wave(&grid)?;
+
+        for _ in 0..=self.max_restarts {
+            if let Some(observed) = self.run(rng, &grid, wave.clone(), entropy.clone()) {
+                return Some(self.render(&grid, &observed));
+            }
+        }
+        None
+    }
+
+    /// Extract all patterns from the sample and find which patterns can overlap
+    fn learn(&mut self) {
+        let sample = &self.sample;
+        let n = self.pattern_size;
+        self.patterns.clear();
+        self.weights.clear();
+        if sample.width >= n && sample.height >= n {
+            for y in 0..=sample.height - n {
+                for x in 0..=sample.width - n {
+                    let pattern: Vec<bool> = (0..n * n)
+                        .map(|i| sample.is_walkable(x + i % n, y + i / n))
+                        .collect();
+                    match self.patterns.iter().position(|p| *p == pattern) {
+                        Some(idx) => self.weights[idx] += 1.0,
+                        None => {
+                            self.patterns.push(pattern);
+                            self.weights.push(1.0);
+                        }
+                    }
+                }
+            }
+        }
+
+        let words = self.words();
+        self.propagator = DIRECTIONS
+            .iter()
+            .map(|&(dx, dy)| {
+                self.patterns
+                    .iter()
+                    .map(|p| {
+                        let mut bits = vec![0u64; words];
+                        for (q_idx, q) in self.patterns.iter().enumerate() {
+                            if agrees(p, q, dx, dy, n as i64) {
+                                bits[q_idx / 64] |= 1 << (q_idx % 64);
+                            }
+                        }
+                        bits
+                    })
+                    .collect()
+            })
+            .collect();
+    }
+
+    fn words(&self) -> usize {
+        self.patterns.len().div_ceil(64)
+    }
+
+    /// Wave with border and fixed tiles constraints applied
+    fn initial_wave(&self, grid: &Grid) -> Option<(Vec<u64>, Vec<f32>)> {
+        let words = self.words();
+        let mut full = vec![0u64; words];
+        for p in 0..self.patterns.len() {
+            full[p / 64] |= 1 << (p % 64);
+        }
+        let mut wave: Vec<u64> = (0..grid.cells()).flat_map(|_| full.clone()).collect();
+
+        let mut constraints = self.fixed_tiles.clone();
+        if self.wall_border {
+            for x in 0..grid.width {
+                constraints.push((UVec2::new(x, 0), false));
+                constraints.push((UVec2::new(x, grid.height - 1), false));
+            }
+            for y in 0..grid.height {
+                constraints.push((UVec2::new(0, y), false));
+                constraints.push((UVec2::new(grid.width - 1, y), false));
+            }
+        }
+
+        for (pos, walkable) in constraints {
+            for (cell, offset) in grid.covering_cells(pos) {
+                for (p, pattern) in self.patterns.iter().enumerate() {
+                    if pattern[offset as usize] != walkable {
+                        wave[cell * words + p / 64] &= !(1 << (p % 64));
+                    }
+                }
+            }
+        }
+
+        let mut entropy = vec![0.0; grid.cells()];
+        for (cell, e) in entropy.iter_mut().enumerate() {
+            *e = self.entropy(&wave[cell * words..(cell + 1) * words]);
+        }
+        let all_cells = (0..grid.cells()).collect();
+        let mut trail = Trail::default();
+        if self.propagate(grid, &mut wave, &mut entropy, &mut trail, all_cells) {
+            Some((wave, entropy))
+        } else {
+            None
+        }
+    }
+
+    /// Single attempt. Returns chosen pattern for each cell.
+    fn run(
+        &self,
+        rng: &mut Rng,
+        grid: &Grid,
+        mut wave: Vec<u64>,
+        mut entropy: Vec<f32>,
+    ) -> Option<Vec<usize>> {
+        let words = self.words();
+        let mut decisions: VecDeque<Decision> = VecDeque::new();
+        let mut trail = Trail::default();
+        let mut backtracks = 0;
+        // Small noise to break ties between the cells with the same entropy
+        let noise: Vec<f32> = (0..grid.cells()).map(|_| rng.f32() * 1e-4).collect();
+        let candidate = |cell: usize, entropy: f32| Candidate {
+            priority: entropy + noise[cell],
+            entropy,
+            cell,
+        };
+        let mut heap: BinaryHeap<Candidate> = (0..grid.cells())
+            .filter(|&c| count_bits(&wave[c * words..(c + 1) * words]) > 1)
+            .map(|c| candidate(c, entropy[c]))
+            .collect();
+
+        loop {
+            // Cell with the lowest entropy. Outdated heap entries are skipped.
+            let mut best = None;
+            while let Some(c) = heap.pop() {
+                let bits = &wave[c.cell * words..(c.cell + 1) * words];
+                if c.entropy == entropy[c.cell] && count_bits(bits) > 1 {
+                    best = Some(c.cell);
+                    break;
+                }
+            }
+            let Some(cell) = best else {
+                return Some(
+                    (0..grid.cells())
+                        .map(|c| first_bit(&wave[c * words..(c + 1) * words]))
+                        .collect(),
+                );
+            };
+
+            // Collapse the cell to the random pattern
+            let pattern = self.choose_pattern(rng, &wave[cell * words..(cell + 1) * words]);
+            if decisions.len() >= self.max_backtrack_depth {
+                decisions.pop_front();
+                let oldest = decisions.front().map_or(trail.len(), |d| d.trail_len);
+                trail.forget(oldest);
+            }
+            decisions.push_back(Decision {
+                trail_len: trail.len(),
+                cell,
+                pattern,
+            });
+            for w in 0..words {
+                let bits = if w == pattern / 64 {
+                    1 << (pattern % 64)
+                } else {
+                    0
+                };
+                self.set_word(&mut wave, &mut entropy, &mut trail, cell * words + w, bits);
+            }
+            entropy[cell] = 0.0;
+            let mut changed_from = trail.len();
+            let mut ok = self.propagate(grid, &mut wave, &mut entropy, &mut trail, vec![cell]);
+
+            // Backtrack: undo the changes and forbid the pattern which led to the contradiction
+            while !ok {
+                let decision = decisions.pop_back()?;
+                backtracks += 1;
+                if backtracks > grid.cells() {
+                    return None;
+                }
+                while trail.len() > decision.trail_len {
+                    let (word, bits, e) = trail.changes.pop_back()?;
+                    wave[word] = bits;
+                    entropy[word / words] = e;
+                    heap.push(candidate(word / words, e));
+                }
+                let word = decision.cell * words + decision.pattern / 64;
+                let bits = wave[word] & !(1 << (decision.pattern % 64));
+                self.set_word(&mut wave, &mut entropy, &mut trail, word, bits);
+                let cell_bits = &wave[decision.cell * words..(decision.cell + 1) * words];
+                if count_bits(cell_bits) == 0 {
+                    continue;
+                }
+                entropy[decision.cell] = self.entropy(cell_bits);
+                changed_from = decision.trail_len;
+                ok = self.propagate(
+                    grid,
+                    &mut wave,
+                    &mut entropy,
+                    &mut trail,
+                    vec![decision.cell],
+                );
+            }
+
+            // Cells with the new entropy get the new heap entries
+            for &(word, _, _) in trail.since(changed_from) {
+                let c = word / words;
+                if count_bits(&wave[c * words..(c + 1) * words]) > 1 {
+                    heap.push(candidate(c, entropy[c]));
+                }
+            }
+        }
+    }
+
+    /// Change the wave word and record its previous value in the trail
+    fn set_word(
+        &self,
+        wave: &mut [u64],
+        entropy: &mut [f32],
+        trail: &mut Trail,
+        word: usize,
+        bits: u64,
+    ) {
+        if wave[word] != bits {
+            trail.record(word, wave[word], entropy[word / self.words()]);
+            wave[word] = bits;
+        }
+    }
+
+    /// Remove patterns which are not compatible with the neighbors.
+    /// Returns false on contradiction.
+    fn propagate(
+        &self,
+        grid: &Grid,
+        wave: &mut [u64],
+        entropy: &mut [f32],
+        trail: &mut Trail,
+        mut stack: Vec<usize>,
+    ) -> bool {
+        let words = self.words();
+        let mut allowed = vec![0u64; words];
+        while let Some(cell) = stack.pop() {
+            for (d, propagator) in self.propagator.iter().enumerate() {
+                let Some(neighbor) = grid.neighbor(cell, d) else {
+                    continue;
+                };
+                allowed.fill(0);
+                for p in bits(&wave[cell * words..(cell + 1) * words]) {
+                    for (a, b) in allowed.iter_mut().zip(propagator[p].iter()) {
+                        *a |= b;
+                    }
+                }
+                let mut changed = false;
+                for (w, a) in allowed.iter().enumerate() {
+                    let word = neighbor * words + w;
+                    let new = wave[word] & a;
+                    changed |= new != wave[word];
+                    self.set_word(wave, entropy, trail, word, new);
+                }
+                if changed {
+                    let neighbor_bits = &wave[neighbor * words..(neighbor + 1) * words];
+                    if count_bits(neighbor_bits) == 0 {
+                        return false;
+                    }
+                    entropy[neighbor] = self.entropy(neighbor_bits);
+                    stack.push(neighbor);
+                }
+            }
+        }
+        true
+    }
+
+    fn entropy(&self, cell_bits: &[u64]) -> f32 {
+        let (sum, sum_log) = bits(cell_bits).fold((0.0, 0.0), |(s, sl), p| {
+            let w = self.weights[p];
+            (s + w, sl + w * w.ln())
+        });
+        if sum == 0.0 {
+            0.0
+        } else {
+            sum.ln() - sum_log / sum
+        }
+    }
+
+    fn choose_pattern(&self, rng: &mut Rng, cell_bits: &[u64]) -> usize {
+        let total: f32 = bits(cell_bits).map(|p| self.weights[p]).sum();
+        let mut r = rng.f32() * total;
+        let mut chosen = first_bit(cell_bits);
+        for p in bits(cell_bits) {
+            chosen = p;
+            r -= self.weights[p];
+            if r <= 0.0 {
+                break;
+            }
+        }
+        chosen
+    }
+
+    fn render(&self, grid: &Grid, observed: &[usize]) -> WalkableLayer {
+        let mut layer = WalkableLayer::new(grid.width, grid.height);
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                let (cell, offset) = grid.cell_for_tile(x, y);
+                layer.set_walkable(x, y, self.patterns[observed[cell]][offset as usize]);
+            }
+        }
+        layer
+    }
+}
+
+/// Positions of the patterns in the output
+struct Grid {
+    width: u32,
+    height: u32,
+    pattern_size: u32,
+    periodic: bool,
+    cols: u32,
+    rows: u32,
+}
+
+impl Grid {
+    fn new(width: u32, height: u32, pattern_size: u32, periodic: bool) -> Grid {
+        let (cols, rows) = if periodic {
+            (width, height)
+        } else {
+            (width - pattern_size + 1, height - pattern_size + 1)
+        };
+        Grid {
+            width,
+            height,
+            pattern_size,
+            periodic,
+            cols,
+            rows,
+        }
+    }
+
+    fn cells(&self) -> usize {
+        (self.cols * self.rows) as usize
+    }
+
+    fn neighbor(&self, cell: usize, direction: usize) -> Option<usize> {
+        let (dx, dy) = DIRECTIONS[direction];
+        let x = (cell as u32 % self.cols) as i64 + dx;
+        let y = (cell as u32 / self.cols) as i64 + dy;
+        let (cols, rows) = (self.cols as i64, self.rows as i64);
+        if self.periodic {
+            Some((y.rem_euclid(rows) * cols + x.rem_euclid(cols)) as usize)
+        } else if x < 0 || y < 0 || x >= cols || y >= rows {
+            None
+        } else {
+            Some((y * cols + x) as usize)
+        }
+    }
+
+    /// All cells whose pattern covers the given tile, with the tile offset inside the pattern
+    fn covering_cells(&self, pos: UVec2) -> Vec<(usize, u32)> {
+        let n = self.pattern_size as i64;
+        let (cols, rows) = (self.cols as i64, self.rows as i64);
+        let mut cells = Vec::new();
+        for dy in 0..n {
+            for dx in 0..n {
+                let (mut x, mut y) = (pos.x as i64 - dx, pos.y as i64 - dy);
+                if self.periodic {
+                    x = x.rem_euclid(cols);
+                    y = y.rem_euclid(rows);
+                } else if x < 0 || y < 0 || x >= cols || y >= rows {
+                    continue;
+                }
+                cells.push(((y * cols + x) as usize, (dy * n + dx) as u32));
+            }
+        }
+        cells
+    }
+
+    /// Cell which defines the given tile, with the tile offset inside the pattern
+    fn cell_for_tile(&self, x: u32, y: u32) -> (usize, u32) {
+        if self.periodic {
+            ((y * self.cols + x) as usize, 0)
+        } else {
+            let cx = u32::min(x, self.cols - 1);
+            let cy = u32::min(y, self.rows - 1);
+            (
+                (cy * self.cols + cx) as usize,
+                (y - cy) * self.pattern_size + x - cx,
+            )
+        }
+    }
+}
+
+/// Check if pattern q placed at (dx, dy) relative to pattern p matches in the overlapping area
+fn agrees(p: &[bool], q: &[bool], dx: i64, dy: i64, n: i64) -> bool {
+    let xmin = i64::max(0, dx);
+    let xmax = i64::min(n, n + dx);
+    let ymin = i64::max(0, dy);
+    let ymax = i64::min(n, n + dy);
+    (ymin..ymax).all(|y| {
+        (xmin..xmax).all(|x| p[(y * n + x) as usize] == q[((y - dy) * n + x - dx) as usize])
+    })
+}
+
+fn count_bits(cell_bits: &[u64]) -> u32 {
+    cell_bits.iter().map(|w| w.count_ones()).sum()
+}
+
+fn first_bit(cell_bits: &[u64]) -> usize {
+    bits(cell_bits).next().unwrap_or(0)
+}
+
+/// Iterate over indexes of the set bits
+fn bits(cell_bits: &[u64]) -> impl Iterator<Item = usize> + '_ {
+    cell_bits.iter().enumerate().flat_map(|(i, &w)| {
+        (0..64)
+            .filter(move |b| w & (1 << b) != 0)
+            .map(move |b| i * 64 + b)
+    })
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample() -> WalkableLayer {
+        WalkableLayer::from_string(
+            "
+            ############
+            #    #     #
+            #    #  #  #
+            ##  ##  #  #
+            #       #  #
+            #   ########
+            #          #
+            ############
+            ",
+        )
+    }
+
+    /// Every N×N window of the output must exist in the sample
+    fn check_windows(wfc: &WaveFunctionCollapse, layer: &WalkableLayer, periodic: bool) {
+        let n = wfc.pattern_size;
+        let (w, h) = (layer.width, layer.height);
+        let (max_x, max_y) = if periodic {
+            (w, h)
+        } else {
+            (w - n + 1, h - n + 1)
+        };
+        for y in 0..max_y {
+            for x in 0..max_x {
+                let window: Vec<bool> = (0..n * n)
+                    .map(|i| layer.is_walkable((x + i % n) % w, (y + i / n) % h))
+                    .collect();
+                assert!(
+                    wfc.patterns.contains(&window),
+                    "Unknown pattern at {x}, {y}"
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_generate() {
+        let wfc = WaveFunctionCollapse::new(&sample());
+        let mut rng = Rng::with_seed(100);
+        let layer = wfc.generate(&mut rng, 40, 30).unwrap();
+        check_windows(&wfc, &layer, false);
+        assert!(layer.tiles.iter().any(|&t| t));
+        for x in 0..40 {
+            assert!(!layer.is_walkable(x, 0));
+            assert!(!layer.is_walkable(x, 29));
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let wfc = WaveFunctionCollapse::new(&sample());
+        let layer1 = wfc.generate(&mut Rng::with_seed(7), 30, 20);
+        let layer2 = wfc.generate(&mut Rng::with_seed(7), 30, 20);
+        assert_eq!(layer1, layer2);
+    }
+
+    #[test]
+    fn test_periodic() {
+        let wfc = WaveFunctionCollapse::new(&sample())
+            .with_periodic(true)
+            .with_wall_border(false);
+        let mut rng = Rng::with_seed(100);
+        let layer = wfc.generate(&mut rng, 24, 16).unwrap();
+        check_windows(&wfc, &layer, true);
+    }
+
+    #[test]
+    fn test_fixed_tile() {
+        let wfc = WaveFunctionCollapse::new(&sample()).with_fixed_tile(UVec2::new(10, 10), true);
+        let mut rng = Rng::with_seed(100);
+        let map = wfc.modify_map(&mut rng, &CaveMap::new(30, 20));
+        assert!(map.is_walkable(10, 10));
+    }
+
+    #[test]
+    fn test_contradiction() {
+        // Sample without any walls can't create the wall border
+        let sample = WalkableLayer::from_string(
+            "
+            #
+            ",
+        );
+        let mut open = WalkableLayer::new(5, 5);
+        open.tiles.fill(true);
+        let wfc = WaveFunctionCollapse::new(&open);
+        let mut rng = Rng::with_seed(100);
+        assert!(wfc.generate(&mut rng, 10, 10).is_none());
+        let wfc = WaveFunctionCollapse::new(&sample);
+        assert_eq!(wfc.num_patterns(), 0);
+    }
+}
@@ -1,7 +1,8 @@
 //! Support function for 2D/3D geometry
 //!
 
-use glam::UVec2;
+use fastrand::Rng;
+use glam::{UVec2, Vec2};
 
 /// Rectangle region on the map
 #[derive(PartialEq, Copy, Clone, Debug)]
@@ -32,20 +33,148 @@ impl Rect {
     }
 
     pub fn width(&self) -> u32 {
-        if self.x2 >= self.x1 {
-            self.x2 - self.x1
-        } else {
-            self.x1 - self.x2
-        }
+        self.x2.abs_diff(self.x1)
     }
 
     pub fn height(&self) -> u32 {
-        if self.y2 >= self.y1 {
-            self.y2 - self.y1
-        } else {
-            self.y1 - self.y2
+        self.y2.abs_diff(self.y1)
+    }
+}
+
+/// Method of measuring distance between 2 points
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DistanceMetric {
+    Euclidean,
+    /// Sum of the distances along each axis
+    Manhattan,
+    /// Maximum of the distances along each axis
+    Chebyshev,
+}
+
+impl DistanceMetric {
+    pub fn distance(&self, a: Vec2, b: Vec2) -> f32 {
+        let d = (a - b).abs();
+        match self {
+            DistanceMetric::Euclidean => d.length(),
+            DistanceMetric::Manhattan => d.x + d.y,
+            DistanceMetric::Chebyshev => d.x.max(d.y),
+        }
+    }
+}
+
+/// Points on the straight line between 2 points (Bresenham's algorithm).
+/// Both end points are included.
+pub fn line(from: UVec2, to: UVec2) -> Vec<UVec2> {
+    let (mut x, mut y) = (from.x as i64, from.y as i64);
+    let (x2, y2) = (to.x as i64, to.y as i64);
+    let dx = (x2 - x).abs();
+    let dy = -(y2 - y).abs();
+    let sx = if x < x2 { 1 } else { -1 };
+    let sy = if y < y2 { 1 } else { -1 };
+    let mut err = dx + dy;
+    let mut points = vec![from];
+
+    while x != x2 || y != y2 {
+        let e2 = 2 * err;
+        if e2 >= dy {
+            err += dy;
+            x += sx;
         }
+        if e2 <= dx {
+            err += dx;
+            y += sy;
+        }
+        points.push(UVec2::new(x as u32, y as u32));
+    }
+
+    points
+}
+
+/// Path where each diagonal step gets the corner tile, so it can be walked in 4 directions
+pub(crate) fn four_connected(path: &[UVec2]) -> Vec<UVec2> {
+    let mut connected: Vec<UVec2> = Vec::new();
+    for &p in path {
+        if let Some(&last) = connected.last() {
+            if last.x != p.x && last.y != p.y {
+                connected.push(UVec2::new(p.x, last.y));
+            }
+        }
+        connected.push(p);
     }
+    connected
+}
+
+/// Random points, where no 2 points are closer then `radius` (Bridson's algorithm).
+/// Candidate point is accepted with the probability given by the `weight` function (0..1),
+/// so more points are placed in the areas with higher weight.
+pub fn poisson_disk(
+    rng: &mut Rng,
+    width: u32,
+    height: u32,
+    radius: f32,
+    weight: impl Fn(UVec2) -> f32,
+) -> Vec<UVec2> {
+    const CANDIDATES: u32 = 30;
+    let mut points: Vec<Vec2> = Vec::new();
+    if width == 0 || height == 0 || radius <= 0.0 {
+        return Vec::new();
+    }
+    // Each grid cell can contain at most 1 point
+    let cell_size = radius / std::f32::consts::SQRT_2;
+    let cols = (width as f32 / cell_size).ceil() as i64;
+    let rows = (height as f32 / cell_size).ceil() as i64;
+    let mut grid: Vec<Option<usize>> = vec![None; (cols * rows) as usize];
+    let cell = |p: Vec2| ((p.x / cell_size) as i64, (p.y / cell_size) as i64);
+    let mut active: Vec<usize> = Vec::new();
+
+    let mut try_add = |p: Vec2, points: &mut Vec<Vec2>, active: &mut Vec<usize>, rng: &mut Rng| {
+        if p.x < 0.0 || p.y < 0.0 || p.x >= width as f32 || p.y >= height as f32 {
+            return false;
+        }
+        let (cx, cy) = cell(p);
+        for y in i64::max(cy - 2, 0)..=i64::min(cy + 2, rows - 1) {
+            for x in i64::max(cx - 2, 0)..=i64::min(cx + 2, cols - 1) {
+                if let Some(other) = grid[(y * cols + x) as usize] {
+                    if points[other].distance(p) < radius {
+                        return false;
+                    }
+                }
+            }
+        }
+        if rng.f32() >= weight(p.as_uvec2()) {
+            return false;
+        }
+        grid[(cy * cols + cx) as usize] = Some(points.len());
+        active.push(points.len());
+        points.push(p);
+        true
+    };
+
+    loop {
+        // Start new cluster at random position. Areas with low weight can separate clusters
+        let started = (0..CANDIDATES).any(|_| {
+            let p = Vec2::new(rng.f32() * width as f32, rng.f32() * height as f32);
+            try_add(p, &mut points, &mut active, rng)
+        });
+        if !started {
+            break;
+        }
+        while !active.is_empty() {
+            let i = rng.usize(0..active.len());
+            let center = points[active[i]];
+            let found = (0..CANDIDATES).any(|_| {
+                let angle = rng.f32() * std::f32::consts::TAU;
+                let distance = radius * (1.0 + rng.f32());
+                let p = center + Vec2::from_angle(angle) * distance;
+                try_add(p, &mut points, &mut active, rng)
+            });
+            if !found {
+                active.swap_remove(i);
+            }
+        }
+    }
+
+    points.iter().map(|p| p.as_uvec2()).collect()
 }
 
 /// ------------------------------------------------------------------------------------------------
@@ -68,4 +197,54 @@ mod tests {
         assert_eq!(rect1.width(), 40);
         assert_eq!(rect1.height(), 30);
     }
+
+    #[test]
+    fn test_distance() {
+        let a = Vec2::new(1.0, 1.0);
+        let b = Vec2::new(4.0, 5.0);
+        assert_eq!(DistanceMetric::Euclidean.distance(a, b), 5.0);
+        assert_eq!(DistanceMetric::Manhattan.distance(a, b), 7.0);
+        assert_eq!(DistanceMetric::Chebyshev.distance(a, b), 4.0);
+    }
+
+    #[test]
+    fn test_line() {
+        let points = line(UVec2::new(1, 1), UVec2::new(5, 3));
+        assert_eq!(points.len(), 5);
+        assert_eq!(points[0], UVec2::new(1, 1));
+        assert_eq!(points[4], UVec2::new(5, 3));
+        assert_eq!(
+            line(UVec2::new(3, 4), UVec2::new(3, 4)),
+            vec![UVec2::new(3, 4)]
+        );
+        let points = line(UVec2::new(4, 0), UVec2::new(0, 0));
+        assert_eq!(points.len(), 5);
+    }
+
+    #[test]
+    fn test_four_connected() {
+        let path = four_connected(&line(UVec2::new(1, 1), UVec2::new(4, 3)));
+        assert_eq!(path[0], UVec2::new(1, 1));
+        assert_eq!(path[path.len() - 1], UVec2::new(4, 3));
+        for w in path.windows(2) {
+            assert_eq!(w[0].x.abs_diff(w[1].x) + w[0].y.abs_diff(w[1].y), 1);
+        }
+    }
+
+    #[test]
+    fn test_poisson_disk() {
+        let mut rng = Rng::with_seed(100);
+        let points = poisson_disk(&mut rng, 80, 50, 8.0, |_| 1.0);
+        assert!(points.len() > 20);
+        for (i, a) in points.iter().enumerate() {
+            for b in points.iter().skip(i + 1) {
+                // Rounding to tiles can make points a bit closer
+                assert!(a.as_vec2().distance(b.as_vec2()) >= 8.0 - 1.5);
+            }
+        }
+        // Points only where the weight is positive
+        let points = poisson_disk(&mut rng, 80, 50, 5.0, |p| if p.x < 40 { 1.0 } else { 0.0 });
+        assert!(!points.is_empty());
+        assert!(points.iter().all(|p| p.x < 40));
+    }
 }
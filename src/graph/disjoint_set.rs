@@ -0,0 +1,60 @@
+//! Union-find structure
+//!
+
+pub struct DisjointSet {
+    parents: Vec<usize>,
+}
+
+impl DisjointSet {
+    pub fn new(size: usize) -> DisjointSet {
+        DisjointSet {
+            parents: (0..size).collect(),
+        }
+    }
+
+    pub fn find(&mut self, idx: usize) -> usize {
+        let mut root = idx;
+        while self.parents[root] != root {
+            root = self.parents[root];
+        }
+        // Path compression
+        let mut current = idx;
+        while self.parents[current] != root {
+            let next = self.parents[current];
+            self.parents[current] = root;
+            current = next;
+        }
+        root
+    }
+
+    /// Join sets. Returns false if both elements were already in the same set
+    pub fn union(&mut self, a: usize, b: usize) -> bool {
+        let root_a = self.find(a);
+        let root_b = self.find(b);
+        if root_a == root_b {
+            false
+        } else {
+            self.parents[root_b] = root_a;
+            true
+        }
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_union() {
+        let mut sets = DisjointSet::new(4);
+        assert!(sets.union(0, 1));
+        assert!(sets.union(2, 3));
+        assert!(!sets.union(1, 0));
+        assert_ne!(sets.find(0), sets.find(3));
+        assert!(sets.union(1, 3));
+        assert_eq!(sets.find(0), sets.find(2));
+    }
+}
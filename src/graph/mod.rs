@@ -0,0 +1,3 @@
+mod disjoint_set;
+
+pub use disjoint_set::DisjointSet;
@@ -12,6 +12,7 @@ pub struct WalkableLayer {
     pub tiles: Vec<bool>,
 }
 
+#[derive(Default, Debug, Clone, PartialEq)]
 pub struct DataLayer<T> {
     pub width: u32,
     pub height: u32,
@@ -131,6 +132,23 @@ impl<T: Clone> DataLayer<T> {
             walkables: vec![default; (width * height) as usize],
         }
     }
+
+    pub fn xy_idx(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+
+    /// Get value at the given location
+    pub fn get(&self, x: u32, y: u32) -> T {
+        self.walkables[self.xy_idx(x, y)].clone()
+    }
+
+    /// Modify value at the given location
+    pub fn set(&mut self, x: u32, y: u32, value: T) {
+        if x < self.width && y < self.height {
+            let idx = self.xy_idx(x, y);
+            self.walkables[idx] = value;
+        }
+    }
 }
 
 impl fmt::Display for WalkableLayer {
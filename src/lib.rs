@@ -28,9 +28,13 @@ pub mod cave;
 pub mod geometry;
 pub mod layer;
 pub mod metric;
+pub mod noise;
+pub mod platformer;
 pub mod poi;
 pub mod rooms;
+pub mod world;
 
+pub(crate) mod graph;
 pub(crate) mod path;
 // use std::time::{SystemTime, UNIX_EPOCH};
 
@@ -48,6 +52,7 @@ pub trait MapFilter {
 pub struct MapBuilder {
     width: u32,
     height: u32,
+    initial_map: Option<CaveMap>,
     modifiers: Vec<Box<dyn MapFilter>>,
 }
 
@@ -57,6 +62,18 @@ impl MapBuilder {
         MapBuilder {
             width,
             height,
+            initial_map: None,
+            modifiers: Vec::new(),
+        }
+    }
+
+    /// Create Map Builder which will apply filters to the given map
+    /// (e.g. rooms created with one of the [rooms] generators)
+    pub fn from_map(map: CaveMap) -> MapBuilder {
+        MapBuilder {
+            width: map.width,
+            height: map.height,
+            initial_map: Some(map),
             modifiers: Vec::new(),
         }
     }
@@ -74,7 +91,10 @@ impl MapBuilder {
 
     /// Build map using provided random number generator
     pub fn build_with_rng(&mut self, rng: &mut Rng) -> CaveMap {
-        let mut map = CaveMap::new(self.width, self.height);
+        let mut map = match &self.initial_map {
+            Some(map) => map.clone(),
+            None => CaveMap::new(self.width, self.height),
+        };
 
         // Build additional layers in turn
         for modifier in self.modifiers.iter() {
@@ -0,0 +1,309 @@
+//! Coherent noise functions.
+//!
+//! Unlike white noise, coherent noise changes smoothly between neighbouring tiles,
+//! so it can be used to create shapes of caves, islands, continents etc.
+//!
+//! Example usage:
+//! ```
+//! use mapgen::noise::{Fbm, NoiseKind};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let fbm = Fbm::new(NoiseKind::Perlin).with_octaves(4);
+//! let layer = fbm.generate(&mut rng, 80, 50);
+//!
+//! assert_eq!(layer.width, 80);
+//! assert!(layer.walkables.iter().all(|v| (0.0..=1.0).contains(v)));
+//! ```
+//!
+
+use std::f32::consts::FRAC_1_SQRT_2 as D;
+
+use fastrand::Rng;
+
+use crate::layer::DataLayer;
+
+/// Offset added to the sample position for each octave, so octaves are not correlated
+const OCTAVE_OFFSET: f32 = 17.31;
+
+const GRADIENTS: [(f32, f32); 8] = [
+    (1.0, 0.0),
+    (-1.0, 0.0),
+    (0.0, 1.0),
+    (0.0, -1.0),
+    (D, D),
+    (-D, D),
+    (D, -D),
+    (-D, -D),
+];
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NoiseKind {
+    /// Interpolated random values at the lattice points
+    Value,
+    /// Classic gradient noise
+    Perlin,
+    /// Gradient noise on the triangular grid. Has less directional artifacts than Perlin
+    Simplex,
+}
+
+/// Single octave noise function seeded with the random permutation table.
+/// Returns values in the range [-1, 1].
+pub struct NoiseSource {
+    kind: NoiseKind,
+    perm: Vec<usize>,
+}
+
+impl NoiseSource {
+    pub fn new(kind: NoiseKind, rng: &mut Rng) -> NoiseSource {
+        let mut perm: Vec<usize> = (0..256).collect();
+        rng.shuffle(&mut perm);
+        perm.extend_from_within(..);
+        NoiseSource { kind, perm }
+    }
+
+    /// Get noise value at the given point
+    pub fn get(&self, x: f32, y: f32) -> f32 {
+        let v = match self.kind {
+            NoiseKind::Value => self.value(x, y),
+            NoiseKind::Perlin => self.perlin(x, y),
+            NoiseKind::Simplex => self.simplex(x, y),
+        };
+        v.clamp(-1.0, 1.0)
+    }
+
+    fn hash(&self, x: i32, y: i32) -> usize {
+        self.perm[self.perm[(x & 255) as usize] + (y & 255) as usize]
+    }
+
+    fn gradient_dot(&self, ix: i32, iy: i32, dx: f32, dy: f32) -> f32 {
+        let (gx, gy) = GRADIENTS[self.hash(ix, iy) % GRADIENTS.len()];
+        gx * dx + gy * dy
+    }
+
+    fn value(&self, x: f32, y: f32) -> f32 {
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let (ix, iy) = (x0 as i32, y0 as i32);
+        let u = fade(x - x0);
+        let v = fade(y - y0);
+        let corner = |dx: i32, dy: i32| self.hash(ix + dx, iy + dy) as f32 / 127.5 - 1.0;
+        let top = lerp(corner(0, 0), corner(1, 0), u);
+        let bottom = lerp(corner(0, 1), corner(1, 1), u);
+        lerp(top, bottom, v)
+    }
+
+    fn perlin(&self, x: f32, y: f32) -> f32 {
+        let x0 = x.floor();
+        let y0 = y.floor();
+        let (ix, iy) = (x0 as i32, y0 as i32);
+        let (fx, fy) = (x - x0, y - y0);
+        let u = fade(fx);
+        let v = fade(fy);
+        let top = lerp(
+            self.gradient_dot(ix, iy, fx, fy),
+            self.gradient_dot(ix + 1, iy, fx - 1.0, fy),
+            u,
+        );
+        let bottom = lerp(
+            self.gradient_dot(ix, iy + 1, fx, fy - 1.0),
+            self.gradient_dot(ix + 1, iy + 1, fx - 1.0, fy - 1.0),
+            u,
+        );
+        // Scale from [-sqrt(0.5), sqrt(0.5)] to [-1, 1]
+        lerp(top, bottom, v) * std::f32::consts::SQRT_2
+    }
+
+    /// 2D simplex noise based on Stefan Gustavson's paper "Simplex noise demystified"
+    fn simplex(&self, x: f32, y: f32) -> f32 {
+        let f2 = 0.5 * (3.0f32.sqrt() - 1.0);
+        let g2 = (3.0 - 3.0f32.sqrt()) / 6.0;
+
+        // Skew the input space to find the simplex cell
+        let s = (x + y) * f2;
+        let i = (x + s).floor();
+        let j = (y + s).floor();
+        let t = (i + j) * g2;
+        let x0 = x - (i - t);
+        let y0 = y - (j - t);
+
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let x1 = x0 - i1 as f32 + g2;
+        let y1 = y0 - j1 as f32 + g2;
+        let x2 = x0 - 1.0 + 2.0 * g2;
+        let y2 = y0 - 1.0 + 2.0 * g2;
+
+        let (ii, jj) = (i as i32, j as i32);
+        let corner = |dx: f32, dy: f32, ci: i32, cj: i32| {
+            let t = 0.5 - dx * dx - dy * dy;
+            if t < 0.0 {
+                0.0
+            } else {
+                t * t * t * t * self.gradient_dot(ci, cj, dx, dy)
+            }
+        };
+
+        let n = corner(x0, y0, ii, jj)
+            + corner(x1, y1, ii + i1, jj + j1)
+            + corner(x2, y2, ii + 1, jj + 1);
+        70.0 * n
+    }
+}
+
+/// Fractal Brownian motion: sum of several octaves of the noise,
+/// each with higher frequency and lower amplitude.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Fbm {
+    pub kind: NoiseKind,
+    /// Frequency of the first octave (in 1/tiles)
+    pub frequency: f32,
+    pub octaves: u32,
+    /// Amplitude multiplier between octaves
+    pub persistence: f32,
+    /// Frequency multiplier between octaves
+    pub lacunarity: f32,
+    /// Domain warping strength (in tiles). 0 disables warping
+    pub warp: f32,
+}
+
+impl Fbm {
+    pub fn new(kind: NoiseKind) -> Fbm {
+        Fbm {
+            kind,
+            frequency: 0.05,
+            octaves: 1,
+            persistence: 0.5,
+            lacunarity: 2.0,
+            warp: 0.0,
+        }
+    }
+
+    pub fn with_frequency(mut self, frequency: f32) -> Fbm {
+        self.frequency = frequency;
+        self
+    }
+
+    pub fn with_octaves(mut self, octaves: u32) -> Fbm {
+        self.octaves = u32::max(octaves, 1);
+        self
+    }
+
+    pub fn with_persistence(mut self, persistence: f32) -> Fbm {
+        self.persistence = persistence;
+        self
+    }
+
+    pub fn with_lacunarity(mut self, lacunarity: f32) -> Fbm {
+        self.lacunarity = lacunarity;
+        self
+    }
+
+    pub fn with_warp(mut self, warp: f32) -> Fbm {
+        self.warp = warp;
+        self
+    }
+
+    /// Sample fBm of the given source at the tile position. Returns values in the range [-1, 1]
+    pub fn sample(&self, source: &NoiseSource, x: f32, y: f32) -> f32 {
+        let mut amplitude = 1.0;
+        let mut frequency = self.frequency;
+        let mut sum = 0.0;
+        let mut norm = 0.0;
+        for octave in 0..self.octaves {
+            let offset = octave as f32 * OCTAVE_OFFSET;
+            sum += amplitude * source.get(x * frequency + offset, y * frequency + offset);
+            norm += amplitude;
+            amplitude *= self.persistence;
+            frequency *= self.lacunarity;
+        }
+        if norm > 0.0 {
+            sum / norm
+        } else {
+            0.0
+        }
+    }
+
+    /// Generate noise field with values in the range [0, 1]
+    pub fn generate(&self, rng: &mut Rng, width: u32, height: u32) -> DataLayer<f32> {
+        let source = NoiseSource::new(self.kind, rng);
+        let warp_sources = if self.warp != 0.0 {
+            Some((
+                NoiseSource::new(self.kind, rng),
+                NoiseSource::new(self.kind, rng),
+            ))
+        } else {
+            None
+        };
+
+        let mut layer = DataLayer::new(width, height, 0.0);
+        for y in 0..height {
+            for x in 0..width {
+                let (mut px, mut py) = (x as f32, y as f32);
+                if let Some((wx, wy)) = &warp_sources {
+                    let dx = self.sample(wx, px, py);
+                    let dy = self.sample(wy, px, py);
+                    px += self.warp * dx;
+                    py += self.warp * dy;
+                }
+                let value = self.sample(&source, px, py);
+                layer.set(x, y, (value * 0.5 + 0.5).clamp(0.0, 1.0));
+            }
+        }
+        layer
+    }
+}
+
+impl Default for Fbm {
+    fn default() -> Self {
+        Self::new(NoiseKind::Simplex).with_octaves(4)
+    }
+}
+
+fn fade(t: f32) -> f32 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f32, b: f32, t: f32) -> f32 {
+    a + t * (b - a)
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_range() {
+        let mut rng = Rng::with_seed(100);
+        for kind in [NoiseKind::Value, NoiseKind::Perlin, NoiseKind::Simplex] {
+            let source = NoiseSource::new(kind, &mut rng);
+            for i in 0..1000 {
+                let v = source.get(i as f32 * 0.37, i as f32 * 0.13);
+                assert!((-1.0..=1.0).contains(&v));
+            }
+        }
+    }
+
+    #[test]
+    fn test_coherent() {
+        let mut rng = Rng::with_seed(100);
+        for kind in [NoiseKind::Value, NoiseKind::Perlin, NoiseKind::Simplex] {
+            let source = NoiseSource::new(kind, &mut rng);
+            for i in 0..100 {
+                let x = i as f32 * 0.1;
+                let diff = (source.get(x, 3.3) - source.get(x + 0.01, 3.3)).abs();
+                assert!(diff < 0.1);
+            }
+        }
+    }
+
+    #[test]
+    fn test_deterministic() {
+        let fbm = Fbm::default().with_warp(4.0);
+        let layer1 = fbm.generate(&mut Rng::with_seed(7), 20, 10);
+        let layer2 = fbm.generate(&mut Rng::with_seed(7), 20, 10);
+        assert_eq!(layer1, layer2);
+    }
+}
@@ -0,0 +1,98 @@
+//! A* path finding on the map with the costs of entering each tile.
+//!
+//! Moves are only in 4 directions. Costs should not be smaller than 1,
+//! otherwise the Manhattan distance is not a valid heuristic.
+//!
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use glam::UVec2;
+
+use crate::layer::DataLayer;
+
+/// Cheapest path between 2 points (both included). Tiles with infinite cost are not passable.
+/// Returns empty path if the target can't be reached.
+pub fn astar(costs: &DataLayer<f32>, from: UVec2, to: UVec2) -> Vec<UVec2> {
+    let len = (costs.width * costs.height) as usize;
+    let mut distances = vec![f32::MAX; len];
+    let mut parents: Vec<Option<usize>> = vec![None; len];
+    let heuristic = |p: UVec2| (p.x.abs_diff(to.x) + p.y.abs_diff(to.y)) as f32;
+
+    let start = costs.xy_idx(from.x, from.y);
+    let target = costs.xy_idx(to.x, to.y);
+    distances[start] = 0.0;
+    let mut open_list = BinaryHeap::new();
+    // Non negative floats keep their order when compared as bits
+    open_list.push(Reverse((heuristic(from).to_bits(), start)));
+
+    while let Some(Reverse((_, idx))) = open_list.pop() {
+        if idx == target {
+            break;
+        }
+        let p = UVec2::new(idx as u32 % costs.width, idx as u32 / costs.width);
+        for (dx, dy) in [(1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let (x, y) = (p.x as i64 + dx, p.y as i64 + dy);
+            if x < 0 || y < 0 || x >= costs.width as i64 || y >= costs.height as i64 {
+                continue;
+            }
+            let n = UVec2::new(x as u32, y as u32);
+            let cost = costs.get(n.x, n.y);
+            if !cost.is_finite() {
+                continue;
+            }
+            let n_idx = costs.xy_idx(n.x, n.y);
+            let distance = distances[idx] + cost.max(0.0);
+            if distance < distances[n_idx] {
+                distances[n_idx] = distance;
+                parents[n_idx] = Some(idx);
+                open_list.push(Reverse(((distance + heuristic(n)).to_bits(), n_idx)));
+            }
+        }
+    }
+
+    if distances[target] == f32::MAX {
+        return Vec::new();
+    }
+    let mut path = vec![to];
+    let mut current = target;
+    while let Some(parent) = parents[current] {
+        path.push(UVec2::new(
+            parent as u32 % costs.width,
+            parent as u32 / costs.width,
+        ));
+        current = parent;
+    }
+    path.reverse();
+    path
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_path_around_wall() {
+        let mut costs = DataLayer::new(5, 5, 1.0);
+        for y in 0..4 {
+            costs.set(2, y, f32::INFINITY);
+        }
+        let path = astar(&costs, UVec2::new(0, 0), UVec2::new(4, 0));
+        assert_eq!(path.first(), Some(&UVec2::new(0, 0)));
+        assert_eq!(path.last(), Some(&UVec2::new(4, 0)));
+        assert!(path.contains(&UVec2::new(2, 4)));
+        assert_eq!(path.len(), 13);
+    }
+
+    #[test]
+    fn test_not_reachable() {
+        let mut costs = DataLayer::new(5, 5, 1.0);
+        for y in 0..5 {
+            costs.set(2, y, f32::INFINITY);
+        }
+        assert!(astar(&costs, UVec2::new(0, 0), UVec2::new(4, 0)).is_empty());
+    }
+}
@@ -25,11 +25,15 @@
 //! ---
 //!
 
-use std::collections::VecDeque;
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, VecDeque};
 
 use glam::UVec2;
 
-use crate::layer::WalkableLayer;
+use crate::layer::{DataLayer, WalkableLayer};
+
+/// Cost multiplier for the diagonal moves
+const DIAGONAL_COST: f32 = 1.45;
 
 /// Representation of a Dijkstra flow map.
 /// map is a vector of floats, having a size equal to size_x * size_y (one per tile).
@@ -37,6 +41,8 @@ use crate::layer::WalkableLayer;
 /// max_depth is the maximum number of iterations this search shall support.
 pub struct DijkstraMap {
     pub tiles: Vec<f32>,
+    /// Previous tile on the cheapest path (only for the maps with costs)
+    parents: Vec<Option<usize>>,
     size_x: u32,
     size_y: u32,
     max_depth: f32,
@@ -49,6 +55,7 @@ impl DijkstraMap {
         let tiles = vec![f32::MAX; len];
         let mut d = DijkstraMap {
             tiles,
+            parents: Vec::new(),
             size_x: map.width,
             size_y: map.height,
             max_depth: len as f32,
@@ -57,6 +64,115 @@ impl DijkstraMap {
         d
     }
 
+    /// Construct Dijkstra map where each tile has its own cost of entering it.
+    /// Tiles with infinite cost are not passable.
+    pub fn with_costs(costs: &DataLayer<f32>, starting_point: &UVec2) -> DijkstraMap {
+        let len = (costs.width * costs.height) as usize;
+        let mut d = DijkstraMap {
+            tiles: vec![f32::MAX; len],
+            parents: vec![None; len],
+            size_x: costs.width,
+            size_y: costs.height,
+            max_depth: f32::MAX,
+        };
+        d.build_with_costs(costs, starting_point);
+        d
+    }
+
+    /// Path from the starting point to the given point (both included).
+    /// Returns empty path if the point is not reachable.
+    pub fn path_to(&self, target: &UVec2) -> Vec<UVec2> {
+        if self.tiles[self.xy_idx(target.x, target.y)] == f32::MAX {
+            return Vec::new();
+        }
+        let mut path = vec![*target];
+        if !self.parents.is_empty() {
+            // Zero cost tiles make plateaus, so follow the parents instead of the lowest neighbor
+            let mut current = self.xy_idx(target.x, target.y);
+            while let Some(parent) = self.parents[current] {
+                path.push(UVec2::new(
+                    parent as u32 % self.size_x,
+                    parent as u32 / self.size_x,
+                ));
+                current = parent;
+            }
+            path.reverse();
+            return path;
+        }
+        let mut current = *target;
+        // Follow the lowest neighbor down to the starting point
+        while self.tiles[self.xy_idx(current.x, current.y)] > 0.0 {
+            let next = self
+                .neighbors(current)
+                .min_by(|a, b| {
+                    let da = self.tiles[self.xy_idx(a.x, a.y)];
+                    let db = self.tiles[self.xy_idx(b.x, b.y)];
+                    da.total_cmp(&db)
+                })
+                .filter(|n| {
+                    self.tiles[self.xy_idx(n.x, n.y)]
+                        < self.tiles[self.xy_idx(current.x, current.y)]
+                });
+            match next {
+                Some(n) => {
+                    path.push(n);
+                    current = n;
+                }
+                None => break,
+            }
+        }
+        path.reverse();
+        path
+    }
+
+    fn neighbors(&self, p: UVec2) -> impl Iterator<Item = UVec2> + '_ {
+        (-1..=1i64)
+            .flat_map(|dy| (-1..=1i64).map(move |dx| (dx, dy)))
+            .filter(|&(dx, dy)| dx != 0 || dy != 0)
+            .filter_map(move |(dx, dy)| {
+                let (x, y) = (p.x as i64 + dx, p.y as i64 + dy);
+                if x < 0 || y < 0 || x >= self.size_x as i64 || y >= self.size_y as i64 {
+                    None
+                } else {
+                    Some(UVec2::new(x as u32, y as u32))
+                }
+            })
+    }
+
+    fn build_with_costs(&mut self, costs: &DataLayer<f32>, starting_point: &UVec2) {
+        let mut open_list = BinaryHeap::new();
+        let idx = self.xy_idx(starting_point.x, starting_point.y);
+        self.tiles[idx] = 0.0;
+        // Non negative floats keep their order when compared as bits
+        open_list.push(Reverse((0.0f32.to_bits(), idx)));
+
+        while let Some(Reverse((depth_bits, idx))) = open_list.pop() {
+            let depth = f32::from_bits(depth_bits);
+            if depth > self.tiles[idx] {
+                continue;
+            }
+            let p = UVec2::new(idx as u32 % self.size_x, idx as u32 / self.size_x);
+            for n in self.neighbors(p).collect::<Vec<UVec2>>() {
+                let cost = costs.get(n.x, n.y);
+                if !cost.is_finite() {
+                    continue;
+                }
+                let step = if n.x != p.x && n.y != p.y {
+                    cost * DIAGONAL_COST
+                } else {
+                    cost
+                };
+                let n_idx = self.xy_idx(n.x, n.y);
+                let new_depth = depth + step.max(0.0);
+                if new_depth < self.tiles[n_idx] {
+                    self.tiles[n_idx] = new_depth;
+                    self.parents[n_idx] = Some(idx);
+                    open_list.push(Reverse((new_depth.to_bits(), n_idx)));
+                }
+            }
+        }
+    }
+
     /// Builds the Dijkstra map: iterate from each starting point, to each exit provided by BaseMap's
     /// exits implementation. Each step adds cost to the current depth, and is discarded if the new
     /// depth is further than the current depth.
@@ -132,6 +248,29 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_costs() {
+        // Going around the expensive tiles is cheaper
+        let mut costs = DataLayer::new(5, 3, 1.0);
+        for y in 0..2 {
+            costs.set(2, y, 10.0);
+        }
+        costs.set(2, 2, f32::INFINITY);
+        let dm = DijkstraMap::with_costs(&costs, &UVec2::new(0, 0));
+        let path = dm.path_to(&UVec2::new(4, 0));
+        assert_eq!(path[0], UVec2::new(0, 0));
+        assert_eq!(path[path.len() - 1], UVec2::new(4, 0));
+        assert!(path.contains(&UVec2::new(2, 0)) || path.contains(&UVec2::new(2, 1)));
+        assert!(!path.contains(&UVec2::new(2, 2)));
+        assert!((dm.tiles[4] - 13.0).abs() < 0.01);
+
+        // Wall of the impassable tiles
+        costs.set(2, 0, f32::INFINITY);
+        costs.set(2, 1, f32::INFINITY);
+        let dm = DijkstraMap::with_costs(&costs, &UVec2::new(0, 0));
+        assert!(dm.path_to(&UVec2::new(4, 0)).is_empty());
+    }
+
     #[test]
     fn test_2() {
         let map_str = "
@@ -223,4 +362,17 @@ mod tests {
             assert!(f32::abs(v - e) <= 0.01);
         }
     }
+
+    #[test]
+    fn test_zero_costs() {
+        let mut costs = DataLayer::new(6, 3, 1.0);
+        for x in 0..6 {
+            costs.set(x, 1, 0.0);
+        }
+        let dm = DijkstraMap::with_costs(&costs, &UVec2::new(0, 1));
+        let path = dm.path_to(&UVec2::new(5, 1));
+        assert_eq!(path.first(), Some(&UVec2::new(0, 1)));
+        assert_eq!(path.last(), Some(&UVec2::new(5, 1)));
+        assert_eq!(path.len(), 6);
+    }
 }
@@ -1,3 +1,5 @@
+mod astar;
 mod dijkstra;
 
+pub use astar::astar;
 pub use dijkstra::DijkstraMap;
@@ -0,0 +1,218 @@
+//! Side-view level generator.
+//!
+//! Level is built from left to right as a sequence of ground segments with different heights.
+//! Segments can be separated by gaps, and have platforms above them. Platforms which are too
+//! high to jump on are connected to the ground with ladders.
+//! Height differences and gaps are small enough to pass with the given [Jump].
+//!
+//! Example generator usage:
+//! ```
+//! use mapgen::platformer::{Jump, Platformer};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let gen = Platformer::new(Jump::new(3, 5));
+//! let map = gen.generate(80, 30, &mut rng);
+//!
+//! assert_eq!(map.width, 80);
+//! assert!(map.exit_point.is_some());
+//! ```
+//!
+
+use fastrand::Rng;
+use glam::UVec2;
+
+use super::{Jump, PlatformMap, Tile};
+use crate::poi::DistantExit;
+
+pub struct Platformer {
+    jump: Jump,
+    min_segment: u32,
+    max_segment: u32,
+    gap_chance: f32,
+    platform_chance: f32,
+    ladder_chance: f32,
+}
+
+impl Platformer {
+    pub fn new(jump: Jump) -> Self {
+        Self {
+            jump,
+            min_segment: 4,
+            max_segment: 12,
+            gap_chance: 0.3,
+            platform_chance: 0.6,
+            ladder_chance: 0.3,
+        }
+    }
+
+    /// Length of the ground with the same height
+    pub fn with_segment_length(mut self, min_length: u32, max_length: u32) -> Self {
+        self.min_segment = u32::max(min_length, 1);
+        self.max_segment = u32::max(max_length, self.min_segment);
+        self
+    }
+
+    /// Probability of the gap between ground segments
+    pub fn with_gap_chance(mut self, gap_chance: f32) -> Self {
+        self.gap_chance = gap_chance;
+        self
+    }
+
+    /// Probability of the platform above the segment, and of the ladder to the higher platform
+    pub fn with_platform_chance(mut self, platform_chance: f32, ladder_chance: f32) -> Self {
+        self.platform_chance = platform_chance;
+        self.ladder_chance = ladder_chance;
+        self
+    }
+
+    pub fn generate(&self, map_width: u32, map_height: u32, rng: &mut Rng) -> PlatformMap {
+        let mut map = PlatformMap::new(map_width, map_height);
+        if map_width < 3 || map_height < 3 {
+            return map;
+        }
+        for x in 0..map_width {
+            map.set_tile(x, 0, Tile::Solid);
+        }
+        for y in 0..map_height {
+            map.set_tile(0, y, Tile::Solid);
+            map.set_tile(map_width - 1, y, Tile::Solid);
+        }
+        let jump_height = self.jump.height.max(1);
+        // Ground stays in the lower half of the map, leaving the space for platforms
+        let min_ground = u32::max(map_height / 2, 2);
+        let max_ground = map_height.saturating_sub(2).max(min_ground);
+
+        let mut ground = max_ground.saturating_sub(1).max(min_ground);
+        let mut x = 1;
+        while x < map_width - 1 {
+            let length = rng.u32(self.min_segment..=self.max_segment);
+            let end = u32::min(x + length, map_width - 1);
+            for sx in x..end {
+                for y in ground..map_height {
+                    map.set_tile(sx, y, Tile::Solid);
+                }
+            }
+            if x == 1 {
+                map.starting_point = Some(UVec2::new(1, ground - 1));
+            }
+            self.add_platforms(rng, &mut map, x, end, ground);
+            x = end;
+
+            // Gap can't be the last thing in the level
+            let max_gap = self.jump.distance.saturating_sub(2);
+            if max_gap > 0
+                && x + max_gap + self.min_segment < map_width - 1
+                && rng.f32() < self.gap_chance
+            {
+                x += rng.u32(1..=max_gap);
+                // Next segment can't be higher than the current one
+                ground = rng.u32(ground..=u32::min(ground + 2, max_ground));
+            } else {
+                let up = ground.saturating_sub(jump_height - 1).max(min_ground);
+                ground = rng.u32(up..=max_ground);
+            }
+        }
+
+        if let Some(start) = map.starting_point {
+            map.exit_point = Some(DistantExit::find_with_jumps(&start, &map, &self.jump));
+        }
+        map
+    }
+
+    /// Platforms above the ground segment between `x1` and `x2`
+    fn add_platforms(&self, rng: &mut Rng, map: &mut PlatformMap, x1: u32, x2: u32, ground: u32) {
+        if x2 - x1 < 3 || rng.f32() >= self.platform_chance {
+            return;
+        }
+        let jump_height = self.jump.height.max(2);
+        // Leave one free tile above the ground for the player
+        let mut level = ground;
+        loop {
+            let ladder = rng.f32() < self.ladder_chance;
+            let rise = if ladder {
+                rng.u32(jump_height + 1..=jump_height + 3)
+            } else {
+                rng.u32(2..=jump_height)
+            };
+            if level < rise + 3 {
+                break;
+            }
+            let y = level - rise;
+            let length = rng.u32(3..=u32::min(x2 - x1, 8));
+            let start = rng.u32(x1..=x2 - length);
+            for x in start..start + length {
+                map.set_tile(x, y, Tile::Platform);
+            }
+            if ladder {
+                let lx = rng.u32(start..start + length);
+                for ly in y..level {
+                    map.set_tile(lx, ly, Tile::Ladder);
+                }
+            }
+            level = y;
+            if rng.f32() >= self.platform_chance {
+                break;
+            }
+        }
+    }
+}
+
+impl Default for Platformer {
+    fn default() -> Self {
+        Self::new(Jump::default())
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platformer::JumpMap;
+
+    #[test]
+    fn test_level() {
+        let mut rng = Rng::with_seed(100);
+        let map = Platformer::default().generate(80, 30, &mut rng);
+        let start = map.starting_point.unwrap();
+        assert!(map.can_stand(start.x as i32, start.y as i32));
+        for y in 0..30 {
+            assert!(map.is_solid(0, y));
+            assert!(map.is_solid(79, y));
+        }
+        let tiles = &map.tile_layer.walkables;
+        assert!(tiles.contains(&Tile::Platform));
+        assert!(tiles.contains(&Tile::Ladder));
+    }
+
+    #[test]
+    fn test_reachable_end() {
+        for seed in 0..10 {
+            let mut rng = Rng::with_seed(seed);
+            let jump = Jump::new(3, 5);
+            let map = Platformer::new(jump).generate(80, 30, &mut rng);
+            let jump_map = JumpMap::new(&map, &map.starting_point.unwrap(), &jump);
+            // Player can get to the right end of the level
+            assert!((0..30).any(|y| jump_map.is_reachable(78, y)));
+            // All ladders can be used
+            for (i, &tile) in map.tile_layer.walkables.iter().enumerate() {
+                if tile == Tile::Ladder {
+                    assert!(jump_map.tiles[i] < f32::MAX);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_small_maps() {
+        let mut rng = Rng::with_seed(1);
+        for width in 0..8 {
+            for height in 0..8 {
+                let map = Platformer::default().generate(width, height, &mut rng);
+                assert_eq!((map.width, map.height), (width, height));
+            }
+        }
+    }
+}
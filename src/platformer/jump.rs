@@ -0,0 +1,301 @@
+//! Reachability of the side-view map.
+//!
+//! The player can walk left and right, falls down when there is nothing below, climbs ladders
+//! and jumps. Jump follows the parabola defined by the maximum height and the distance
+//! (in tiles) of the jump on the flat ground. Shorter jumps have the same height.
+//! Solid tiles stop the jump, and one-way platforms can be passed from below.
+//!
+//! This module calculates the cost of getting from the starting point to each tile where
+//! the player can stand. Tiles which are not reachable get f32::MAX value.
+//!
+//! Example usage:
+//! ```
+//! use mapgen::platformer::{Jump, JumpMap, PlatformMap};
+//! use glam::UVec2;
+//!
+//! let map = PlatformMap::from_string("
+//!     ........
+//!     ....====
+//!     ........
+//!     ........
+//!     ########
+//! ");
+//! let start = UVec2::new(1, 3);
+//! assert!(JumpMap::new(&map, &start, &Jump::new(3, 4)).is_reachable(5, 0));
+//! assert!(!JumpMap::new(&map, &start, &Jump::new(2, 4)).is_reachable(5, 0));
+//! ```
+//!
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use glam::{IVec2, UVec2};
+
+use super::{PlatformMap, Tile};
+
+/// Player movement abilities
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Jump {
+    /// Maximum jump height in tiles
+    pub height: u32,
+    /// Horizontal distance of the longest jump on the flat ground
+    pub distance: u32,
+}
+
+impl Jump {
+    pub fn new(height: u32, distance: u32) -> Self {
+        Self { height, distance }
+    }
+}
+
+impl Default for Jump {
+    fn default() -> Self {
+        Self::new(3, 5)
+    }
+}
+
+pub struct JumpMap {
+    /// Cost of getting to each tile. Tiles where player can't stand are not reachable
+    pub tiles: Vec<f32>,
+    /// Tiles which the player passes when moving around the reachable part of the map
+    pub visited: Vec<bool>,
+    width: u32,
+}
+
+impl JumpMap {
+    pub fn new(map: &PlatformMap, starting_point: &UVec2, jump: &Jump) -> Self {
+        let len = (map.width * map.height) as usize;
+        let mut jump_map = JumpMap {
+            tiles: vec![f32::MAX; len],
+            visited: vec![false; len],
+            width: map.width,
+        };
+        jump_map.build(map, starting_point.as_ivec2(), jump);
+        jump_map
+    }
+
+    pub fn is_reachable(&self, x: u32, y: u32) -> bool {
+        self.tiles[self.xy_idx(x, y)] < f32::MAX
+    }
+
+    fn build(&mut self, map: &PlatformMap, starting_point: IVec2, jump: &Jump) {
+        if map.is_solid(starting_point.x, starting_point.y) {
+            return;
+        }
+        // Player can start in the air
+        let Some((start, cost)) = self.fall(map, starting_point) else {
+            return;
+        };
+        let mut open_list = BinaryHeap::new();
+        let idx = self.xy_idx(start.x as u32, start.y as u32);
+        self.tiles[idx] = cost;
+        // Non negative floats keep their order when compared as bits
+        open_list.push(Reverse((cost.to_bits(), idx)));
+
+        while let Some(Reverse((depth_bits, idx))) = open_list.pop() {
+            let depth = f32::from_bits(depth_bits);
+            if depth > self.tiles[idx] {
+                continue;
+            }
+            let p = IVec2::new(
+                (idx as u32 % self.width) as i32,
+                (idx as u32 / self.width) as i32,
+            );
+            for (n, cost) in self.moves(map, p, jump) {
+                let n_idx = self.xy_idx(n.x as u32, n.y as u32);
+                let new_depth = depth + cost;
+                if new_depth < self.tiles[n_idx] {
+                    self.tiles[n_idx] = new_depth;
+                    open_list.push(Reverse((new_depth.to_bits(), n_idx)));
+                }
+            }
+        }
+    }
+
+    /// All places where the player can get from the given tile, with the cost of the move
+    fn moves(&mut self, map: &PlatformMap, p: IVec2, jump: &Jump) -> Vec<(IVec2, f32)> {
+        let mut moves = Vec::new();
+        // Walk
+        for dir in [-1, 1] {
+            let next = IVec2::new(p.x + dir, p.y);
+            if !map.is_solid(next.x, next.y) {
+                moves.extend(self.fall(map, next).map(|(n, c)| (n, c + 1.0)));
+            }
+        }
+        // Climb
+        if map.tile(p.x as u32, p.y as u32) == Tile::Ladder && !map.is_solid(p.x, p.y - 1) {
+            moves.push((IVec2::new(p.x, p.y - 1), 1.0));
+        }
+        if p.y + 1 < map.height as i32 && map.tile(p.x as u32, p.y as u32 + 1) == Tile::Ladder {
+            moves.push((IVec2::new(p.x, p.y + 1), 1.0));
+        }
+        // Jump
+        moves.extend(self.vertical_jump(map, p, jump));
+        for distance in 1..=jump.distance {
+            for dir in [-1, 1] {
+                moves.extend(self.jump(map, p, dir, distance, jump.height));
+            }
+        }
+        moves.retain(|&(n, _)| n != p);
+        moves
+    }
+
+    /// Fall down until there is something to stand on.
+    /// Returns None if the player falls out of the map.
+    fn fall(&mut self, map: &PlatformMap, from: IVec2) -> Option<(IVec2, f32)> {
+        let mut p = from;
+        let mut cost = 0.0;
+        while p.y < map.height as i32 {
+            self.mark(p);
+            if map.can_stand(p.x, p.y) {
+                return Some((p, cost));
+            }
+            p.y += 1;
+            cost += 1.0;
+        }
+        None
+    }
+
+    fn vertical_jump(
+        &mut self,
+        map: &PlatformMap,
+        from: IVec2,
+        jump: &Jump,
+    ) -> Option<(IVec2, f32)> {
+        let mut p = from;
+        let mut cost = 0.0;
+        while from.y - p.y < jump.height as i32 && !map.is_solid(p.x, p.y - 1) {
+            p.y -= 1;
+            cost += 1.0;
+            self.mark(p);
+        }
+        self.fall(map, p).map(|(n, c)| (n, cost + c))
+    }
+
+    /// Follow the jump parabola. Player moves up before moving sideways,
+    /// and falls down before moving sideways.
+    fn jump(
+        &mut self,
+        map: &PlatformMap,
+        from: IVec2,
+        dir: i32,
+        distance: u32,
+        height: u32,
+    ) -> Option<(IVec2, f32)> {
+        let mut p = from;
+        let mut cost = 0.0;
+        let k = distance as f32;
+        for i in 1.. {
+            let t = (2.0 * i as f32 - k) / k;
+            let y = from.y - (height as f32 * (1.0 - t * t)).round() as i32;
+            // Descending part of the jump can land on anything
+            let descending = 2 * i > distance;
+            while p.y > y {
+                if map.is_solid(p.x, p.y - 1) {
+                    return self.fall(map, p).map(|(n, c)| (n, cost + c));
+                }
+                p.y -= 1;
+                cost += 1.0;
+                self.mark(p);
+            }
+            while p.y < y {
+                p.y += 1;
+                cost += 1.0;
+                if p.y >= map.height as i32 {
+                    return None;
+                }
+                self.mark(p);
+                if map.can_stand(p.x, p.y) {
+                    return Some((p, cost));
+                }
+            }
+            if map.is_solid(p.x + dir, p.y) {
+                return self.fall(map, p).map(|(n, c)| (n, cost + c));
+            }
+            p.x += dir;
+            cost += 1.0;
+            self.mark(p);
+            if descending && map.can_stand(p.x, p.y) {
+                return Some((p, cost));
+            }
+        }
+        None
+    }
+
+    fn mark(&mut self, p: IVec2) {
+        let idx = self.xy_idx(p.x as u32, p.y as u32);
+        self.visited[idx] = true;
+    }
+
+    fn xy_idx(&self, x: u32, y: u32) -> usize {
+        (y * self.width + x) as usize
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_gap() {
+        let map = PlatformMap::from_string(
+            "
+            #          #
+            #          #
+            #          #
+            #          #
+            ####   #####
+            ####   #####
+            ",
+        );
+        let start = UVec2::new(1, 3);
+        let jump_map = JumpMap::new(&map, &start, &Jump::new(2, 4));
+        assert!(jump_map.is_reachable(10, 3));
+        // Gap is too wide
+        let jump_map = JumpMap::new(&map, &start, &Jump::new(2, 3));
+        assert!(!jump_map.is_reachable(10, 3));
+        assert!(jump_map.is_reachable(3, 3));
+        // Player can't stand in the air
+        assert!(!jump_map.is_reachable(5, 2));
+        assert!(jump_map.visited[map.xy_idx(5, 2)]);
+    }
+
+    #[test]
+    fn test_ladder() {
+        let map = PlatformMap::from_string(
+            "
+            #      #
+            #==H   #
+            #  H   #
+            #  H   #
+            #  H   #
+            ########
+            ",
+        );
+        let start = UVec2::new(6, 4);
+        let jump_map = JumpMap::new(&map, &start, &Jump::new(2, 4));
+        assert!(jump_map.is_reachable(1, 0));
+        assert!(jump_map.is_reachable(3, 2));
+        // Top of the ladder
+        assert!(jump_map.tiles[map.xy_idx(3, 0)] < f32::MAX);
+    }
+
+    #[test]
+    fn test_wall() {
+        let map = PlatformMap::from_string(
+            "
+            #        #
+            #   #    #
+            #   #    #
+            #   #    #
+            ##########
+            ",
+        );
+        let start = UVec2::new(1, 3);
+        assert!(JumpMap::new(&map, &start, &Jump::new(3, 4)).is_reachable(8, 3));
+        assert!(!JumpMap::new(&map, &start, &Jump::new(2, 6)).is_reachable(8, 3));
+    }
+}
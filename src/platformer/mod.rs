@@ -0,0 +1,17 @@
+//! Generators for side-view (platformer) maps.
+//! Unlike the other modules, the player is pulled down by gravity, so the map is traversed by
+//! walking, falling, jumping and climbing ladders.
+//! This process is done in few steps:
+//! * Generate ground, gaps, platforms and ladders with [Platformer]
+//! * Check which places can be reached with the given [Jump] using [JumpMap]
+//! * Place the exit with [crate::poi::DistantExit::find_with_jumps] and remove unreachable
+//!   areas with [crate::poi::CullUnreachable::remove_unjumpable_tiles]
+//!
+
+pub mod generator;
+pub mod jump;
+pub mod tile_map;
+
+pub use generator::Platformer;
+pub use jump::{Jump, JumpMap};
+pub use tile_map::{PlatformMap, Tile};
@@ -0,0 +1,163 @@
+//! Side-view map. Rows are counted from the top, so the gravity pulls towards larger `y`.
+//!
+
+use std::fmt;
+
+use glam::UVec2;
+
+use crate::layer::{DataLayer, WalkableLayer};
+
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Tile {
+    #[default]
+    Empty,
+    Solid,
+    /// One-way platform. Can be passed from below and stood on from above
+    Platform,
+    Ladder,
+}
+
+impl Tile {
+    /// Character used to print the map
+    pub fn symbol(&self) -> char {
+        match self {
+            Tile::Empty => ' ',
+            Tile::Solid => '#',
+            Tile::Platform => '=',
+            Tile::Ladder => 'H',
+        }
+    }
+}
+
+#[derive(Default, Clone)]
+pub struct PlatformMap {
+    pub width: u32,
+    pub height: u32,
+    pub tile_layer: DataLayer<Tile>,
+    /// Tiles which are not solid (the player can be there)
+    pub walkable_layer: WalkableLayer,
+    pub starting_point: Option<UVec2>,
+    pub exit_point: Option<UVec2>,
+}
+
+impl PlatformMap {
+    /// Generates an empty map (all tiles are the air)
+    pub fn new(width: u32, height: u32) -> Self {
+        let mut walkable_layer = WalkableLayer::new(width, height);
+        walkable_layer.tiles.fill(true);
+        Self {
+            width,
+            height,
+            tile_layer: DataLayer::new(width, height, Tile::Empty),
+            walkable_layer,
+            starting_point: None,
+            exit_point: None,
+        }
+    }
+
+    /// Create map from given string.
+    /// `#` is solid, `=` is platform, `H` is ladder and any other character is empty
+    pub fn from_string(map_string: &str) -> Self {
+        let lines: Vec<&str> = map_string
+            .split('\n')
+            .map(|l| l.trim())
+            .filter(|l| !l.is_empty())
+            .collect();
+        let cols = lines.iter().map(|l| l.len() as u32).max().unwrap_or(1);
+        let mut map = Self::new(cols, lines.len() as u32);
+        for (y, line) in lines.iter().enumerate() {
+            for (x, c) in line.chars().enumerate() {
+                let tile = match c {
+                    '#' => Tile::Solid,
+                    '=' => Tile::Platform,
+                    'H' => Tile::Ladder,
+                    _ => Tile::Empty,
+                };
+                map.set_tile(x as u32, y as u32, tile);
+            }
+        }
+        map
+    }
+
+    pub fn tile(&self, x: u32, y: u32) -> Tile {
+        self.tile_layer.get(x, y)
+    }
+
+    pub fn set_tile(&mut self, x: u32, y: u32, tile: Tile) {
+        self.tile_layer.set(x, y, tile);
+        self.walkable_layer.set_walkable(x, y, tile != Tile::Solid);
+    }
+
+    /// Tiles outside of the map are solid, except below the bottom edge (bottomless pit)
+    pub fn is_solid(&self, x: i32, y: i32) -> bool {
+        if x < 0 || y < 0 || x >= self.width as i32 {
+            true
+        } else if y >= self.height as i32 {
+            false
+        } else {
+            self.tile(x as u32, y as u32) == Tile::Solid
+        }
+    }
+
+    /// Check if the player can stay at the given tile without falling
+    pub fn can_stand(&self, x: i32, y: i32) -> bool {
+        if self.is_solid(x, y) || y + 1 >= self.height as i32 {
+            return false;
+        }
+        self.tile(x as u32, y as u32) == Tile::Ladder
+            || self.tile(x as u32, y as u32 + 1) != Tile::Empty
+    }
+
+    pub fn xy_idx(&self, x: u32, y: u32) -> usize {
+        self.tile_layer.xy_idx(x, y)
+    }
+}
+
+impl fmt::Display for PlatformMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in 0..self.height {
+            let line: String = (0..self.width)
+                .map(|x| match UVec2::new(x, y) {
+                    p if Some(p) == self.starting_point => '@',
+                    p if Some(p) == self.exit_point => '>',
+                    _ => self.tile(x, y).symbol(),
+                })
+                .collect();
+            let _ = writeln!(f, "{}", line);
+        }
+        Ok(())
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_string() {
+        let map = PlatformMap::from_string(
+            "
+            #    #
+            # == #
+            # H  #
+            ##H  #
+            ",
+        );
+        assert_eq!(map.width, 6);
+        assert_eq!(map.tile(2, 1), Tile::Platform);
+        assert_eq!(map.tile(2, 3), Tile::Ladder);
+        assert!(map.is_solid(0, 0));
+        assert!(map.walkable_layer.is_walkable(2, 1));
+        // Standing on platform, ladder and solid ground
+        assert!(map.can_stand(2, 0));
+        assert!(map.can_stand(2, 2));
+        assert!(map.can_stand(1, 2));
+        assert!(!map.can_stand(4, 1));
+        // Bottom row has nothing below
+        assert!(!map.can_stand(4, 3));
+        assert!(!map.is_solid(4, 4));
+    }
+}
@@ -1,11 +1,14 @@
 //! Remove unreachable areas from the map.
 //! It will add wall on every tile which is not accessible from the starting point.
+//! On the side-view maps this includes platforms and ladders which can't be reached
+//! with the given jump.
 //!
 
 use glam::UVec2;
 
 use crate::layer::WalkableLayer;
 use crate::path::DijkstraMap;
+use crate::platformer::{Jump, JumpMap, PlatformMap, Tile};
 
 pub struct CullUnreachable;
 
@@ -30,6 +33,24 @@ impl CullUnreachable {
         }
         new_map
     }
+
+    /// Fill every tile which the player never passes with the solid tile
+    pub fn remove_unjumpable_tiles(
+        starting_point: &UVec2,
+        map: &PlatformMap,
+        jump: &Jump,
+    ) -> PlatformMap {
+        let mut new_map = map.clone();
+        let jump_map = JumpMap::new(map, starting_point, jump);
+        for y in 0..map.height {
+            for x in 0..map.width {
+                if !jump_map.visited[map.xy_idx(x, y)] {
+                    new_map.set_tile(x, y, Tile::Solid);
+                }
+            }
+        }
+        new_map
+    }
 }
 
 /// ------------------------------------------------------------------------------------------------
@@ -39,6 +60,7 @@ impl CullUnreachable {
 mod tests {
     use glam::UVec2;
 
+    use crate::platformer::{Jump, PlatformMap};
     use crate::{layer::WalkableLayer, poi::CullUnreachable};
 
     #[test]
@@ -61,4 +83,30 @@ mod tests {
 
         assert_eq!(new_map, expected_map);
     }
+
+    #[test]
+    fn test_culling_with_jumps() {
+        let map_str = "
+        ##########
+        #=       #
+        #    ##  #
+        #    #   #
+        ##########
+        ";
+        let map = PlatformMap::from_string(map_str);
+        let starting_point = UVec2::new(8, 3);
+        let expected_map_str = "
+        ##########
+        ##########
+        #######  #
+        ######   #
+        ##########
+        ";
+        let expected_map = PlatformMap::from_string(expected_map_str);
+
+        let new_map =
+            CullUnreachable::remove_unjumpable_tiles(&starting_point, &map, &Jump::new(1, 3));
+
+        assert_eq!(new_map.tile_layer, expected_map.tile_layer);
+    }
 }
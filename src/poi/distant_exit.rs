@@ -7,6 +7,7 @@ use glam::UVec2;
 
 use crate::layer::WalkableLayer;
 use crate::path::DijkstraMap;
+use crate::platformer::{Jump, JumpMap, PlatformMap};
 use std::f32;
 
 pub struct DistantExit;
@@ -24,6 +25,20 @@ impl DistantExit {
         }
         map.idx_point(best_idx)
     }
+
+    /// Find exit on the side-view map, where the player moves by walking, jumping and climbing
+    pub fn find_with_jumps(starting_point: &UVec2, map: &PlatformMap, jump: &Jump) -> UVec2 {
+        let mut best_idx = map.xy_idx(starting_point.x, starting_point.y);
+        let mut best_value = 0.0;
+        let jump_map = JumpMap::new(map, starting_point, jump);
+        for (i, &value) in jump_map.tiles.iter().enumerate() {
+            if value < f32::MAX && value > best_value {
+                best_value = value;
+                best_idx = i;
+            }
+        }
+        map.walkable_layer.idx_point(best_idx)
+    }
 }
 
 /// ------------------------------------------------------------------------------------------------
@@ -33,6 +48,7 @@ impl DistantExit {
 mod tests {
     use glam::UVec2;
 
+    use crate::platformer::{Jump, PlatformMap};
     use crate::{layer::WalkableLayer, poi::DistantExit};
 
     #[test]
@@ -49,4 +65,21 @@ mod tests {
 
         assert_eq!(exit_point, UVec2::new(1, 2));
     }
+
+    #[test]
+    fn test_exit_with_jumps() {
+        let map = PlatformMap::from_string(
+            "
+            #         #
+            #===      #
+            #         #
+            #    ##   #
+            ####  #####
+            ",
+        );
+        let starting_point = UVec2::new(8, 3);
+        let exit_point = DistantExit::find_with_jumps(&starting_point, &map, &Jump::new(2, 4));
+
+        assert_eq!(exit_point, UVec2::new(1, 0));
+    }
 }
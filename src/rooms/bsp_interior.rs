@@ -22,15 +22,28 @@ use glam::UVec2;
 
 use crate::geometry::Rect;
 
-use super::RoomsMap;
+use super::room_shape::choose_shape;
+use super::{RoomShape, RoomsMap};
 
 pub struct BspInterior {
     min_room_size: u32,
+    shapes: Vec<RoomShape>,
 }
 
 impl BspInterior {
     pub fn new(min_room_size: u32) -> Self {
-        Self { min_room_size }
+        Self {
+            min_room_size,
+            shapes: vec![RoomShape::Rectangle],
+        }
+    }
+
+    /// Shapes of the rooms. Each room gets the random shape from the list.
+    pub fn with_shapes(mut self, shapes: Vec<RoomShape>) -> Self {
+        if !shapes.is_empty() {
+            self.shapes = shapes;
+        }
+        self
     }
 
     pub fn generate(&self, map_width: u32, max_height: u32, rng: &mut Rng) -> RoomsMap {
@@ -43,8 +56,8 @@ impl BspInterior {
 
         let rooms_copy = rects.clone();
         for r in rooms_copy.iter() {
-            let room = *r;
-            map.add_room(room);
+            let shape = choose_shape(rng, &self.shapes);
+            map.add_shaped_room(rng, *r, shape);
         }
 
         // Now we want corridors
@@ -55,7 +68,9 @@ impl BspInterior {
             let start_y = rng.choice(room.y1..room.y2).unwrap();
             let end_x = rng.choice(next_room.x1..next_room.x2).unwrap();
             let end_y = rng.choice(next_room.y1..next_room.y2).unwrap();
-            map.add_corridor(UVec2::new(start_x, start_y), UVec2::new(end_x, end_y));
+            let start = map.closest_room_tile(i, UVec2::new(start_x, start_y));
+            let end = map.closest_room_tile(i + 1, UVec2::new(end_x, end_y));
+            map.add_corridor(start, end);
         }
 
         map
@@ -105,7 +120,7 @@ impl BspInterior {
 
 impl Default for BspInterior {
     fn default() -> Self {
-        Self { min_room_size: 8 }
+        Self::new(8)
     }
 }
 
@@ -115,6 +130,7 @@ impl Default for BspInterior {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::path::DijkstraMap;
 
     #[test]
     fn no_corridors_on_borders() {
@@ -130,4 +146,20 @@ mod tests {
             assert!(map.is_blocked(79, j));
         }
     }
+
+    #[test]
+    fn test_shaped_rooms_are_connected() {
+        let mut rng = Rng::with_seed(10);
+        let bsp = BspInterior::default().with_shapes(vec![RoomShape::Circle, RoomShape::CaveBlob]);
+        let map = bsp.generate(80, 50, &mut rng);
+        assert_eq!(map.room_tiles.len(), map.rooms.len());
+        let start = map.room_tiles[0][0];
+        let dijkstra = DijkstraMap::new(&map.walkable_layer, &start);
+        for (rect, tiles) in map.rooms.iter().zip(map.room_tiles.iter()) {
+            assert!(tiles.len() < (rect.width() * rect.height()) as usize);
+            for p in tiles {
+                assert!(dijkstra.tiles[map.xy_idx(p.x, p.y)] < f32::MAX);
+            }
+        }
+    }
 }
@@ -17,17 +17,30 @@
 //! ```
 //!
 
-use super::RoomsMap;
+use super::room_shape::choose_shape;
+use super::{RoomShape, RoomsMap};
 use crate::geometry::Rect;
 use fastrand::Rng;
 
 pub struct BspRooms {
     max_split: u32,
+    shapes: Vec<RoomShape>,
 }
 
 impl BspRooms {
     pub fn new(max_split: u32) -> Self {
-        Self { max_split }
+        Self {
+            max_split,
+            shapes: vec![RoomShape::Rectangle],
+        }
+    }
+
+    /// Shapes of the rooms. Each room gets the random shape from the list.
+    pub fn with_shapes(mut self, shapes: Vec<RoomShape>) -> Self {
+        if !shapes.is_empty() {
+            self.shapes = shapes;
+        }
+        self
     }
 
     pub fn generate(&self, map_width: u32, max_height: u32, rng: &mut Rng) -> RoomsMap {
@@ -45,7 +58,8 @@ impl BspRooms {
             let candidate = self.get_random_sub_rect(rect, rng);
 
             if self.is_possible(candidate, &map) {
-                map.add_room(candidate);
+                let shape = choose_shape(rng, &self.shapes);
+                map.add_shaped_room(rng, candidate, shape);
                 rects.append(&mut self.split_into_subrects(rect));
             }
         }
@@ -148,7 +162,7 @@ impl BspRooms {
 
 impl Default for BspRooms {
     fn default() -> Self {
-        Self { max_split: 240 }
+        Self::new(240)
     }
 }
 
@@ -0,0 +1,241 @@
+//! Corridor styles used to connect the rooms.
+//!
+//! * L-shape with the bend after the horizontal or vertical part
+//! * Straight line
+//! * Winding corridor, which randomly wanders towards the target
+//! * Routed corridor, which goes around the rooms when possible
+//!
+//! Carved tiles outside of the rooms are stored in the `corridors` field of the map.
+//!
+//! Example usage:
+//! ```
+//! use mapgen::rooms::{Corridor, CorridorStyle, RoomsMap};
+//! use mapgen::geometry::Rect;
+//! use fastrand::Rng;
+//! use glam::UVec2;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let mut map = RoomsMap::new(20, 10);
+//! map.add_room(Rect::new(1, 1, 4, 4));
+//! map.add_room(Rect::new(14, 5, 4, 4));
+//! let corridor = Corridor::new(CorridorStyle::Winding).with_width(2);
+//! corridor.carve(&mut rng, &mut map, UVec2::new(3, 3), UVec2::new(15, 7));
+//!
+//! assert_eq!(map.corridors.len(), 1);
+//! ```
+//!
+
+use fastrand::Rng;
+use glam::{IVec2, UVec2};
+
+use super::RoomsMap;
+use crate::geometry::{four_connected, line, Rect};
+use crate::layer::DataLayer;
+use crate::path::astar;
+
+/// Cost of going through the room in the routed corridor
+const ROOM_COST: f32 = 20.0;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum CorridorStyle {
+    /// Horizontal and vertical part in random order
+    LShape,
+    /// Bresenham's line with the corner tile at each diagonal step
+    Straight,
+    /// Random walk biased towards the target
+    Winding,
+    /// Cheapest path, which avoids other rooms
+    Routed,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Corridor {
+    pub style: CorridorStyle,
+    pub width: u32,
+}
+
+impl Corridor {
+    pub fn new(style: CorridorStyle) -> Self {
+        Self { style, width: 1 }
+    }
+
+    pub fn with_width(mut self, width: u32) -> Self {
+        self.width = u32::max(width, 1);
+        self
+    }
+
+    /// Carve the corridor between 2 points and add it to the map.
+    /// Returns carved tiles outside of the rooms.
+    pub fn carve(&self, rng: &mut Rng, map: &mut RoomsMap, from: UVec2, to: UVec2) -> Vec<UVec2> {
+        let path = match self.style {
+            CorridorStyle::LShape => l_shape(from, to, rng.bool()),
+            CorridorStyle::Straight => straight(from, to),
+            CorridorStyle::Winding => winding(rng, map, from, to),
+            CorridorStyle::Routed => routed(map, from, to),
+        };
+        map.carve_path(&path, self.width)
+    }
+}
+
+impl Default for Corridor {
+    fn default() -> Self {
+        Self::new(CorridorStyle::LShape)
+    }
+}
+
+/// Horizontal and vertical segments. Both end points are included.
+pub(crate) fn l_shape(from: UVec2, to: UVec2, horizontal_first: bool) -> Vec<UVec2> {
+    let corner = if horizontal_first {
+        UVec2::new(to.x, from.y)
+    } else {
+        UVec2::new(from.x, to.y)
+    };
+    let mut path = line(from, corner);
+    path.extend(line(corner, to).into_iter().skip(1));
+    path
+}
+
+/// Line where diagonal steps get the corner tile, so the corridor can be walked in 4 directions
+fn straight(from: UVec2, to: UVec2) -> Vec<UVec2> {
+    four_connected(&line(from, to))
+}
+
+/// Random walk which moves towards the target 2 times out of 3
+fn winding(rng: &mut Rng, map: &RoomsMap, from: UVec2, to: UVec2) -> Vec<UVec2> {
+    let mut path = vec![from];
+    let mut current = from;
+    let max_steps = 10 * (map.width + map.height);
+    for _ in 0..max_steps {
+        if current == to {
+            return path;
+        }
+        let towards = (to.as_ivec2() - current.as_ivec2()).signum();
+        let step = if rng.u32(0..3) < 2 {
+            if towards.x != 0 && (towards.y == 0 || rng.bool()) {
+                IVec2::new(towards.x, 0)
+            } else {
+                IVec2::new(0, towards.y)
+            }
+        } else {
+            [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y][rng.usize(0..4)]
+        };
+        let next = current.as_ivec2() + step;
+        if next.x < 1
+            || next.y < 1
+            || next.x >= map.width as i32 - 1
+            || next.y >= map.height as i32 - 1
+        {
+            continue;
+        }
+        current = next.as_uvec2();
+        path.push(current);
+    }
+    // Finish the corridor if the walk was too long
+    path.extend(l_shape(current, to, rng.bool()).into_iter().skip(1));
+    path
+}
+
+/// Cheapest path where the rooms (except the ones with the end points) are expensive
+fn routed(map: &RoomsMap, from: UVec2, to: UVec2) -> Vec<UVec2> {
+    let mut costs = DataLayer::new(map.width, map.height, 1.0);
+    let inside = |r: &Rect, p: UVec2| p.x >= r.x1 && p.x < r.x2 && p.y >= r.y1 && p.y < r.y2;
+    for room in map.rooms.iter() {
+        if inside(room, from) || inside(room, to) {
+            continue;
+        }
+        // Also keep the walls around the room
+        for y in room.y1.saturating_sub(1)..=room.y2 {
+            for x in room.x1.saturating_sub(1)..=room.x2 {
+                costs.set(x, y, ROOM_COST);
+            }
+        }
+    }
+    for x in 0..map.width {
+        costs.set(x, 0, f32::INFINITY);
+        costs.set(x, map.height - 1, f32::INFINITY);
+    }
+    for y in 0..map.height {
+        costs.set(0, y, f32::INFINITY);
+        costs.set(map.width - 1, y, f32::INFINITY);
+    }
+    let path = astar(&costs, from, to);
+    if path.is_empty() {
+        l_shape(from, to, true)
+    } else {
+        path
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn connected(path: &[UVec2]) -> bool {
+        path.windows(2)
+            .all(|w| w[0].x.abs_diff(w[1].x) + w[0].y.abs_diff(w[1].y) == 1)
+    }
+
+    #[test]
+    fn test_l_shape() {
+        let path = l_shape(UVec2::new(1, 1), UVec2::new(3, 4), false);
+        assert_eq!(path.len(), 6);
+        assert_eq!(path[3], UVec2::new(1, 4));
+        assert!(connected(&path));
+    }
+
+    #[test]
+    fn test_straight() {
+        let path = straight(UVec2::new(1, 1), UVec2::new(8, 4));
+        assert_eq!(path.first(), Some(&UVec2::new(1, 1)));
+        assert_eq!(path.last(), Some(&UVec2::new(8, 4)));
+        assert_eq!(path.len(), 7 + 3 + 1);
+        assert!(connected(&path));
+    }
+
+    #[test]
+    fn test_winding() {
+        let mut rng = Rng::with_seed(7);
+        let map = RoomsMap::new(30, 20);
+        let path = winding(&mut rng, &map, UVec2::new(2, 2), UVec2::new(25, 15));
+        assert_eq!(path.last(), Some(&UVec2::new(25, 15)));
+        assert!(connected(&path));
+    }
+
+    #[test]
+    fn test_routed_around_room() {
+        let mut rng = Rng::with_seed(7);
+        let mut map = RoomsMap::new(30, 14);
+        map.add_room(Rect::new(1, 3, 3, 3));
+        map.add_room(Rect::new(12, 2, 5, 7));
+        map.add_room(Rect::new(25, 3, 3, 3));
+        let tiles = Corridor::new(CorridorStyle::Routed).carve(
+            &mut rng,
+            &mut map,
+            UVec2::new(2, 4),
+            UVec2::new(26, 4),
+        );
+        assert!(tiles.iter().any(|p| p.y == 10));
+        assert!(tiles
+            .iter()
+            .all(|p| p.x < 11 || p.x > 17 || p.y < 1 || p.y > 9));
+        assert_eq!(map.corridors, vec![tiles]);
+    }
+
+    #[test]
+    fn test_width() {
+        let mut rng = Rng::with_seed(7);
+        let mut map = RoomsMap::new(20, 10);
+        let tiles = Corridor::new(CorridorStyle::Straight).with_width(3).carve(
+            &mut rng,
+            &mut map,
+            UVec2::new(5, 5),
+            UVec2::new(14, 5),
+        );
+        assert_eq!(tiles.len(), 3 * 12);
+        assert!(map.is_walkable(4, 4) && map.is_walkable(15, 6));
+        assert!(map.is_blocked(5, 7));
+    }
+}
@@ -0,0 +1,194 @@
+//! Connect rooms with the minimum spanning tree and a few extra loops.
+//!
+//! Candidate connections are taken from the relative neighbourhood graph of the room centers:
+//! rooms are connected if there is no other room closer to both of them.
+//! Minimum spanning tree of this graph makes sure that all rooms are connected,
+//! and the given fraction of the remaining edges is added back to create loops.
+//!
+//! Connected rooms are stored in the `connections` field of the map.
+//!
+//! Example generator usage:
+//! ```
+//! use mapgen::rooms::{MstCorridors, SimpleRooms};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let map = SimpleRooms::default().generate(80, 50, &mut rng);
+//! let map = MstCorridors::new().with_loops(0.2).generate(&map, &mut rng);
+//!
+//! assert!(map.connections.len() >= map.rooms.len() - 1);
+//! ```
+//!
+
+use fastrand::Rng;
+
+use super::{Corridor, RoomsMap};
+use crate::graph::DisjointSet;
+
+pub struct MstCorridors {
+    loops: f32,
+    corridor: Corridor,
+}
+
+impl MstCorridors {
+    pub fn new() -> Self {
+        Self {
+            loops: 0.15,
+            corridor: Corridor::default(),
+        }
+    }
+
+    /// Fraction of the non tree edges which are added as loops (0..1)
+    pub fn with_loops(mut self, loops: f32) -> Self {
+        self.loops = loops.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Style and width of the corridors
+    pub fn with_corridor(mut self, corridor: Corridor) -> Self {
+        self.corridor = corridor;
+        self
+    }
+
+    pub fn generate(&self, map: &RoomsMap, rng: &mut Rng) -> RoomsMap {
+        let mut new_map = map.clone();
+        for (a, b) in self.room_graph(map, rng) {
+            let from = map.closest_room_tile(a, map.rooms[a].center());
+            let to = map.closest_room_tile(b, map.rooms[b].center());
+            self.corridor.carve(rng, &mut new_map, from, to);
+            new_map.connections.push((a, b));
+        }
+        new_map
+    }
+
+    /// Pairs of rooms which should be connected
+    pub fn room_graph(&self, map: &RoomsMap, rng: &mut Rng) -> Vec<(usize, usize)> {
+        let mut edges = neighbourhood_graph(map);
+        edges.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+        let mut sets = DisjointSet::new(map.rooms.len());
+        let mut graph = Vec::new();
+        let mut extra = Vec::new();
+        for (a, b, _) in edges {
+            if sets.union(a, b) {
+                graph.push((a, b));
+            } else {
+                extra.push((a, b));
+            }
+        }
+
+        let count = (self.loops * extra.len() as f32).round() as usize;
+        rng.shuffle(&mut extra);
+        graph.extend(extra.into_iter().take(count));
+        graph
+    }
+}
+
+impl Default for MstCorridors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Relative neighbourhood graph of the room centers with the edge lengths
+fn neighbourhood_graph(map: &RoomsMap) -> Vec<(usize, usize, f32)> {
+    let centers: Vec<_> = map.rooms.iter().map(|r| r.center().as_vec2()).collect();
+    let mut edges = Vec::new();
+    for (i, a) in centers.iter().enumerate() {
+        for (j, b) in centers.iter().enumerate().skip(i + 1) {
+            let distance = a.distance(*b);
+            let blocked = centers.iter().enumerate().any(|(k, c)| {
+                k != i && k != j && a.distance(*c) < distance && b.distance(*c) < distance
+            });
+            if !blocked {
+                edges.push((i, j, distance));
+            }
+        }
+    }
+    edges
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Rect;
+    use crate::layer::WalkableLayer;
+    use crate::path::DijkstraMap;
+    use crate::rooms::{RoomShape, SimpleRooms};
+
+    #[test]
+    fn test_neighbourhood() {
+        let mut map = RoomsMap::new(40, 20);
+        map.add_room(Rect::new(1, 1, 3, 3));
+        map.add_room(Rect::new(11, 1, 3, 3));
+        map.add_room(Rect::new(21, 1, 3, 3));
+        map.add_room(Rect::new(11, 11, 3, 3));
+        let edges: Vec<(usize, usize)> = neighbourhood_graph(&map)
+            .iter()
+            .map(|&(a, b, _)| (a, b))
+            .collect();
+        assert_eq!(edges, vec![(0, 1), (1, 2), (1, 3)]);
+    }
+
+    #[test]
+    fn test_tree() {
+        let mut rng = Rng::with_seed(10);
+        let map = SimpleRooms::default().generate(80, 50, &mut rng);
+        let map = MstCorridors::new().with_loops(0.0).generate(&map, &mut rng);
+        assert_eq!(map.connections.len(), map.rooms.len() - 1);
+
+        let dijkstra = DijkstraMap::new(&map.walkable_layer, &map.rooms[0].center());
+        for room in map.rooms.iter() {
+            let c = room.center();
+            assert!(dijkstra.tiles[map.xy_idx(c.x, c.y)] < f32::MAX);
+        }
+    }
+
+    #[test]
+    fn test_loops() {
+        let mut rng = Rng::with_seed(10);
+        let map = SimpleRooms::default().generate(80, 50, &mut rng);
+        let tree = MstCorridors::new()
+            .with_loops(0.0)
+            .room_graph(&map, &mut rng);
+        let all = MstCorridors::new()
+            .with_loops(1.0)
+            .room_graph(&map, &mut rng);
+        assert_eq!(all[..tree.len()], tree[..]);
+        assert_eq!(all.len(), neighbourhood_graph(&map).len());
+    }
+
+    #[test]
+    fn test_rooms_without_center() {
+        // Prefab with the floor only in the corner
+        let corner = WalkableLayer::from_string(
+            "
+            #######
+            #  ####
+            #  ####
+            #######
+            #######
+            #######
+            ",
+        );
+        let mut rng = Rng::with_seed(10);
+        let map = SimpleRooms::default()
+            .with_shapes(vec![RoomShape::Prefab(corner)])
+            .generate(80, 50, &mut rng);
+        assert!(map
+            .rooms
+            .iter()
+            .all(|r| !map.is_walkable(r.center().x, r.center().y)));
+        let map = MstCorridors::new().with_loops(0.0).generate(&map, &mut rng);
+
+        let dijkstra = DijkstraMap::new(&map.walkable_layer, &map.room_tiles[0][0]);
+        for tiles in map.room_tiles.iter() {
+            assert!(tiles
+                .iter()
+                .all(|p| dijkstra.tiles[map.xy_idx(p.x, p.y)] < f32::MAX));
+        }
+    }
+}
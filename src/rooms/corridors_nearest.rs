@@ -33,8 +33,11 @@ impl NearestCorridors {
 
             if !room_distance.is_empty() {
                 room_distance.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
-                let dest_center = map.rooms[room_distance[0].0].center();
-                new_map.add_corridor(room_center, dest_center);
+                let dest = room_distance[0].0;
+                // Rooms with the shape may not contain their centers
+                let from = map.closest_room_tile(i, room_center);
+                let to = map.closest_room_tile(dest, map.rooms[dest].center());
+                new_map.add_corridor(from, to);
                 connected.insert(i);
             }
         }
@@ -48,6 +51,10 @@ impl NearestCorridors {
 /// ------------------------------------------------------------------------------------------------
 #[cfg(test)]
 mod tests {
+    use fastrand::Rng;
+    use glam::UVec2;
+
+    use crate::rooms::{RoomShape, SimpleRooms};
     use crate::{geometry::Rect, layer::WalkableLayer};
 
     use super::*;
@@ -73,4 +80,27 @@ mod tests {
 
         assert_eq!(map.walkable_layer, expected);
     }
+
+    #[test]
+    fn test_shaped_rooms() {
+        let mut rng = Rng::with_seed(5);
+        let map = SimpleRooms::default()
+            .with_shapes(vec![RoomShape::Circle])
+            .generate(80, 50, &mut rng);
+        let map = NearestCorridors::new().generate(&map);
+        let stored: HashSet<UVec2> = map
+            .room_tiles
+            .iter()
+            .chain(map.corridors.iter())
+            .flatten()
+            .copied()
+            .collect();
+        for y in 0..map.height {
+            for x in 0..map.width {
+                if map.is_walkable(x, y) {
+                    assert!(stored.contains(&UVec2::new(x, y)), "({}, {})", x, y);
+                }
+            }
+        }
+    }
 }
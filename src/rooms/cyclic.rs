@@ -0,0 +1,313 @@
+//! Cyclic dungeon generator.
+//!
+//! Mission graph is created with the [Grammar], and then embedded into the map.
+//! Rooms are placed on the grid, next to the connected rooms when possible.
+//! Each graph edge becomes the corridor which goes around other rooms, so the corridors
+//! don't create connections which are not in the graph.
+//!
+//! Generator returns the map together with the graph, which knows the room of each node,
+//! the corridor of each edge and the door positions of locked connections and shortcuts.
+//!
+//! Example generator usage:
+//! ```
+//! use mapgen::rooms::CyclicDungeon;
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let gen = CyclicDungeon::default();
+//! let (map, graph) = gen.generate(80, 50, &mut rng);
+//!
+//! assert_eq!(map.width, 80);
+//! assert_eq!(map.rooms.len(), graph.nodes.len());
+//! ```
+//!
+
+use std::collections::VecDeque;
+
+use fastrand::Rng;
+use glam::{IVec2, UVec2};
+
+use super::{Connection, Grammar, MissionGraph, RoomsMap};
+use crate::geometry::{four_connected, Rect};
+use crate::layer::DataLayer;
+use crate::path::DijkstraMap;
+
+/// Cost of moving along existing corridors. Merged corridors would join unconnected rooms
+const CORRIDOR_COST: f32 = 20.0;
+
+pub struct CyclicDungeon {
+    grammar: Grammar,
+    max_expansions: u32,
+    cell_size: u32,
+}
+
+impl CyclicDungeon {
+    pub fn new(grammar: Grammar) -> Self {
+        Self {
+            grammar,
+            max_expansions: 30,
+            cell_size: 11,
+        }
+    }
+
+    /// Maximum number of rules applied to the graph
+    pub fn with_max_expansions(mut self, max_expansions: u32) -> Self {
+        self.max_expansions = max_expansions;
+        self
+    }
+
+    /// Size of the grid cell with a single room
+    pub fn with_cell_size(mut self, cell_size: u32) -> Self {
+        self.cell_size = u32::max(cell_size, 9);
+        self
+    }
+
+    /// Generate the map. Nodes which don't fit into the map have no room.
+    pub fn generate(
+        &self,
+        map_width: u32,
+        map_height: u32,
+        rng: &mut Rng,
+    ) -> (RoomsMap, MissionGraph) {
+        let mut map = RoomsMap::new(map_width, map_height);
+        let mut graph = self.grammar.generate(rng, self.max_expansions);
+        let cols = (map_width.saturating_sub(2) / self.cell_size) as i32;
+        let rows = (map_height.saturating_sub(2) / self.cell_size) as i32;
+        if cols == 0 || rows == 0 || graph.nodes.is_empty() {
+            return (map, graph);
+        }
+
+        let cells = self.place_nodes(rng, &graph, cols, rows);
+        for (node, cell) in graph.nodes.iter_mut().zip(cells.iter()) {
+            if let Some(cell) = cell {
+                let max_size = self.cell_size - 6;
+                let w = rng.u32(3..=max_size);
+                let h = rng.u32(3..=max_size);
+                let x = 1 + cell.x as u32 * self.cell_size + 3 + rng.u32(0..=max_size - w);
+                let y = 1 + cell.y as u32 * self.cell_size + 3 + rng.u32(0..=max_size - h);
+                map.add_room(Rect::new(x, y, w, h));
+                node.room = Some(map.rooms.len() - 1);
+            }
+        }
+
+        for i in 0..graph.edges.len() {
+            let edge = &graph.edges[i];
+            let (Some(from), Some(to)) = (graph.nodes[edge.from].room, graph.nodes[edge.to].room)
+            else {
+                continue;
+            };
+            let corridor = route_corridor(&map, from, to);
+            if corridor.is_empty() {
+                continue;
+            }
+            for p in corridor.iter() {
+                map.set_walkable(p.x, p.y, true);
+            }
+            let edge = &mut graph.edges[i];
+            if edge.connection != Connection::Open {
+                edge.door = door_position(&corridor, &map.rooms[from], &map.rooms[to]);
+            }
+            edge.corridor = Some(map.corridors.len());
+            map.corridors.push(corridor);
+        }
+
+        (map, graph)
+    }
+
+    /// Grid cell of each node. Nodes are placed next to the already placed neighbours.
+    fn place_nodes(
+        &self,
+        rng: &mut Rng,
+        graph: &MissionGraph,
+        cols: i32,
+        rows: i32,
+    ) -> Vec<Option<IVec2>> {
+        let mut neighbors = vec![Vec::new(); graph.nodes.len()];
+        for edge in graph.edges.iter() {
+            neighbors[edge.from].push(edge.to);
+            neighbors[edge.to].push(edge.from);
+        }
+        let mut cells: Vec<Option<IVec2>> = vec![None; graph.nodes.len()];
+        let mut used = vec![false; (cols * rows) as usize];
+        let in_grid = |c: IVec2| c.x >= 0 && c.y >= 0 && c.x < cols && c.y < rows;
+        let idx = |c: IVec2| (c.y * cols + c.x) as usize;
+
+        let start = IVec2::new(0, rng.i32(0..rows));
+        cells[0] = Some(start);
+        used[idx(start)] = true;
+        let mut queue = VecDeque::from([0]);
+        while let Some(node) = queue.pop_front() {
+            let Some(cell) = cells[node] else {
+                continue;
+            };
+            for &n in neighbors[node].iter() {
+                if cells[n].is_some() {
+                    continue;
+                }
+                // Free cell next to the parent, touching most of the placed neighbours
+                let score = |c: IVec2| {
+                    neighbors[n]
+                        .iter()
+                        .filter_map(|&m| cells[m])
+                        .filter(|m| (*m - c).abs().element_sum() == 1)
+                        .count()
+                };
+                let mut candidates: Vec<IVec2> = [IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y]
+                    .iter()
+                    .map(|&d| cell + d)
+                    .filter(|&c| in_grid(c) && !used[idx(c)])
+                    .collect();
+                rng.shuffle(&mut candidates);
+                let mut best = candidates.into_iter().max_by_key(|&c| score(c));
+                if best.is_none() {
+                    // Closest free cell
+                    best = (0..cols * rows)
+                        .map(|i| IVec2::new(i % cols, i / cols))
+                        .filter(|&c| !used[idx(c)])
+                        .min_by_key(|&c| (c - cell).abs().element_sum());
+                }
+                if let Some(c) = best {
+                    cells[n] = Some(c);
+                    used[idx(c)] = true;
+                    queue.push_back(n);
+                }
+            }
+        }
+        cells
+    }
+}
+
+impl Default for CyclicDungeon {
+    fn default() -> Self {
+        Self::new(Grammar::default())
+    }
+}
+
+/// Cheapest path between the rooms, which stays away from other rooms and corridors.
+/// Returns the corridor tiles outside of both rooms.
+fn route_corridor(map: &RoomsMap, from: usize, to: usize) -> Vec<UVec2> {
+    let mut costs = DataLayer::new(map.width, map.height, 1.0);
+    for p in map.corridors.iter().flatten() {
+        for (dx, dy) in [(0, 0), (1, 0), (-1, 0), (0, 1), (0, -1)] {
+            let (x, y) = (p.x as i32 + dx, p.y as i32 + dy);
+            if x >= 0 && y >= 0 {
+                costs.set(x as u32, y as u32, CORRIDOR_COST);
+            }
+        }
+    }
+    for (i, room) in map.rooms.iter().enumerate() {
+        if i == from || i == to {
+            continue;
+        }
+        for y in room.y1.saturating_sub(2)..room.y2 + 2 {
+            for x in room.x1.saturating_sub(2)..room.x2 + 2 {
+                costs.set(x, y, f32::INFINITY);
+            }
+        }
+    }
+    for x in 0..map.width {
+        costs.set(x, 0, f32::INFINITY);
+        costs.set(x, map.height - 1, f32::INFINITY);
+    }
+    for y in 0..map.height {
+        costs.set(0, y, f32::INFINITY);
+        costs.set(map.width - 1, y, f32::INFINITY);
+    }
+
+    let (a, b) = (map.rooms[from], map.rooms[to]);
+    let path = DijkstraMap::with_costs(&costs, &a.center()).path_to(&b.center());
+    let inside = |p: &UVec2| {
+        [a, b]
+            .iter()
+            .any(|r| p.x >= r.x1 && p.x < r.x2 && p.y >= r.y1 && p.y < r.y2)
+    };
+    // Corridor starts and ends at the room edges, diagonal steps get the corner tile
+    let outside: Vec<UVec2> = path.into_iter().filter(|p| !inside(p)).collect();
+    four_connected(&outside)
+        .into_iter()
+        .filter(|p| !inside(p))
+        .collect()
+}
+
+/// Corridor tile closest to the target room, which splits the corridor into 2 parts.
+/// Stairs made by the diagonal steps touch each other, so not every tile can be the door.
+fn door_position(corridor: &[UVec2], from: &Rect, to: &Rect) -> Option<UVec2> {
+    let touches = |a: &UVec2, b: &UVec2| a.x.abs_diff(b.x) <= 1 && a.y.abs_diff(b.y) <= 1;
+    let touches_room =
+        |p: &UVec2, r: &Rect| p.x + 1 >= r.x1 && p.x <= r.x2 && p.y + 1 >= r.y1 && p.y <= r.y2;
+    (0..corridor.len()).rev().find_map(|i| {
+        let (before, after) = (&corridor[..i], &corridor[i + 1..]);
+        let split = before
+            .iter()
+            .all(|p| !touches_room(p, to) && after.iter().all(|q| !touches(p, q)))
+            && after.iter().all(|q| !touches_room(q, from));
+        split.then_some(corridor[i])
+    })
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layer::WalkableLayer;
+
+    /// Rooms reachable from the first room when the given tiles are blocked
+    fn reachable_rooms(map: &RoomsMap, blocked: &[UVec2]) -> Vec<bool> {
+        let mut layer: WalkableLayer = map.walkable_layer.clone();
+        for p in blocked {
+            layer.set_walkable(p.x, p.y, false);
+        }
+        let dijkstra = DijkstraMap::new(&layer, &map.rooms[0].center());
+        map.rooms
+            .iter()
+            .map(|r| dijkstra.tiles[map.xy_idx(r.center().x, r.center().y)] < f32::MAX)
+            .collect()
+    }
+
+    #[test]
+    fn test_embedding() {
+        let mut rng = Rng::with_seed(100);
+        let (map, graph) = CyclicDungeon::default().generate(80, 50, &mut rng);
+        assert!(graph.nodes.iter().all(|n| n.room.is_some()));
+        assert!(graph.edges.iter().all(|e| e.corridor.is_some()));
+        for (i, room) in map.rooms.iter().enumerate() {
+            for other in map.rooms.iter().skip(i + 1) {
+                assert!(!room.intersect(other));
+            }
+        }
+        assert!(reachable_rooms(&map, &[]).iter().all(|&r| r));
+    }
+
+    #[test]
+    fn test_locked_doors() {
+        for seed in 0..10 {
+            let mut rng = Rng::with_seed(seed);
+            let (map, graph) = CyclicDungeon::default().generate(80, 50, &mut rng);
+            let doors: Vec<UVec2> = graph
+                .edges
+                .iter()
+                .filter(|e| matches!(e.connection, Connection::Locked(_)))
+                .filter_map(|e| e.door)
+                .collect();
+            // Without the keys, player can get only to the rooms reachable in the graph
+            let mut open_graph = graph.clone();
+            open_graph
+                .edges
+                .retain(|e| !matches!(e.connection, Connection::Locked(_)));
+            for e in open_graph.edges.iter_mut() {
+                e.connection = Connection::Open;
+            }
+            for n in open_graph.nodes.iter_mut() {
+                n.key = None;
+            }
+            let expected = open_graph.reachable();
+            let reachable = reachable_rooms(&map, &doors);
+            for (node, &r) in graph.nodes.iter().zip(expected.iter()) {
+                let room = node.room.unwrap();
+                assert!(r || !reachable[room], "seed {}", seed);
+            }
+        }
+    }
+}
@@ -0,0 +1,251 @@
+//! Doors placed where the corridors enter the rooms.
+//!
+//! Door tile is the corridor tile next to the room, where the corridor leads away from the room.
+//! Corridors running along the room wall don't get the doors.
+//! Tiles of the same wide entrance share the door kind.
+//!
+//! Example usage:
+//! ```
+//! use mapgen::rooms::{DoorPlacement, NearestCorridors, SimpleRooms};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let map = SimpleRooms::default().generate(80, 50, &mut rng);
+//! let map = NearestCorridors::new().generate(&map);
+//! let doors = DoorPlacement::new().with_chokepoints_only(true).generate(&map, &mut rng);
+//!
+//! assert_eq!(doors.len(), map.rooms.len());
+//! ```
+//!
+
+use std::collections::HashMap;
+
+use fastrand::Rng;
+use glam::{IVec2, UVec2};
+
+use super::RoomsMap;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum DoorKind {
+    Open,
+    Closed,
+    Locked,
+    /// Looks like the wall
+    Secret,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct Door {
+    pub position: UVec2,
+    pub kind: DoorKind,
+}
+
+pub struct DoorPlacement {
+    door_chance: f32,
+    chokepoints_only: bool,
+    /// Weights of the Open, Closed, Locked and Secret doors
+    weights: [f32; 4],
+}
+
+impl DoorPlacement {
+    pub fn new() -> Self {
+        Self {
+            door_chance: 1.0,
+            chokepoints_only: false,
+            weights: [0.3, 0.5, 0.1, 0.1],
+        }
+    }
+
+    /// Chance that the entrance gets the door (0..1)
+    pub fn with_door_chance(mut self, door_chance: f32) -> Self {
+        self.door_chance = door_chance.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Place doors only in the entrances which are 1 tile wide
+    pub fn with_chokepoints_only(mut self, chokepoints_only: bool) -> Self {
+        self.chokepoints_only = chokepoints_only;
+        self
+    }
+
+    /// Relative probabilities of the door kinds
+    pub fn with_kind_weights(mut self, open: f32, closed: f32, locked: f32, secret: f32) -> Self {
+        self.weights = [open, closed, locked, secret].map(|w| w.max(0.0));
+        self
+    }
+
+    /// Doors of each room. Door between 2 rooms is reported for both of them.
+    pub fn generate(&self, map: &RoomsMap, rng: &mut Rng) -> Vec<Vec<Door>> {
+        let mut doors = vec![Vec::new(); map.rooms.len()];
+        // Decided kind for each entrance tile
+        let mut kinds: HashMap<UVec2, Option<DoorKind>> = HashMap::new();
+        let rooms = map.room_index();
+        for y in 1..map.height.saturating_sub(1) {
+            for x in 1..map.width.saturating_sub(1) {
+                let p = UVec2::new(x, y);
+                if map.is_blocked(x, y) || rooms[map.xy_idx(x, y)].is_some() {
+                    continue;
+                }
+                for (room, dir) in entrances(map, &rooms, p) {
+                    if self.chokepoints_only && !is_chokepoint(map, p, dir) {
+                        continue;
+                    }
+                    let kind = match kinds.get(&p) {
+                        Some(&kind) => kind,
+                        None => {
+                            // Continue the entrance found on the left or above
+                            let kind = [UVec2::new(x - 1, y), UVec2::new(x, y - 1)]
+                                .iter()
+                                .find_map(|n| kinds.get(n).copied())
+                                .unwrap_or_else(|| self.choose_kind(rng));
+                            kinds.insert(p, kind);
+                            kind
+                        }
+                    };
+                    if let Some(kind) = kind {
+                        doors[room].push(Door { position: p, kind });
+                    }
+                }
+            }
+        }
+        doors
+    }
+
+    fn choose_kind(&self, rng: &mut Rng) -> Option<DoorKind> {
+        if rng.f32() >= self.door_chance {
+            return None;
+        }
+        let kinds = [
+            DoorKind::Open,
+            DoorKind::Closed,
+            DoorKind::Locked,
+            DoorKind::Secret,
+        ];
+        let mut roll = rng.f32() * self.weights.iter().sum::<f32>();
+        for (kind, weight) in kinds.iter().zip(self.weights.iter()) {
+            if roll < *weight {
+                return Some(*kind);
+            }
+            roll -= weight;
+        }
+        Some(DoorKind::Closed)
+    }
+}
+
+impl Default for DoorPlacement {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Rooms next to the corridor tile, where the corridor continues on the opposite side.
+/// `room_index` is the room of each tile from [RoomsMap::room_index].
+/// Returns room index and direction towards the room.
+fn entrances(map: &RoomsMap, room_index: &[Option<usize>], p: UVec2) -> Vec<(usize, IVec2)> {
+    let mut rooms = Vec::new();
+    for dir in [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y] {
+        let inner = (p.as_ivec2() + dir).as_uvec2();
+        let outer = (p.as_ivec2() - dir).as_uvec2();
+        if let Some(room) = room_index[map.xy_idx(inner.x, inner.y)] {
+            if map.is_walkable(outer.x, outer.y) && !rooms.iter().any(|&(r, _)| r == room) {
+                rooms.push((room, dir));
+            }
+        }
+    }
+    rooms
+}
+
+/// Tiles on both sides of the entrance are walls
+fn is_chokepoint(map: &RoomsMap, p: UVec2, dir: IVec2) -> bool {
+    let side = IVec2::new(dir.y, dir.x);
+    [p.as_ivec2() + side, p.as_ivec2() - side]
+        .iter()
+        .all(|s| map.is_blocked(s.x as u32, s.y as u32))
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Rect;
+    use crate::rooms::{NearestCorridors, RoomShape, SimpleRooms};
+
+    fn test_map() -> RoomsMap {
+        let mut map = RoomsMap::new(16, 9);
+        map.add_room(Rect::new(1, 1, 4, 4));
+        map.add_room(Rect::new(10, 1, 5, 7));
+        // 1 tile corridor between the rooms and 2 tiles wide corridor going down
+        map.add_corridor(UVec2::new(2, 2), UVec2::new(11, 2));
+        map.carve_path(&[UVec2::new(2, 4), UVec2::new(2, 5), UVec2::new(2, 6)], 2);
+        map
+    }
+
+    #[test]
+    fn test_doors() {
+        let map = test_map();
+        let mut rng = Rng::with_seed(1);
+        let doors = DoorPlacement::new().generate(&map, &mut rng);
+        let positions: Vec<Vec<UVec2>> = doors
+            .iter()
+            .map(|d| d.iter().map(|d| d.position).collect())
+            .collect();
+        assert_eq!(
+            positions,
+            vec![
+                vec![UVec2::new(5, 2), UVec2::new(2, 5), UVec2::new(3, 5)],
+                vec![UVec2::new(9, 2)]
+            ]
+        );
+        // Wide entrance has the same door
+        assert_eq!(doors[0][1].kind, doors[0][2].kind);
+    }
+
+    #[test]
+    fn test_chokepoints() {
+        let map = test_map();
+        let mut rng = Rng::with_seed(1);
+        let doors = DoorPlacement::new()
+            .with_chokepoints_only(true)
+            .with_kind_weights(0.0, 0.0, 0.0, 1.0)
+            .generate(&map, &mut rng);
+        let expected = |x| {
+            vec![Door {
+                position: UVec2::new(x, 2),
+                kind: DoorKind::Secret,
+            }]
+        };
+        assert_eq!(doors, vec![expected(5), expected(9)]);
+    }
+
+    #[test]
+    fn test_door_chance() {
+        let map = test_map();
+        let mut rng = Rng::with_seed(1);
+        let doors = DoorPlacement::new()
+            .with_door_chance(0.0)
+            .generate(&map, &mut rng);
+        assert!(doors.iter().all(|d| d.is_empty()));
+    }
+
+    #[test]
+    fn test_shaped_rooms() {
+        let mut rng = Rng::with_seed(5);
+        let map = SimpleRooms::default()
+            .with_shapes(vec![RoomShape::Circle])
+            .generate(80, 50, &mut rng);
+        let map = NearestCorridors::new().generate(&map);
+        let doors = DoorPlacement::new().generate(&map, &mut rng);
+        assert!(doors.iter().any(|d| !d.is_empty()));
+        for (room, doors) in doors.iter().enumerate() {
+            for door in doors.iter() {
+                let p = door.position.as_ivec2();
+                let touches_floor = [IVec2::X, IVec2::NEG_X, IVec2::Y, IVec2::NEG_Y]
+                    .iter()
+                    .any(|&d| map.room_tiles[room].contains(&(p + d).as_uvec2()));
+                assert!(touches_floor, "{:?}", door);
+            }
+        }
+    }
+}
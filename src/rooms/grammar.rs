@@ -0,0 +1,583 @@
+//! Mission graph generated with the graph grammar
+//! (as in Joris Dormans' work and Unexplored cyclic generation).
+//!
+//! Graph starts from the axiom. Each node with the label which has rules (non-terminal)
+//! is replaced with the subgraph from the randomly chosen rule. Edges coming to the replaced
+//! node are connected to the `in` node of the rule, and edges leaving it to the `out` node.
+//! Rules can create cycles, locked connections with the matching keys and shortcuts.
+//!
+//! Grammar is written in the text format (e.g. loaded from the data file):
+//! ```text
+//! # Comment
+//! start
+//! node s Start
+//! node d Dungeon
+//! node g Goal
+//! edge s d
+//! edge d g
+//! end
+//!
+//! rule Dungeon 2          # Rule for the label with the optional weight
+//! node a Room
+//! node k Room key red     # Room with the key. Key names are local to the rule
+//! node b Room
+//! edge a k
+//! edge a b lock red       # Locked connection, key has to be in the same block
+//! edge b a shortcut       # One-way connection
+//! in a                    # Defaults to the first node
+//! out b                   # Defaults to the last node
+//! end
+//! ```
+//!
+//! Example usage:
+//! ```
+//! use mapgen::rooms::Grammar;
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let grammar = Grammar::default();
+//! let graph = grammar.generate(&mut rng, 20);
+//!
+//! assert_eq!(graph.nodes[0].label, "Start");
+//! assert!(graph.is_solvable());
+//! ```
+//!
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use fastrand::Rng;
+use glam::UVec2;
+
+const DEFAULT_GRAMMAR: &str = "
+start
+node s Start
+node d1 Dungeon
+node d2 Dungeon
+node g Goal
+edge s d1
+edge d1 d2
+edge d2 g
+end
+
+# Key hidden in the side branch opens the way forward
+rule Dungeon 2
+node a Section
+node k Room key k
+node b Room
+edge a k
+edge a b lock k
+end
+
+# Two paths between the same rooms
+rule Dungeon 2
+node a Room
+node b Section
+node c Section
+node d Room
+edge a b
+edge a c
+edge b d
+edge c d
+in a
+out d
+end
+
+# Long way around with the shortcut back
+rule Dungeon 1
+node a Room
+node b Section
+node c Room
+edge a b
+edge b c
+edge c a shortcut
+in a
+out c
+end
+
+rule Section 2
+node a Room
+node b Room
+edge a b
+end
+
+rule Section 1
+node a Room
+end
+";
+
+/// How the rooms are connected
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Connection {
+    Open,
+    /// Needs the key with the given id
+    Locked(u32),
+    /// Can be passed only from the `from` node to the `to` node
+    Shortcut,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MissionNode {
+    pub label: String,
+    /// Key placed in this room
+    pub key: Option<u32>,
+    /// Index of the room in the map (after embedding)
+    pub room: Option<usize>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct MissionEdge {
+    pub from: usize,
+    pub to: usize,
+    pub connection: Connection,
+    /// Index of the corridor in the map (after embedding)
+    pub corridor: Option<usize>,
+    /// Position of the door for the locked connections and shortcuts (after embedding)
+    pub door: Option<UVec2>,
+}
+
+/// Graph of rooms. The first node is the entrance to the dungeon
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MissionGraph {
+    pub nodes: Vec<MissionNode>,
+    pub edges: Vec<MissionEdge>,
+}
+
+impl MissionGraph {
+    /// Index of the first node with the given label
+    pub fn find(&self, label: &str) -> Option<usize> {
+        self.nodes.iter().position(|n| n.label == label)
+    }
+
+    /// Nodes which can be reached from the start, collecting keys on the way
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.nodes.len()];
+        if self.nodes.is_empty() {
+            return reachable;
+        }
+        reachable[0] = true;
+        let mut changed = true;
+        while changed {
+            changed = false;
+            let keys: Vec<u32> = self
+                .nodes
+                .iter()
+                .zip(reachable.iter())
+                .filter(|(_, &r)| r)
+                .filter_map(|(n, _)| n.key)
+                .collect();
+            for edge in self.edges.iter() {
+                let passable = match edge.connection {
+                    Connection::Open | Connection::Shortcut => true,
+                    Connection::Locked(key) => keys.contains(&key),
+                };
+                if !passable {
+                    continue;
+                }
+                if reachable[edge.from] && !reachable[edge.to] {
+                    reachable[edge.to] = true;
+                    changed = true;
+                }
+                if edge.connection != Connection::Shortcut
+                    && reachable[edge.to]
+                    && !reachable[edge.from]
+                {
+                    reachable[edge.from] = true;
+                    changed = true;
+                }
+            }
+        }
+        reachable
+    }
+
+    /// All rooms can be reached from the start without getting stuck behind the locked door
+    pub fn is_solvable(&self) -> bool {
+        self.reachable().iter().all(|&r| r)
+    }
+}
+
+/// Line number and its words
+type Line = (usize, Vec<String>);
+
+/// Graph pattern used as the axiom or the right side of the rule
+#[derive(Clone, Debug, PartialEq)]
+struct Pattern {
+    /// Local name, label and key name
+    nodes: Vec<(String, String, Option<String>)>,
+    /// From, to, connection with the local key name
+    edges: Vec<(usize, usize, Option<String>, bool)>,
+    input: usize,
+    output: usize,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+struct Rule {
+    label: String,
+    weight: u32,
+    pattern: Pattern,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Grammar {
+    axiom: Pattern,
+    rules: Vec<Rule>,
+}
+
+impl Grammar {
+    /// Expand the axiom. Non-terminal nodes left after `max_expansions` keep their labels.
+    pub fn generate(&self, rng: &mut Rng, max_expansions: u32) -> MissionGraph {
+        let mut graph = MissionGraph::default();
+        let mut next_key = 0;
+        add_pattern(&mut graph, &self.axiom, None, &mut next_key);
+
+        for _ in 0..max_expansions {
+            let open: Vec<usize> = (0..graph.nodes.len())
+                .filter(|&i| self.rules.iter().any(|r| r.label == graph.nodes[i].label))
+                .collect();
+            if open.is_empty() {
+                break;
+            }
+            let node = open[rng.usize(0..open.len())];
+            let rule = self.choose_rule(rng, &graph.nodes[node].label);
+            add_pattern(&mut graph, &rule.pattern, Some(node), &mut next_key);
+        }
+        graph
+    }
+
+    fn choose_rule(&self, rng: &mut Rng, label: &str) -> &Rule {
+        let rules: Vec<&Rule> = self.rules.iter().filter(|r| r.label == label).collect();
+        let total: u32 = rules.iter().map(|r| r.weight).sum();
+        let mut pick = rng.u32(0..total.max(1));
+        for rule in rules.iter() {
+            if pick < rule.weight {
+                return rule;
+            }
+            pick -= rule.weight;
+        }
+        rules[0]
+    }
+}
+
+/// Add nodes and edges of the pattern to the graph.
+/// If the node is replaced, the pattern input takes its place (so the indexes don't change)
+/// and the edges leaving the replaced node start from the pattern output.
+fn add_pattern(
+    graph: &mut MissionGraph,
+    pattern: &Pattern,
+    replaced: Option<usize>,
+    next_key: &mut u32,
+) {
+    let mut keys: HashMap<String, u32> = HashMap::new();
+    let mut key_id = |name: &String| {
+        *keys.entry(name.clone()).or_insert_with(|| {
+            *next_key += 1;
+            *next_key
+        })
+    };
+    // Global index of each pattern node
+    let mut indexes = Vec::new();
+    for (i, (_, label, key)) in pattern.nodes.iter().enumerate() {
+        let node = MissionNode {
+            label: label.clone(),
+            key: key.as_ref().map(&mut key_id),
+            room: None,
+        };
+        match replaced {
+            Some(idx) if i == pattern.input => {
+                let old_key = graph.nodes[idx].key;
+                graph.nodes[idx] = node;
+                graph.nodes[idx].key = graph.nodes[idx].key.or(old_key);
+                indexes.push(idx);
+            }
+            _ => {
+                indexes.push(graph.nodes.len());
+                graph.nodes.push(node);
+            }
+        }
+    }
+    if let Some(idx) = replaced {
+        for edge in graph.edges.iter_mut().filter(|e| e.from == idx) {
+            edge.from = indexes[pattern.output];
+        }
+    }
+    for (from, to, lock, shortcut) in pattern.edges.iter() {
+        let connection = match (lock, shortcut) {
+            (Some(key), _) => Connection::Locked(key_id(key)),
+            (None, true) => Connection::Shortcut,
+            (None, false) => Connection::Open,
+        };
+        graph.edges.push(MissionEdge {
+            from: indexes[*from],
+            to: indexes[*to],
+            connection,
+            corridor: None,
+            door: None,
+        });
+    }
+}
+
+impl Default for Grammar {
+    fn default() -> Self {
+        DEFAULT_GRAMMAR.parse().expect("Default grammar is valid")
+    }
+}
+
+impl FromStr for Grammar {
+    type Err = String;
+
+    /// Parse grammar in the text format described in the module docs
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut axiom = None;
+        let mut rules = Vec::new();
+        // Block header and its lines
+        let mut block: Option<(Vec<String>, Vec<Line>)> = None;
+
+        for (line_no, line) in s.lines().enumerate() {
+            let line_no = line_no + 1;
+            let words: Vec<String> = line
+                .split('#')
+                .next()
+                .unwrap_or("")
+                .split_whitespace()
+                .map(|w| w.to_string())
+                .collect();
+            if words.is_empty() {
+                continue;
+            }
+            match (&mut block, words[0].as_str()) {
+                (None, "start") | (None, "rule") => block = Some((words, Vec::new())),
+                (None, _) => return Err(format!("Line {}: expected 'start' or 'rule'", line_no)),
+                (Some(_), "end") => {
+                    let (header, lines) = block.take().unwrap_or_default();
+                    let pattern = parse_pattern(&lines)?;
+                    if header[0] == "start" {
+                        axiom = Some(pattern);
+                    } else {
+                        let label = header
+                            .get(1)
+                            .ok_or(format!("Line {}: rule without the label", line_no))?;
+                        let weight = match header.get(2) {
+                            Some(w) => w
+                                .parse()
+                                .map_err(|_| format!("Line {}: wrong weight: {}", line_no, w))?,
+                            None => 1,
+                        };
+                        rules.push(Rule {
+                            label: label.clone(),
+                            weight,
+                            pattern,
+                        });
+                    }
+                }
+                (Some((_, lines)), _) => lines.push((line_no, words)),
+            }
+        }
+        if block.is_some() {
+            return Err("Missing 'end' of the last block".to_string());
+        }
+        let axiom = axiom.ok_or("Missing 'start' block".to_string())?;
+        Ok(Grammar { axiom, rules })
+    }
+}
+
+fn parse_pattern(lines: &[Line]) -> Result<Pattern, String> {
+    let mut pattern = Pattern {
+        nodes: Vec::new(),
+        edges: Vec::new(),
+        input: 0,
+        output: 0,
+    };
+    let mut input = None;
+    let mut output = None;
+    // Locks with the line numbers, checked after all keys of the block are known
+    let mut locks = Vec::new();
+    for (line_no, words) in lines.iter() {
+        let find = |name: Option<&String>| {
+            name.and_then(|name| pattern.nodes.iter().position(|n| &n.0 == name))
+                .ok_or(format!("Line {}: unknown node", line_no))
+        };
+        match (words[0].as_str(), words.len()) {
+            ("node", 3) => pattern
+                .nodes
+                .push((words[1].clone(), words[2].clone(), None)),
+            ("node", 5) if words[3] == "key" => {
+                pattern
+                    .nodes
+                    .push((words[1].clone(), words[2].clone(), Some(words[4].clone())))
+            }
+            ("edge", 3) => {
+                pattern
+                    .edges
+                    .push((find(words.get(1))?, find(words.get(2))?, None, false))
+            }
+            ("edge", 4) if words[3] == "shortcut" => {
+                pattern
+                    .edges
+                    .push((find(words.get(1))?, find(words.get(2))?, None, true))
+            }
+            ("edge", 5) if words[3] == "lock" => {
+                pattern.edges.push((
+                    find(words.get(1))?,
+                    find(words.get(2))?,
+                    Some(words[4].clone()),
+                    false,
+                ));
+                locks.push((line_no, &words[4]));
+            }
+            ("in", 2) => input = Some(find(words.get(1))?),
+            ("out", 2) => output = Some(find(words.get(1))?),
+            _ => {
+                return Err(format!(
+                    "Line {}: can't parse '{}'",
+                    line_no,
+                    words.join(" ")
+                ))
+            }
+        }
+    }
+    if pattern.nodes.is_empty() {
+        return Err("Block without nodes".to_string());
+    }
+    for (line_no, key) in locks {
+        if !pattern.nodes.iter().any(|n| n.2.as_ref() == Some(key)) {
+            return Err(format!("Line {}: no node with the key '{}'", line_no, key));
+        }
+    }
+    pattern.input = input.unwrap_or(0);
+    pattern.output = output.unwrap_or(pattern.nodes.len() - 1);
+    Ok(pattern)
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let grammar: Grammar = "
+            start
+            node s Start
+            node g Goal key a
+            edge s g lock a
+            end
+            rule Goal 3
+            node x Room key k
+            end
+        "
+        .parse()
+        .unwrap();
+        assert_eq!(grammar.axiom.nodes.len(), 2);
+        assert_eq!(grammar.axiom.edges[0], (0, 1, Some("a".to_string()), false));
+        assert_eq!(grammar.rules[0].weight, 3);
+        assert!("start\nnode s Start\nedge s x\nend"
+            .parse::<Grammar>()
+            .is_err());
+        assert!("start\nnode s Start\n".parse::<Grammar>().is_err());
+    }
+
+    #[test]
+    fn test_lock_without_key() {
+        let result = "start\nnode s Start\nnode g Goal\nedge s g lock a\nend".parse::<Grammar>();
+        assert_eq!(
+            result.err(),
+            Some("Line 4: no node with the key 'a'".to_string())
+        );
+    }
+
+    #[test]
+    fn test_expand() {
+        let grammar: Grammar = "
+            start
+            node s Start
+            node m Middle
+            node g Goal
+            edge s m
+            edge m g
+            end
+            rule Middle
+            node a Room
+            node k Room key red
+            node b Room
+            edge a k
+            edge a b lock red
+            edge b a shortcut
+            in a
+            out b
+            end
+        "
+        .parse()
+        .unwrap();
+        let mut rng = Rng::with_seed(100);
+        let graph = grammar.generate(&mut rng, 10);
+        assert_eq!(graph.nodes.len(), 5);
+        assert!(graph.find("Middle").is_none());
+        let start = graph.find("Start").unwrap();
+        let goal = graph.find("Goal").unwrap();
+        let key = graph.nodes.iter().position(|n| n.key.is_some()).unwrap();
+        let id = graph.nodes[key].key.unwrap();
+        // Start connects to the input, and the output connects to the goal
+        let a = graph.edges.iter().find(|e| e.from == start).unwrap().to;
+        let b = graph.edges.iter().find(|e| e.to == goal).unwrap().from;
+        assert!(graph
+            .edges
+            .iter()
+            .any(|e| e.from == a && e.to == b && e.connection == Connection::Locked(id)));
+        assert!(graph
+            .edges
+            .iter()
+            .any(|e| e.from == b && e.to == a && e.connection == Connection::Shortcut));
+        assert!(graph.is_solvable());
+    }
+
+    #[test]
+    fn test_solvable() {
+        let node = |key| MissionNode {
+            label: "Room".to_string(),
+            key,
+            room: None,
+        };
+        let edge = |from, to, connection| MissionEdge {
+            from,
+            to,
+            connection,
+            corridor: None,
+            door: None,
+        };
+        let mut graph = MissionGraph {
+            nodes: vec![node(None), node(None), node(Some(1))],
+            edges: vec![
+                edge(0, 1, Connection::Open),
+                edge(1, 2, Connection::Locked(1)),
+            ],
+        };
+        // Key is behind its own lock
+        assert!(!graph.is_solvable());
+        graph.edges.push(edge(2, 0, Connection::Shortcut));
+        assert!(!graph.is_solvable());
+        graph.edges.push(edge(0, 2, Connection::Open));
+        assert!(graph.is_solvable());
+    }
+
+    #[test]
+    fn test_default_grammar() {
+        for seed in 0..20 {
+            let mut rng = Rng::with_seed(seed);
+            let graph = Grammar::default().generate(&mut rng, 50);
+            assert!(graph
+                .nodes
+                .iter()
+                .all(|n| n.label != "Dungeon" && n.label != "Section"));
+            assert!(graph.find("Goal").is_some());
+            assert!(graph.is_solvable());
+            // Each lock has its key
+            for e in graph.edges.iter() {
+                if let Connection::Locked(id) = e.connection {
+                    assert!(graph.nodes.iter().any(|n| n.key == Some(id)));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,501 @@
+//! Lock and key puzzle placement.
+//!
+//! Rooms are connected into the graph by the corridors (and by touching each other).
+//! Locked rooms are chosen far away from the start room, and all the corridor tiles at
+//! their entrances become the doors. Each key is placed in the room which can be reached
+//! without this key, so the player can never get stuck.
+//!
+//! Example usage:
+//! ```
+//! use mapgen::rooms::{LockAndKey, NearestCorridors, SimpleRooms};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let map = SimpleRooms::default().generate(80, 50, &mut rng);
+//! let map = NearestCorridors::new().generate(&map);
+//! let puzzle = LockAndKey::new().with_locks(2).generate(&map, 0, &mut rng);
+//!
+//! assert!(puzzle.is_solvable(&map));
+//! assert!(puzzle.graph.is_solvable());
+//! ```
+//!
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use fastrand::Rng;
+use glam::{IVec2, UVec2};
+
+use super::{Connection, MissionEdge, MissionGraph, MissionNode, RoomsMap};
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lock {
+    /// Room behind the locked doors
+    pub room: usize,
+    /// Corridor tiles at all entrances to the room
+    pub doors: Vec<UVec2>,
+    /// Room with the key
+    pub key_room: usize,
+    pub key: UVec2,
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LockPuzzle {
+    pub start_room: usize,
+    /// Locks in the order in which they can be opened. Key id is the index in this list.
+    pub locks: Vec<Lock>,
+    /// Room adjacency graph. Node index is the room index.
+    pub graph: MissionGraph,
+    /// Number of rooms between the start room and the given room
+    pub distances: Vec<Option<usize>>,
+}
+
+impl LockPuzzle {
+    /// Rooms which can be reached from the start room, collecting keys on the way
+    pub fn reachable_rooms(&self, map: &RoomsMap) -> Vec<bool> {
+        let (visited, _) = solve(map, self.start_room, &self.locks, self.locks.len());
+        map.room_tiles
+            .iter()
+            .map(|tiles| tiles.iter().any(|p| visited[map.xy_idx(p.x, p.y)]))
+            .collect()
+    }
+
+    /// All rooms can be reached and all locks opened
+    pub fn is_solvable(&self, map: &RoomsMap) -> bool {
+        let (_, opened) = solve(map, self.start_room, &self.locks, self.locks.len());
+        opened.len() == self.locks.len() && self.reachable_rooms(map).iter().all(|&r| r)
+    }
+}
+
+pub struct LockAndKey {
+    locks: usize,
+}
+
+impl LockAndKey {
+    pub fn new() -> Self {
+        Self { locks: 3 }
+    }
+
+    /// Number of locks to place. There can be less of them if the map is too small.
+    pub fn with_locks(mut self, locks: usize) -> Self {
+        self.locks = locks;
+        self
+    }
+
+    /// Place locks and keys on the connected map
+    pub fn generate(&self, map: &RoomsMap, start_room: usize, rng: &mut Rng) -> LockPuzzle {
+        let mut puzzle = LockPuzzle {
+            start_room,
+            ..Default::default()
+        };
+        if start_room >= map.rooms.len() {
+            return puzzle;
+        }
+        let edges = room_edges(map);
+        puzzle.distances = room_distances(map.rooms.len(), &edges, start_room);
+
+        let mut locks: Vec<Lock> = Vec::new();
+        for _ in 0..self.locks {
+            match self.add_lock(map, start_room, &puzzle.distances, &locks, rng) {
+                Some(lock) => locks.push(lock),
+                None => break,
+            }
+        }
+
+        // Order the locks by the time they are opened
+        let (_, opened) = solve(map, start_room, &locks, locks.len());
+        puzzle.locks = opened.iter().map(|&i| locks[i].clone()).collect();
+        puzzle.graph = build_graph(map, &puzzle, &edges);
+        puzzle
+    }
+
+    fn add_lock(
+        &self,
+        map: &RoomsMap,
+        start_room: usize,
+        distances: &[Option<usize>],
+        locks: &[Lock],
+        rng: &mut Rng,
+    ) -> Option<Lock> {
+        let doors: HashSet<UVec2> = locks.iter().flat_map(|l| l.doors.clone()).collect();
+        let room_index = map.room_index();
+        let mut candidates: Vec<usize> = (0..map.rooms.len())
+            .filter(|&i| i != start_room && distances[i].is_some())
+            .filter(|&i| locks.iter().all(|l| l.room != i))
+            .collect();
+        rng.shuffle(&mut candidates);
+        // Prefer the rooms far away from the start
+        candidates.sort_by_key(|&i| std::cmp::Reverse(distances[i]));
+
+        for room in candidates {
+            let Some(entrances) = room_entrances(map, &room_index, room) else {
+                continue;
+            };
+            if entrances.is_empty() || entrances.iter().any(|p| doors.contains(p)) {
+                continue;
+            }
+            let mut new_locks = locks.to_vec();
+            new_locks.push(Lock {
+                room,
+                doors: entrances,
+                key_room: start_room,
+                key: start_tile(map, start_room),
+            });
+            // The new key is not placed yet
+            let (visited, _) = solve(map, start_room, &new_locks, locks.len());
+            let is_visited = |room: usize| {
+                map.room_tiles[room]
+                    .iter()
+                    .any(|p| visited[map.xy_idx(p.x, p.y)])
+            };
+            if is_visited(room) {
+                continue;
+            }
+            let mut key_rooms: Vec<usize> = (0..map.rooms.len())
+                .filter(|&i| is_visited(i))
+                .filter(|&i| locks.iter().all(|l| l.key_room != i))
+                .collect();
+            rng.shuffle(&mut key_rooms);
+            key_rooms.sort_by_key(|&i| std::cmp::Reverse(distances[i]));
+            let Some(&key_room) = key_rooms.first() else {
+                continue;
+            };
+            let tiles: Vec<UVec2> = map.room_tiles[key_room]
+                .iter()
+                .filter(|p| visited[map.xy_idx(p.x, p.y)])
+                .copied()
+                .collect();
+            let lock = new_locks.last_mut().unwrap();
+            lock.key_room = key_room;
+            lock.key = tiles[rng.usize(0..tiles.len())];
+            return new_locks.pop();
+        }
+        None
+    }
+}
+
+impl Default for LockAndKey {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Floor tile of the start room where the player begins
+fn start_tile(map: &RoomsMap, start_room: usize) -> UVec2 {
+    map.closest_room_tile(start_room, map.rooms[start_room].center())
+}
+
+/// Tiles next to the room floor (in 8 directions), ordered by rows.
+/// `room_index` is the room of each tile from [RoomsMap::room_index].
+fn room_border(map: &RoomsMap, room_index: &[Option<usize>], room: usize) -> Vec<UVec2> {
+    let mut border: Vec<UVec2> = map.room_tiles[room]
+        .iter()
+        .flat_map(|p| {
+            (-1..=1).flat_map(move |dy| (-1..=1).map(move |dx| p.as_ivec2() + IVec2::new(dx, dy)))
+        })
+        .filter(|n| n.x >= 0 && n.y >= 0 && n.x < map.width as i32 && n.y < map.height as i32)
+        .map(|n| n.as_uvec2())
+        .filter(|n| room_index[map.xy_idx(n.x, n.y)] != Some(room))
+        .collect();
+    border.sort_by_key(|p| (p.y, p.x));
+    border.dedup();
+    border
+}
+
+/// Walkable tiles around the room. None if the room touches the other room.
+fn room_entrances(map: &RoomsMap, room_index: &[Option<usize>], room: usize) -> Option<Vec<UVec2>> {
+    let mut entrances = Vec::new();
+    for p in room_border(map, room_index, room) {
+        if !map.is_walkable(p.x, p.y) {
+            continue;
+        }
+        if room_index[map.xy_idx(p.x, p.y)].is_some() {
+            return None;
+        }
+        entrances.push(p);
+    }
+    Some(entrances)
+}
+
+/// Pairs of rooms connected by the corridor or touching each other.
+/// Each pair comes with the corridor tiles next to both rooms.
+fn room_edges(map: &RoomsMap) -> HashMap<(usize, usize), Vec<UVec2>> {
+    // Corridors are the walkable tiles outside of the rooms
+    let room_index = map.room_index();
+    let room_at = |p: UVec2| room_index[map.xy_idx(p.x, p.y)];
+    let mut region: Vec<Option<usize>> = vec![None; (map.width * map.height) as usize];
+    let mut rooms_in_region: Vec<HashSet<usize>> = Vec::new();
+    let mut edges: HashMap<(usize, usize), Vec<UVec2>> = HashMap::new();
+    for y in 0..map.height {
+        for x in 0..map.width {
+            let p = UVec2::new(x, y);
+            if let Some(room) = room_at(p) {
+                for n in neighbors(map, p) {
+                    if let Some(other) = room_at(n).filter(|&o| o != room) {
+                        edges.entry((room.min(other), room.max(other))).or_default();
+                    }
+                }
+                continue;
+            }
+            if !map.is_walkable(x, y) || region[map.xy_idx(x, y)].is_some() {
+                continue;
+            }
+            let id = rooms_in_region.len();
+            let mut rooms = HashSet::new();
+            let mut queue = VecDeque::from([p]);
+            region[map.xy_idx(x, y)] = Some(id);
+            while let Some(p) = queue.pop_front() {
+                for n in neighbors(map, p) {
+                    if let Some(room) = room_at(n) {
+                        rooms.insert(room);
+                    } else if region[map.xy_idx(n.x, n.y)].is_none() {
+                        region[map.xy_idx(n.x, n.y)] = Some(id);
+                        queue.push_back(n);
+                    }
+                }
+            }
+            rooms_in_region.push(rooms);
+        }
+    }
+
+    for (id, rooms) in rooms_in_region.iter().enumerate() {
+        for &a in rooms.iter() {
+            for &b in rooms.iter().filter(|&&b| a < b) {
+                let tiles = edges.entry((a, b)).or_default();
+                // Tiles next to the rooms in this corridor
+                for room in [a, b] {
+                    let tile = room_border(map, &room_index, room)
+                        .into_iter()
+                        .find(|p| region[map.xy_idx(p.x, p.y)] == Some(id));
+                    if let Some(p) = tile {
+                        tiles.push(p);
+                    }
+                }
+            }
+        }
+    }
+    edges
+}
+
+/// Walkable neighbors (in 8 directions)
+fn neighbors(map: &RoomsMap, p: UVec2) -> impl Iterator<Item = UVec2> + '_ {
+    map.walkable_layer
+        .get_available_exits(p.x, p.y)
+        .into_iter()
+        .map(|(x, y, _)| UVec2::new(x, y))
+}
+
+/// Breadth first search over the room graph
+fn room_distances(
+    room_count: usize,
+    edges: &HashMap<(usize, usize), Vec<UVec2>>,
+    start_room: usize,
+) -> Vec<Option<usize>> {
+    let mut distances = vec![None; room_count];
+    distances[start_room] = Some(0);
+    let mut queue = VecDeque::from([start_room]);
+    while let Some(room) = queue.pop_front() {
+        let d = distances[room].unwrap_or(0);
+        for &(a, b) in edges.keys() {
+            let other = if a == room {
+                b
+            } else if b == room {
+                a
+            } else {
+                continue;
+            };
+            if distances[other].is_none() {
+                distances[other] = Some(d + 1);
+                queue.push_back(other);
+            }
+        }
+    }
+    distances
+}
+
+/// Walk the map from the start room. Only the first `keys` locks have their keys placed.
+/// Returns visited tiles and indexes of the locks in the order they were opened.
+fn solve(
+    map: &RoomsMap,
+    start_room: usize,
+    locks: &[Lock],
+    keys: usize,
+) -> (Vec<bool>, Vec<usize>) {
+    let mut visited = vec![false; (map.width * map.height) as usize];
+    let mut opened = Vec::new();
+    let doors: HashMap<UVec2, usize> = locks
+        .iter()
+        .enumerate()
+        .flat_map(|(i, l)| l.doors.iter().map(move |&d| (d, i)))
+        .collect();
+    let key_tiles: HashMap<UVec2, usize> = locks
+        .iter()
+        .take(keys)
+        .enumerate()
+        .map(|(i, l)| (l.key, i))
+        .collect();
+
+    let start = start_tile(map, start_room);
+    visited[map.xy_idx(start.x, start.y)] = true;
+    let mut queue = VecDeque::from([start]);
+    while let Some(p) = queue.pop_front() {
+        if let Some(&lock) = key_tiles.get(&p) {
+            if !opened.contains(&lock) {
+                opened.push(lock);
+                // Doors next to the visited tiles can be passed now
+                for &d in locks[lock].doors.iter() {
+                    let idx = map.xy_idx(d.x, d.y);
+                    if !visited[idx] && neighbors(map, d).any(|n| visited[map.xy_idx(n.x, n.y)]) {
+                        visited[idx] = true;
+                        queue.push_back(d);
+                    }
+                }
+            }
+        }
+        for n in neighbors(map, p) {
+            let idx = map.xy_idx(n.x, n.y);
+            let locked = doors.get(&n).is_some_and(|l| !opened.contains(l));
+            if !visited[idx] && !locked {
+                visited[idx] = true;
+                queue.push_back(n);
+            }
+        }
+    }
+    (visited, opened)
+}
+
+fn build_graph(
+    map: &RoomsMap,
+    puzzle: &LockPuzzle,
+    edges: &HashMap<(usize, usize), Vec<UVec2>>,
+) -> MissionGraph {
+    let mut graph = MissionGraph::default();
+    for i in 0..map.rooms.len() {
+        let label = if i == puzzle.start_room {
+            "Start"
+        } else {
+            "Room"
+        };
+        graph.nodes.push(MissionNode {
+            label: label.to_string(),
+            key: puzzle
+                .locks
+                .iter()
+                .position(|l| l.key_room == i)
+                .map(|k| k as u32),
+            room: Some(i),
+        });
+    }
+    // The start room is the first node in the mission graph
+    graph.nodes.swap(0, puzzle.start_room);
+    let node = |room: usize| graph.nodes.iter().position(|n| n.room == Some(room));
+    let node_index: Vec<usize> = (0..map.rooms.len()).map(|i| node(i).unwrap()).collect();
+
+    let mut pairs: Vec<&(usize, usize)> = edges.keys().collect();
+    pairs.sort();
+    for &(a, b) in pairs {
+        let mut connections = Vec::new();
+        for (from, to) in [(a, b), (b, a)] {
+            if let Some(k) = puzzle.locks.iter().position(|l| l.room == to) {
+                let door = puzzle.locks[k]
+                    .doors
+                    .iter()
+                    .find(|d| edges[&(a, b)].contains(d))
+                    .or(puzzle.locks[k].doors.first());
+                connections.push((from, to, Connection::Locked(k as u32), door.copied()));
+            }
+        }
+        if connections.is_empty() {
+            connections.push((a, b, Connection::Open, None));
+        }
+        for (from, to, connection, door) in connections {
+            graph.edges.push(MissionEdge {
+                from: node_index[from],
+                to: node_index[to],
+                connection,
+                corridor: None,
+                door,
+            });
+        }
+    }
+    graph
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geometry::Rect;
+    use crate::layer::WalkableLayer;
+    use crate::rooms::{NearestCorridors, RoomShape, SimpleRooms};
+
+    #[test]
+    fn test_corridor_chain() {
+        let mut map = RoomsMap::new(20, 5);
+        map.add_room(Rect::new(1, 1, 3, 3));
+        map.add_room(Rect::new(8, 1, 3, 3));
+        map.add_room(Rect::new(15, 1, 3, 3));
+        map.add_corridor(UVec2::new(2, 2), UVec2::new(17, 2));
+        let mut rng = Rng::with_seed(1);
+        let puzzle = LockAndKey::new().with_locks(2).generate(&map, 0, &mut rng);
+
+        assert_eq!(puzzle.distances, vec![Some(0), Some(1), Some(2)]);
+        assert_eq!(puzzle.locks.len(), 2);
+        assert_eq!(puzzle.locks[0].room, 1);
+        assert_eq!(puzzle.locks[0].key_room, 0);
+        assert_eq!(
+            puzzle.locks[0].doors,
+            vec![UVec2::new(7, 2), UVec2::new(11, 2)]
+        );
+        assert_eq!(puzzle.locks[1].room, 2);
+        assert_eq!(puzzle.locks[1].key_room, 1);
+        assert!(puzzle.is_solvable(&map));
+        assert!(puzzle.graph.is_solvable());
+    }
+
+    #[test]
+    fn test_no_softlocks() {
+        for seed in 0..10 {
+            let mut rng = Rng::with_seed(seed);
+            let map = SimpleRooms::default().generate(80, 50, &mut rng);
+            let map = NearestCorridors::new().generate(&map);
+            let puzzle = LockAndKey::new().with_locks(4).generate(&map, 0, &mut rng);
+            assert!(puzzle.is_solvable(&map));
+            assert!(!puzzle.locks.is_empty());
+            for (i, lock) in puzzle.locks.iter().enumerate() {
+                // Room is closed until its key is found, and the key needs only previous keys
+                let (visited, _) = solve(&map, 0, &puzzle.locks, i);
+                assert!(map.room_tiles[lock.room]
+                    .iter()
+                    .all(|p| !visited[map.xy_idx(p.x, p.y)]));
+                assert!(visited[map.xy_idx(lock.key.x, lock.key.y)]);
+            }
+        }
+    }
+
+    #[test]
+    fn test_start_room_without_center() {
+        // Prefab with the floor only in the corner
+        let corner = WalkableLayer::from_string(
+            "
+            #######
+            #  ####
+            #  ####
+            #######
+            #######
+            #######
+            ",
+        );
+        let mut rng = Rng::with_seed(3);
+        let map = SimpleRooms::default()
+            .with_shapes(vec![RoomShape::Prefab(corner)])
+            .generate(80, 50, &mut rng);
+        let map = NearestCorridors::new().generate(&map);
+        let c = map.rooms[0].center();
+        assert!(map.is_blocked(c.x, c.y));
+        let puzzle = LockAndKey::new().with_locks(2).generate(&map, 0, &mut rng);
+        assert!(!puzzle.locks.is_empty());
+        assert!(puzzle.is_solvable(&map));
+    }
+}
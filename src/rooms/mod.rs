@@ -1,19 +1,42 @@
 //! This module generate map based on rooms and corridors.
 //! This process is done in few steps:
 //! * Generate room with one of the generators ([SimpleRooms], [BspInterior], etc)
-//! * Generate corridors to connect rooms e.g [NearestCorridors]
+//!   or carve rooms and corridors together with [Tunneler]
+//! * Or lay out the whole town with the buildings using [Town]
+//! * Or create the mission graph with the [Grammar] and embed it with [CyclicDungeon]
+//! * Generate corridors to connect rooms e.g [NearestCorridors] or [MstCorridors]
+//! * Place doors at the room entrances with [DoorPlacement]
+//! * Place locked doors and their keys with [LockAndKey]
 //! * Then we can use add start end exit point [crate::poi::AreaStartingPosition]
-//!     and [crate::poi::CullUnreachable]
+//!   and [crate::poi::CullUnreachable]
 //!
 
 pub mod bsp_interior;
 pub mod bsp_rooms;
+pub mod corridor;
+pub mod corridors_mst;
 pub mod corridors_nearest;
+pub mod cyclic;
+pub mod doors;
+pub mod grammar;
+pub mod locks;
+pub mod room_shape;
 pub mod simple_rooms;
 pub mod tile_map;
+pub mod town;
+pub mod tunneler;
 
 pub use bsp_interior::BspInterior;
 pub use bsp_rooms::BspRooms;
+pub use corridor::{Corridor, CorridorStyle};
+pub use corridors_mst::MstCorridors;
 pub use corridors_nearest::NearestCorridors;
+pub use cyclic::CyclicDungeon;
+pub use doors::{Door, DoorKind, DoorPlacement};
+pub use grammar::{Connection, Grammar, MissionEdge, MissionGraph, MissionNode};
+pub use locks::{Lock, LockAndKey, LockPuzzle};
+pub use room_shape::RoomShape;
 pub use simple_rooms::SimpleRooms;
 pub use tile_map::RoomsMap;
+pub use town::{Building, Town, TownMap};
+pub use tunneler::{Tunneler, TunnelerParams};
@@ -0,0 +1,278 @@
+//! Shapes of the rooms carved inside of the room bounds.
+//!
+//! All shapes (except prefabs) contain the center of the bounds. Prefabs may not contain it,
+//! so corridors start at the room tile closest to the center ([super::RoomsMap::closest_room_tile]).
+//!
+//! Example usage:
+//! ```
+//! use mapgen::rooms::{RoomShape, RoomsMap};
+//! use mapgen::geometry::Rect;
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let mut map = RoomsMap::new(20, 20);
+//! map.add_shaped_room(&mut rng, Rect::new(2, 2, 15, 15), &RoomShape::Circle);
+//!
+//! assert!(map.is_walkable(9, 9));
+//! assert!(map.is_blocked(2, 2));
+//! assert_eq!(map.rooms[0], Rect::new(2, 2, 15, 15));
+//! ```
+//!
+
+use std::collections::{HashSet, VecDeque};
+
+use fastrand::Rng;
+use glam::UVec2;
+
+use crate::geometry::Rect;
+use crate::layer::WalkableLayer;
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum RoomShape {
+    #[default]
+    Rectangle,
+    /// Largest circle which fits into the bounds
+    Circle,
+    Ellipse,
+    /// Horizontal and vertical arm, each 1/3 of the bounds wide
+    Cross,
+    /// Rectangle with one random corner cut out
+    LShape,
+    /// Rectangle with the grid of pillars
+    PillaredHall,
+    /// Cave created with cellular automata
+    CaveBlob,
+    /// Walkable tiles of the template, centered in the bounds
+    Prefab(WalkableLayer),
+}
+
+impl RoomShape {
+    /// Floor tiles of the room with the given bounds
+    pub fn tiles(&self, rng: &mut Rng, rect: &Rect) -> Vec<UVec2> {
+        let (w, h) = (rect.width(), rect.height());
+        let center = rect.center();
+        let all =
+            (rect.y1..rect.y2).flat_map(|y| (rect.x1..rect.x2).map(move |x| UVec2::new(x, y)));
+        match self {
+            RoomShape::Rectangle => all.collect(),
+            RoomShape::Circle => {
+                let r = w.min(h) as f32 / 2.0;
+                all.filter(|p| in_ellipse(rect, *p, r, r)).collect()
+            }
+            RoomShape::Ellipse => {
+                let (rx, ry) = (w as f32 / 2.0, h as f32 / 2.0);
+                all.filter(|p| in_ellipse(rect, *p, rx, ry)).collect()
+            }
+            RoomShape::Cross => {
+                let (arm_w, arm_h) = (u32::max(w / 3, 1), u32::max(h / 3, 1));
+                let (x1, y1) = (rect.x1 + (w - arm_w) / 2, rect.y1 + (h - arm_h) / 2);
+                all.filter(|p| (p.x >= x1 && p.x < x1 + arm_w) || (p.y >= y1 && p.y < y1 + arm_h))
+                    .collect()
+            }
+            RoomShape::LShape => {
+                let (left, top) = (rng.bool(), rng.bool());
+                all.filter(|p| {
+                    let cut_x = if left { p.x < center.x } else { p.x > center.x };
+                    let cut_y = if top { p.y < center.y } else { p.y > center.y };
+                    !(cut_x && cut_y)
+                })
+                .collect()
+            }
+            RoomShape::PillaredHall => all
+                .filter(|p| {
+                    let (x, y) = (p.x - rect.x1, p.y - rect.y1);
+                    let pillar = x % 3 == 1 && y % 3 == 1 && x + 1 < w && y + 1 < h;
+                    !pillar || *p == center
+                })
+                .collect(),
+            RoomShape::CaveBlob => cave_blob(rng, rect),
+            RoomShape::Prefab(template) => {
+                let x1 = rect.x1 as i64 + (w as i64 - template.width as i64) / 2;
+                let y1 = rect.y1 as i64 + (h as i64 - template.height as i64) / 2;
+                all.filter(|p| {
+                    let (x, y) = (p.x as i64 - x1, p.y as i64 - y1);
+                    x >= 0 && y >= 0 && template.is_walkable(x as u32, y as u32)
+                })
+                .collect()
+            }
+        }
+    }
+}
+
+/// Random shape from the list. Single shape doesn't use random numbers.
+pub(crate) fn choose_shape<'a>(rng: &mut Rng, shapes: &'a [RoomShape]) -> &'a RoomShape {
+    if shapes.len() > 1 {
+        &shapes[rng.usize(0..shapes.len())]
+    } else {
+        &shapes[0]
+    }
+}
+
+fn in_ellipse(rect: &Rect, p: UVec2, rx: f32, ry: f32) -> bool {
+    let cx = (rect.x1 + rect.x2) as f32 / 2.0;
+    let cy = (rect.y1 + rect.y2) as f32 / 2.0;
+    let dx = (p.x as f32 + 0.5 - cx) / rx;
+    let dy = (p.y as f32 + 0.5 - cy) / ry;
+    dx * dx + dy * dy <= 1.0
+}
+
+/// Cellular automata inside of the bounds. Only the cave connected to the center is kept.
+fn cave_blob(rng: &mut Rng, rect: &Rect) -> Vec<UVec2> {
+    let (w, h) = (rect.width() as i32, rect.height() as i32);
+    let idx = |x: i32, y: i32| (y * w + x) as usize;
+    let mut floor: Vec<bool> = (0..w * h).map(|_| rng.f32() < 0.6).collect();
+    for _ in 0..4 {
+        let mut next = floor.clone();
+        for y in 0..h {
+            for x in 0..w {
+                let walls = (-1..=1)
+                    .flat_map(|dy| (-1..=1).map(move |dx| (x + dx, y + dy)))
+                    .filter(|&(nx, ny)| {
+                        nx < 0 || ny < 0 || nx >= w || ny >= h || !floor[idx(nx, ny)]
+                    })
+                    .count();
+                next[idx(x, y)] = walls < 5;
+            }
+        }
+        floor = next;
+    }
+
+    let center = rect.center();
+    let start = (center.x - rect.x1) as i32;
+    let start = (start, (center.y - rect.y1) as i32);
+    floor[idx(start.0, start.1)] = true;
+    let mut visited = HashSet::from([start]);
+    let mut queue = VecDeque::from([start]);
+    while let Some((x, y)) = queue.pop_front() {
+        for (nx, ny) in [(x + 1, y), (x - 1, y), (x, y + 1), (x, y - 1)] {
+            if nx >= 0
+                && ny >= 0
+                && nx < w
+                && ny < h
+                && floor[idx(nx, ny)]
+                && visited.insert((nx, ny))
+            {
+                queue.push_back((nx, ny));
+            }
+        }
+    }
+    let mut tiles: Vec<UVec2> = visited
+        .into_iter()
+        .map(|(x, y)| UVec2::new(rect.x1 + x as u32, rect.y1 + y as u32))
+        .collect();
+    tiles.sort_by_key(|p| (p.y, p.x));
+    tiles
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn to_layer(rect: &Rect, tiles: &[UVec2]) -> WalkableLayer {
+        let mut layer = WalkableLayer::new(rect.x2 + 1, rect.y2 + 1);
+        for p in tiles {
+            layer.set_walkable(p.x, p.y, true);
+        }
+        layer
+    }
+
+    #[test]
+    fn test_circle() {
+        let mut rng = Rng::with_seed(1);
+        let rect = Rect::new(1, 1, 7, 7);
+        let tiles = RoomShape::Circle.tiles(&mut rng, &rect);
+        let expected = WalkableLayer::from_string(
+            "
+            #########
+            ###   ###
+            ##     ##
+            #       #
+            #       #
+            #       #
+            ##     ##
+            ###   ###
+            #########
+            ",
+        );
+        assert_eq!(to_layer(&rect, &tiles), expected);
+    }
+
+    #[test]
+    fn test_cross_and_pillars() {
+        let mut rng = Rng::with_seed(1);
+        let rect = Rect::new(1, 1, 6, 6);
+        let cross = RoomShape::Cross.tiles(&mut rng, &rect);
+        let pillars = RoomShape::PillaredHall.tiles(&mut rng, &rect);
+        let expected_cross = WalkableLayer::from_string(
+            "
+            ########
+            ###  ###
+            ###  ###
+            #      #
+            #      #
+            ###  ###
+            ###  ###
+            ########
+            ",
+        );
+        let expected_pillars = WalkableLayer::from_string(
+            "
+            ########
+            #      #
+            # #  # #
+            #      #
+            #      #
+            # #  # #
+            #      #
+            ########
+            ",
+        );
+        assert_eq!(to_layer(&rect, &cross), expected_cross);
+        assert_eq!(to_layer(&rect, &pillars), expected_pillars);
+    }
+
+    #[test]
+    fn test_shapes_contain_center() {
+        let mut rng = Rng::with_seed(3);
+        let rect = Rect::new(3, 2, 9, 7);
+        let shapes = [
+            RoomShape::Rectangle,
+            RoomShape::Circle,
+            RoomShape::Ellipse,
+            RoomShape::Cross,
+            RoomShape::LShape,
+            RoomShape::PillaredHall,
+            RoomShape::CaveBlob,
+        ];
+        for shape in shapes.iter() {
+            let tiles = shape.tiles(&mut rng, &rect);
+            assert!(tiles.contains(&rect.center()), "{:?}", shape);
+            assert!(tiles
+                .iter()
+                .all(|p| p.x >= rect.x1 && p.x < rect.x2 && p.y >= rect.y1 && p.y < rect.y2));
+        }
+    }
+
+    #[test]
+    fn test_prefab() {
+        let mut rng = Rng::with_seed(1);
+        let template = WalkableLayer::from_string(
+            "
+            #####
+            # # #
+            #   #
+            #####
+            ",
+        );
+        let rect = Rect::new(1, 1, 5, 4);
+        let tiles = RoomShape::Prefab(template).tiles(&mut rng, &rect);
+        let expected: Vec<UVec2> = [(2, 2), (4, 2), (2, 3), (3, 3), (4, 3)]
+            .iter()
+            .map(|&(x, y)| UVec2::new(x, y))
+            .collect();
+        assert_eq!(tiles, expected);
+    }
+}
@@ -19,13 +19,15 @@
 
 use fastrand::Rng;
 
-use super::RoomsMap;
+use super::room_shape::choose_shape;
+use super::{RoomShape, RoomsMap};
 use crate::geometry::Rect;
 
 pub struct SimpleRooms {
     max_rooms: u32,
     min_room_size: u32,
     max_room_size: u32,
+    shapes: Vec<RoomShape>,
 }
 
 impl SimpleRooms {
@@ -34,9 +36,18 @@ impl SimpleRooms {
             max_rooms,
             min_room_size,
             max_room_size,
+            shapes: vec![RoomShape::Rectangle],
         }
     }
 
+    /// Shapes of the rooms. Each room gets the random shape from the list.
+    pub fn with_shapes(mut self, shapes: Vec<RoomShape>) -> Self {
+        if !shapes.is_empty() {
+            self.shapes = shapes;
+        }
+        self
+    }
+
     pub fn generate(&self, map_width: u32, max_height: u32, rng: &mut Rng) -> RoomsMap {
         // Create room with dimensions
         let mut map = RoomsMap::new(map_width, max_height);
@@ -49,7 +60,8 @@ impl SimpleRooms {
             let new_room = Rect::new(x, y, w, h);
             let intersects = map.rooms.iter().any(|r| new_room.intersect(r));
             if !intersects {
-                map.add_room(new_room);
+                let shape = choose_shape(rng, &self.shapes);
+                map.add_shaped_room(rng, new_room, shape);
             }
         }
 
@@ -63,6 +75,7 @@ impl Default for SimpleRooms {
             max_rooms: 30,
             min_room_size: 6,
             max_room_size: 10,
+            shapes: vec![RoomShape::Rectangle],
         }
     }
 }
@@ -1,18 +1,27 @@
 //! Generators for dungeon type maps.
 //!
 
+use std::collections::HashSet;
 use std::fmt;
 
+use fastrand::Rng;
 use glam::UVec2;
 
+use super::corridor::l_shape;
+use super::RoomShape;
 use crate::{geometry::Rect, layer::WalkableLayer};
 
 #[derive(Default, Clone)]
 pub struct RoomsMap {
     pub width: u32,
     pub height: u32,
+    /// Bounds of each room
     pub rooms: Vec<Rect>,
+    /// Exact floor tiles of each room
+    pub room_tiles: Vec<Vec<UVec2>>,
     pub corridors: Vec<Vec<UVec2>>,
+    /// Pairs of rooms connected by the corridor generator
+    pub connections: Vec<(usize, usize)>,
     pub walkable_layer: WalkableLayer,
 }
 
@@ -23,40 +32,88 @@ impl RoomsMap {
             height,
             walkable_layer: WalkableLayer::new(width, height),
             rooms: Vec::new(),
+            room_tiles: Vec::new(),
             corridors: Vec::new(),
+            connections: Vec::new(),
         }
     }
 
     /// Create room on the map at given location
     /// Room is created by setting all tiles in the room to the Floor
     pub fn add_room(&mut self, rect: Rect) {
+        let tiles = (rect.y1..rect.y2)
+            .flat_map(|y| (rect.x1..rect.x2).map(move |x| UVec2::new(x, y)))
+            .collect();
+        self.add_room_tiles(rect, tiles);
+    }
+
+    /// Create room with the given shape inside of the bounds
+    pub fn add_shaped_room(&mut self, rng: &mut Rng, rect: Rect, shape: &RoomShape) {
+        let tiles = shape.tiles(rng, &rect);
+        self.add_room_tiles(rect, tiles);
+    }
+
+    fn add_room_tiles(&mut self, rect: Rect, tiles: Vec<UVec2>) {
+        for p in tiles.iter() {
+            self.set_walkable(p.x, p.y, true);
+        }
         self.rooms.push(rect);
-        for x in rect.x1..rect.x2 {
-            for y in rect.y1..rect.y2 {
-                self.set_walkable(x, y, true);
+        self.room_tiles.push(tiles);
+    }
+
+    /// Floor tile of the room closest to the given point
+    pub fn closest_room_tile(&self, room: usize, p: UVec2) -> UVec2 {
+        self.room_tiles[room]
+            .iter()
+            .min_by_key(|t| t.as_ivec2().distance_squared(p.as_ivec2()))
+            .copied()
+            .unwrap_or(p)
+    }
+
+    /// Room of each tile (indexed with [RoomsMap::xy_idx]), None outside of the room floor
+    pub fn room_index(&self) -> Vec<Option<usize>> {
+        let mut index = vec![None; (self.width * self.height) as usize];
+        for (room, tiles) in self.room_tiles.iter().enumerate() {
+            for p in tiles.iter() {
+                index[self.xy_idx(p.x, p.y)] = Some(room);
             }
         }
+        index
     }
 
+    /// Connect 2 points with the L-shaped corridor (horizontal part first)
     pub fn add_corridor(&mut self, from: UVec2, to: UVec2) {
+        self.carve_path(&l_shape(from, to, true), 1);
+    }
+
+    /// Make the path walkable with the square brush of the given width and store it
+    /// as the corridor. Map border is never carved.
+    /// Returns carved tiles outside of the rooms.
+    pub fn carve_path(&mut self, path: &[UVec2], width: u32) -> Vec<UVec2> {
+        let width = width.max(1) as i32;
+        let room_index = self.room_index();
         let mut corridor = Vec::new();
-        let mut x = from.x;
-        let mut y = from.y;
-
-        while x != to.x || y != to.y {
-            if x < to.x {
-                x += 1;
-            } else if x > to.x {
-                x -= 1;
-            } else if y < to.y {
-                y += 1;
-            } else if y > to.y {
-                y -= 1;
+        let mut carved = HashSet::new();
+        for p in path {
+            for dy in -(width - 1) / 2..=width / 2 {
+                for dx in -(width - 1) / 2..=width / 2 {
+                    let (x, y) = (p.x as i32 + dx, p.y as i32 + dy);
+                    if x < 1 || y < 1 || x >= self.width as i32 - 1 || y >= self.height as i32 - 1 {
+                        continue;
+                    }
+                    let tile = UVec2::new(x as u32, y as u32);
+                    self.set_walkable(tile.x, tile.y, true);
+                    let in_room = room_index[self.xy_idx(tile.x, tile.y)].is_some();
+                    if !in_room && carved.insert(tile) {
+                        corridor.push(tile);
+                    }
+                }
             }
-
-            corridor.push(UVec2::new(x, y));
-            self.walkable_layer.set_walkable(x, y, true);
         }
+        if !corridor.is_empty() {
+            self.corridors.push(corridor.clone());
+        }
+        corridor
     }
 
     pub fn is_walkable(&self, x: u32, y: u32) -> bool {
@@ -121,4 +178,14 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn test_corridor_is_stored() {
+        let mut map = RoomsMap::new(10, 5);
+        map.add_room(Rect::new(1, 1, 2, 3));
+        map.add_room(Rect::new(7, 1, 2, 3));
+        map.add_corridor(UVec2::new(1, 2), UVec2::new(8, 3));
+        let expected: Vec<UVec2> = (3..7).map(|x| UVec2::new(x, 2)).collect();
+        assert_eq!(map.corridors, vec![expected]);
+    }
 }
@@ -0,0 +1,420 @@
+//! Town generator.
+//!
+//! Town area is divided into blocks separated by streets. Blocks are subdivided into lots
+//! (BSP similar to [crate::rooms::BspInterior]), and each lot gets a building with the door
+//! on the street facing wall. Building interiors can be partitioned into rooms.
+//!
+//! Example generator usage:
+//! ```
+//! use mapgen::rooms::Town;
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let town = Town::default();
+//! let map = town.generate(80, 50, &mut rng);
+//!
+//! assert_eq!(map.width, 80);
+//! assert!(!map.buildings.is_empty());
+//! ```
+//!
+
+use std::fmt;
+
+use fastrand::Rng;
+use glam::UVec2;
+
+use crate::geometry::Rect;
+use crate::layer::WalkableLayer;
+
+/// Single building in the town
+#[derive(Clone, Debug, PartialEq)]
+pub struct Building {
+    /// Area covered by the building, including walls
+    pub footprint: Rect,
+    pub doors: Vec<UVec2>,
+    /// Floor area of the rooms inside the building
+    pub rooms: Vec<Rect>,
+}
+
+#[derive(Default, Clone)]
+pub struct TownMap {
+    pub width: u32,
+    pub height: u32,
+    pub streets: Vec<Rect>,
+    pub buildings: Vec<Building>,
+    pub walkable_layer: WalkableLayer,
+}
+
+impl TownMap {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            streets: Vec::new(),
+            buildings: Vec::new(),
+            walkable_layer: WalkableLayer::new(width, height),
+        }
+    }
+
+    pub fn is_walkable(&self, x: u32, y: u32) -> bool {
+        self.walkable_layer.is_walkable(x, y)
+    }
+
+    pub fn is_blocked(&self, x: u32, y: u32) -> bool {
+        !self.is_walkable(x, y)
+    }
+
+    /// Modify tile at the given location
+    pub fn set_walkable(&mut self, x: u32, y: u32, set: bool) {
+        self.walkable_layer.set_walkable(x, y, set);
+    }
+}
+
+impl fmt::Display for TownMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.walkable_layer)
+    }
+}
+
+pub struct Town {
+    street_width: u32,
+    min_block_size: u32,
+    max_block_size: u32,
+    min_lot_size: u32,
+    max_lot_size: u32,
+    interiors: bool,
+    min_room_size: u32,
+}
+
+impl Town {
+    pub fn new() -> Self {
+        Self {
+            street_width: 2,
+            min_block_size: 10,
+            max_block_size: 24,
+            min_lot_size: 6,
+            max_lot_size: 12,
+            interiors: true,
+            min_room_size: 3,
+        }
+    }
+
+    pub fn with_street_width(mut self, street_width: u32) -> Self {
+        self.street_width = u32::max(street_width, 1);
+        self
+    }
+
+    /// Size of the area between streets
+    pub fn with_block_size(mut self, min_size: u32, max_size: u32) -> Self {
+        self.min_block_size = u32::max(min_size, 1);
+        self.max_block_size = u32::max(max_size, 2 * self.min_block_size + self.street_width);
+        self
+    }
+
+    /// Size of the lot with single building (including the yard around the building)
+    pub fn with_lot_size(mut self, min_size: u32, max_size: u32) -> Self {
+        self.min_lot_size = u32::max(min_size, 5);
+        self.max_lot_size = u32::max(max_size, 2 * self.min_lot_size);
+        self
+    }
+
+    /// Partition buildings into rooms
+    pub fn with_interiors(mut self, interiors: bool) -> Self {
+        self.interiors = interiors;
+        self
+    }
+
+    pub fn generate(&self, map_width: u32, map_height: u32, rng: &mut Rng) -> TownMap {
+        let mut map = TownMap::new(map_width, map_height);
+        let sw = self.street_width;
+        if map_width < 2 + 2 * sw + self.min_lot_size || map_height < 2 + 2 * sw + self.min_lot_size
+        {
+            return map;
+        }
+        for y in 1..map_height - 1 {
+            for x in 1..map_width - 1 {
+                map.set_walkable(x, y, true);
+            }
+        }
+
+        // Ring street along the town border
+        let (w, h) = (map_width - 2, map_height - 2);
+        map.streets.push(Rect::new(1, 1, w, sw));
+        map.streets.push(Rect::new(1, map_height - 1 - sw, w, sw));
+        map.streets.push(Rect::new(1, 1 + sw, sw, h - 2 * sw));
+        map.streets
+            .push(Rect::new(map_width - 1 - sw, 1 + sw, sw, h - 2 * sw));
+
+        let inner = Rect::new(1 + sw, 1 + sw, w - 2 * sw, h - 2 * sw);
+        let mut blocks = Vec::new();
+        self.split_blocks(rng, inner, &mut map.streets, &mut blocks);
+
+        for block in blocks {
+            let mut lots = Vec::new();
+            self.split_lots(rng, block, block, &mut lots);
+            for lot in lots {
+                if let Some(building) = self.build(rng, &mut map, block, lot) {
+                    map.buildings.push(building);
+                }
+            }
+        }
+
+        map
+    }
+
+    /// Divide area into blocks with the streets between them
+    fn split_blocks(
+        &self,
+        rng: &mut Rng,
+        rect: Rect,
+        streets: &mut Vec<Rect>,
+        blocks: &mut Vec<Rect>,
+    ) {
+        let sw = self.street_width;
+        let (w, h) = (rect.width(), rect.height());
+        let vertical = w >= h;
+        let size = if vertical { w } else { h };
+        if size <= self.max_block_size || size < 2 * self.min_block_size + sw {
+            blocks.push(rect);
+            return;
+        }
+
+        let pos = rng.u32(self.min_block_size..=size - self.min_block_size - sw);
+        let (a, street, b) = if vertical {
+            (
+                Rect::new(rect.x1, rect.y1, pos, h),
+                Rect::new(rect.x1 + pos, rect.y1, sw, h),
+                Rect::new(rect.x1 + pos + sw, rect.y1, w - pos - sw, h),
+            )
+        } else {
+            (
+                Rect::new(rect.x1, rect.y1, w, pos),
+                Rect::new(rect.x1, rect.y1 + pos, w, sw),
+                Rect::new(rect.x1, rect.y1 + pos + sw, w, h - pos - sw),
+            )
+        };
+        streets.push(street);
+        self.split_blocks(rng, a, streets, blocks);
+        self.split_blocks(rng, b, streets, blocks);
+    }
+
+    /// Divide block into lots. Each lot has to touch the block edge (street)
+    fn split_lots(&self, rng: &mut Rng, block: Rect, rect: Rect, lots: &mut Vec<Rect>) {
+        let (w, h) = (rect.width(), rect.height());
+        let vertical = w >= h;
+        let size = if vertical { w } else { h };
+        if size > self.max_lot_size && size >= 2 * self.min_lot_size {
+            let pos = rng.u32(self.min_lot_size..=size - self.min_lot_size);
+            let (a, b) = if vertical {
+                (
+                    Rect::new(rect.x1, rect.y1, pos, h),
+                    Rect::new(rect.x1 + pos, rect.y1, w - pos, h),
+                )
+            } else {
+                (
+                    Rect::new(rect.x1, rect.y1, w, pos),
+                    Rect::new(rect.x1, rect.y1 + pos, w, h - pos),
+                )
+            };
+            if touches_edge(&block, &a) && touches_edge(&block, &b) {
+                self.split_lots(rng, block, a, lots);
+                self.split_lots(rng, block, b, lots);
+                return;
+            }
+        }
+        lots.push(rect);
+    }
+
+    /// Build walls and door of the building on the lot
+    fn build(&self, rng: &mut Rng, map: &mut TownMap, block: Rect, lot: Rect) -> Option<Building> {
+        // Leave 1 tile of the yard around the building
+        if lot.width() < 5 || lot.height() < 5 {
+            return None;
+        }
+        let footprint = Rect::new(lot.x1 + 1, lot.y1 + 1, lot.width() - 2, lot.height() - 2);
+        let (x1, y1, x2, y2) = (
+            footprint.x1,
+            footprint.y1,
+            footprint.x2 - 1,
+            footprint.y2 - 1,
+        );
+        for x in x1..=x2 {
+            map.set_walkable(x, y1, false);
+            map.set_walkable(x, y2, false);
+        }
+        for y in y1..=y2 {
+            map.set_walkable(x1, y, false);
+            map.set_walkable(x2, y, false);
+        }
+
+        // Door on the wall facing the street
+        let mut sides = Vec::new();
+        if lot.y1 == block.y1 {
+            sides.push(
+                (x1 + 1..x2)
+                    .map(|x| UVec2::new(x, y1))
+                    .collect::<Vec<UVec2>>(),
+            );
+        }
+        if lot.y2 == block.y2 {
+            sides.push((x1 + 1..x2).map(|x| UVec2::new(x, y2)).collect());
+        }
+        if lot.x1 == block.x1 {
+            sides.push((y1 + 1..y2).map(|y| UVec2::new(x1, y)).collect());
+        }
+        if lot.x2 == block.x2 {
+            sides.push((y1 + 1..y2).map(|y| UVec2::new(x2, y)).collect());
+        }
+        let side = &sides[rng.usize(0..sides.len())];
+        let door = side[rng.usize(0..side.len())];
+        map.set_walkable(door.x, door.y, true);
+
+        let mut building = Building {
+            footprint,
+            doors: vec![door],
+            rooms: Vec::new(),
+        };
+        let floor = Rect::new(x1 + 1, y1 + 1, x2 - x1 - 1, y2 - y1 - 1);
+        if self.interiors {
+            self.partition(rng, map, floor, &mut building);
+        } else {
+            building.rooms.push(floor);
+        }
+        Some(building)
+    }
+
+    /// Split building floor into rooms with the inner walls and doors
+    fn partition(&self, rng: &mut Rng, map: &mut TownMap, rect: Rect, building: &mut Building) {
+        let (w, h) = (rect.width(), rect.height());
+        let vertical = w >= h;
+        let size = if vertical { w } else { h };
+        let min = self.min_room_size;
+        if size > 2 * min {
+            // Walls can't end next to the existing doors
+            let candidates: Vec<u32> = (min..size - min)
+                .filter(|&pos| {
+                    let (end1, end2) = if vertical {
+                        let x = rect.x1 + pos;
+                        (UVec2::new(x, rect.y1 - 1), UVec2::new(x, rect.y2))
+                    } else {
+                        let y = rect.y1 + pos;
+                        (UVec2::new(rect.x1 - 1, y), UVec2::new(rect.x2, y))
+                    };
+                    !building.doors.contains(&end1) && !building.doors.contains(&end2)
+                })
+                .collect();
+            if !candidates.is_empty() {
+                let pos = candidates[rng.usize(0..candidates.len())];
+                let (a, b, wall): (Rect, Rect, Vec<UVec2>) = if vertical {
+                    let x = rect.x1 + pos;
+                    (
+                        Rect::new(rect.x1, rect.y1, pos, h),
+                        Rect::new(x + 1, rect.y1, w - pos - 1, h),
+                        (rect.y1..rect.y2).map(|y| UVec2::new(x, y)).collect(),
+                    )
+                } else {
+                    let y = rect.y1 + pos;
+                    (
+                        Rect::new(rect.x1, rect.y1, w, pos),
+                        Rect::new(rect.x1, y + 1, w, h - pos - 1),
+                        (rect.x1..rect.x2).map(|x| UVec2::new(x, y)).collect(),
+                    )
+                };
+                for p in wall.iter() {
+                    map.set_walkable(p.x, p.y, false);
+                }
+                let door = wall[rng.usize(0..wall.len())];
+                map.set_walkable(door.x, door.y, true);
+                building.doors.push(door);
+                self.partition(rng, map, a, building);
+                self.partition(rng, map, b, building);
+                return;
+            }
+        }
+        building.rooms.push(rect);
+    }
+}
+
+impl Default for Town {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn touches_edge(block: &Rect, rect: &Rect) -> bool {
+    rect.x1 == block.x1 || rect.y1 == block.y1 || rect.x2 == block.x2 || rect.y2 == block.y2
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Number of walkable tiles reachable (4-connected) from the given point
+    fn reachable(map: &TownMap, start: UVec2) -> usize {
+        let mut visited = vec![false; map.walkable_layer.tiles.len()];
+        let mut stack = vec![start];
+        let mut count = 0;
+        while let Some(p) = stack.pop() {
+            let idx = map.walkable_layer.xy_idx(p.x, p.y);
+            if visited[idx] || map.is_blocked(p.x, p.y) {
+                continue;
+            }
+            visited[idx] = true;
+            count += 1;
+            stack.push(UVec2::new(p.x - 1, p.y));
+            stack.push(UVec2::new(p.x + 1, p.y));
+            stack.push(UVec2::new(p.x, p.y - 1));
+            stack.push(UVec2::new(p.x, p.y + 1));
+        }
+        count
+    }
+
+    #[test]
+    fn test_buildings() {
+        let mut rng = Rng::with_seed(100);
+        let map = Town::default().generate(80, 50, &mut rng);
+        assert!(map.buildings.len() > 5);
+        for (i, b) in map.buildings.iter().enumerate() {
+            for other in map.buildings.iter().skip(i + 1) {
+                assert!(!b.footprint.intersect(&other.footprint));
+            }
+            for street in map.streets.iter() {
+                assert!(!b.footprint.intersect(street));
+            }
+            for room in b.rooms.iter() {
+                assert!(room.x1 > b.footprint.x1 && room.x2 < b.footprint.x2);
+                assert!(room.y1 > b.footprint.y1 && room.y2 < b.footprint.y2);
+            }
+            for door in b.doors.iter() {
+                assert!(map.is_walkable(door.x, door.y));
+            }
+        }
+    }
+
+    #[test]
+    fn test_connected() {
+        let mut rng = Rng::with_seed(100);
+        let map = Town::default().generate(80, 50, &mut rng);
+        let walkable = map.walkable_layer.tiles.iter().filter(|&&t| t).count();
+        assert_eq!(reachable(&map, UVec2::new(1, 1)), walkable);
+        for x in 0..80 {
+            assert!(map.is_blocked(x, 0));
+            assert!(map.is_blocked(x, 49));
+        }
+    }
+
+    #[test]
+    fn test_no_interiors() {
+        let mut rng = Rng::with_seed(100);
+        let map = Town::default()
+            .with_interiors(false)
+            .generate(80, 50, &mut rng);
+        for b in map.buildings.iter() {
+            assert_eq!(b.rooms.len(), 1);
+            assert_eq!(b.doors.len(), 1);
+        }
+    }
+}
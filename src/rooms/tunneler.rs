@@ -0,0 +1,331 @@
+//! Tunneler agents map generator.
+//!
+//! Tunnelers walk through the map carving corridors. On the way they can turn,
+//! spawn child tunnelers at junctions and carve rooms to the side.
+//! Each tunneler dies after its lifespan.
+//!
+//! Tunneler behaviour is defined by the generation table. The first tunnelers use
+//! the first entry, their children use the second one, etc.
+//! Tunnelers from the last generation don't spawn children.
+//!
+//! Example generator usage:
+//! ```
+//! use mapgen::rooms::Tunneler;
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let tunneler = Tunneler::default();
+//! let map = tunneler.generate(80, 50, &mut rng);
+//!
+//! assert_eq!(map.width, 80);
+//! assert!(!map.rooms.is_empty());
+//! ```
+//!
+
+use std::collections::VecDeque;
+
+use fastrand::Rng;
+use glam::IVec2;
+
+use super::RoomsMap;
+use crate::geometry::Rect;
+
+const DIRECTIONS: [IVec2; 4] = [IVec2::X, IVec2::Y, IVec2::NEG_X, IVec2::NEG_Y];
+
+/// Parameters of the single tunneler generation
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TunnelerParams {
+    pub min_width: u32,
+    pub max_width: u32,
+    /// Number of steps before the tunneler dies
+    pub lifespan: u32,
+    /// Probability of changing direction at each step
+    pub turn_chance: f32,
+    /// Probability of spawning a child tunneler at each step
+    pub spawn_chance: f32,
+    /// Probability of carving a room to the side at each step
+    pub room_chance: f32,
+    pub min_room_size: u32,
+    pub max_room_size: u32,
+}
+
+impl Default for TunnelerParams {
+    fn default() -> Self {
+        Self {
+            min_width: 1,
+            max_width: 1,
+            lifespan: 30,
+            turn_chance: 0.1,
+            spawn_chance: 0.05,
+            room_chance: 0.05,
+            min_room_size: 4,
+            max_room_size: 8,
+        }
+    }
+}
+
+struct Agent {
+    pos: IVec2,
+    dir: IVec2,
+    generation: usize,
+    width: u32,
+}
+
+pub struct Tunneler {
+    generations: Vec<TunnelerParams>,
+    max_tunnelers: u32,
+}
+
+impl Tunneler {
+    pub fn new(generations: Vec<TunnelerParams>) -> Self {
+        Self {
+            generations,
+            max_tunnelers: 50,
+        }
+    }
+
+    /// Maximum number of tunnelers (including children) created during generation
+    pub fn with_max_tunnelers(mut self, max_tunnelers: u32) -> Self {
+        self.max_tunnelers = max_tunnelers;
+        self
+    }
+
+    pub fn generate(&self, map_width: u32, map_height: u32, rng: &mut Rng) -> RoomsMap {
+        let mut map = RoomsMap::new(map_width, map_height);
+        if self.generations.is_empty() || map_width < 3 || map_height < 3 {
+            return map;
+        }
+
+        // 2 tunnelers start from the center in the opposite directions
+        let center = IVec2::new(map_width as i32 / 2, map_height as i32 / 2);
+        let dir = DIRECTIONS[rng.usize(0..4)];
+        let mut agents = VecDeque::new();
+        agents.push_back(self.new_agent(rng, center, dir, 0));
+        agents.push_back(self.new_agent(rng, center, -dir, 0));
+        let mut spawned = 2;
+
+        while let Some(mut agent) = agents.pop_front() {
+            let params = self.generations[agent.generation];
+            let mut path = Vec::new();
+            if !fits(&map, agent.pos, agent.width) {
+                continue;
+            }
+
+            for _ in 0..params.lifespan {
+                path.push(agent.pos.as_uvec2());
+
+                if rng.f32() < params.room_chance {
+                    self.try_room(rng, &mut map, &agent, &params);
+                }
+                if rng.f32() < params.spawn_chance
+                    && agent.generation + 1 < self.generations.len()
+                    && spawned < self.max_tunnelers
+                {
+                    let dir = turn(rng, agent.dir);
+                    agents.push_back(self.new_agent(rng, agent.pos, dir, agent.generation + 1));
+                    spawned += 1;
+                }
+
+                if rng.f32() < params.turn_chance {
+                    agent.dir = turn(rng, agent.dir);
+                }
+                if !fits(&map, agent.pos + agent.dir, agent.width) {
+                    // Try to turn away from the map edge
+                    let dir = turn(rng, agent.dir);
+                    if fits(&map, agent.pos + dir, agent.width) {
+                        agent.dir = dir;
+                    } else if fits(&map, agent.pos - dir, agent.width) {
+                        agent.dir = -dir;
+                    } else {
+                        break;
+                    }
+                }
+                agent.pos += agent.dir;
+            }
+
+            // Corridor keeps all the carved tiles, not only the tunneler positions
+            map.carve_path(&path, agent.width);
+        }
+
+        map
+    }
+
+    fn new_agent(&self, rng: &mut Rng, pos: IVec2, dir: IVec2, generation: usize) -> Agent {
+        let params = self.generations[generation];
+        let width = rng.u32(params.min_width..=u32::max(params.min_width, params.max_width));
+        Agent {
+            pos,
+            dir,
+            generation,
+            width: u32::max(width, 1),
+        }
+    }
+
+    /// Carve the room next to the corridor, if there is enough space
+    fn try_room(&self, rng: &mut Rng, map: &mut RoomsMap, agent: &Agent, params: &TunnelerParams) {
+        let max_size = u32::max(params.min_room_size, params.max_room_size);
+        let w = rng.u32(params.min_room_size..=max_size) as i32;
+        let h = rng.u32(params.min_room_size..=max_size) as i32;
+        let side = if rng.bool() {
+            IVec2::new(-agent.dir.y, agent.dir.x)
+        } else {
+            IVec2::new(agent.dir.y, -agent.dir.x)
+        };
+        // Room touches the corridor edge and is centered on the tunneler position
+        let (before, after) = extent(agent.width);
+        let offset = if side.x + side.y > 0 {
+            after + 1
+        } else {
+            before + 1
+        };
+        let x = match side.x {
+            1 => agent.pos.x + offset,
+            -1 => agent.pos.x - offset - w + 1,
+            _ => agent.pos.x - w / 2,
+        };
+        let y = match side.y {
+            1 => agent.pos.y + offset,
+            -1 => agent.pos.y - offset - h + 1,
+            _ => agent.pos.y - h / 2,
+        };
+        if x < 1 || y < 1 || x + w > map.width as i32 - 1 || y + h > map.height as i32 - 1 {
+            return;
+        }
+
+        let room = Rect::new(x as u32, y as u32, w as u32, h as u32);
+        if map.rooms.iter().all(|r| !r.intersect(&room)) {
+            map.add_room(room);
+        }
+    }
+}
+
+impl Default for Tunneler {
+    fn default() -> Self {
+        Self::new(vec![
+            TunnelerParams {
+                min_width: 2,
+                max_width: 3,
+                lifespan: 60,
+                turn_chance: 0.05,
+                spawn_chance: 0.08,
+                room_chance: 0.02,
+                ..Default::default()
+            },
+            TunnelerParams {
+                lifespan: 40,
+                spawn_chance: 0.05,
+                room_chance: 0.05,
+                ..Default::default()
+            },
+            TunnelerParams {
+                lifespan: 20,
+                turn_chance: 0.2,
+                room_chance: 0.1,
+                ..Default::default()
+            },
+        ])
+    }
+}
+
+/// Check if the corridor with the given width can be carved without touching the map border
+fn fits(map: &RoomsMap, pos: IVec2, width: u32) -> bool {
+    let (before, after) = extent(width);
+    pos.x - before >= 1
+        && pos.y - before >= 1
+        && pos.x + after < map.width as i32 - 1
+        && pos.y + after < map.height as i32 - 1
+}
+
+/// Number of tiles carved before and after the tunneler position (same as `RoomsMap::carve_path`)
+fn extent(width: u32) -> (i32, i32) {
+    let width = width as i32;
+    ((width - 1) / 2, width / 2)
+}
+
+/// Turn left or right
+fn turn(rng: &mut Rng, dir: IVec2) -> IVec2 {
+    if rng.bool() {
+        IVec2::new(-dir.y, dir.x)
+    } else {
+        IVec2::new(dir.y, -dir.x)
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::path::DijkstraMap;
+    use glam::UVec2;
+
+    #[test]
+    fn test_connected() {
+        let mut rng = Rng::with_seed(100);
+        let map = Tunneler::default().generate(80, 50, &mut rng);
+        let center = UVec2::new(40, 25);
+        let dijkstra = DijkstraMap::new(&map.walkable_layer, &center);
+        for (i, &walkable) in map.walkable_layer.tiles.iter().enumerate() {
+            if walkable {
+                assert!(dijkstra.tiles[i] < f32::MAX);
+            }
+        }
+        for x in 0..80 {
+            assert!(map.is_blocked(x, 0));
+            assert!(map.is_blocked(x, 49));
+        }
+    }
+
+    #[test]
+    fn test_rooms() {
+        let mut rng = Rng::with_seed(100);
+        let map = Tunneler::default().generate(80, 50, &mut rng);
+        assert!(!map.rooms.is_empty());
+        assert!(!map.corridors.is_empty());
+        for (i, room) in map.rooms.iter().enumerate() {
+            for x in room.x1..room.x2 {
+                for y in room.y1..room.y2 {
+                    assert!(map.is_walkable(x, y));
+                }
+            }
+            for other in map.rooms.iter().skip(i + 1) {
+                assert!(!room.intersect(other));
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_generation() {
+        let params = TunnelerParams {
+            spawn_chance: 1.0,
+            room_chance: 0.0,
+            ..Default::default()
+        };
+        let mut rng = Rng::with_seed(100);
+        let map = Tunneler::new(vec![params]).generate(40, 30, &mut rng);
+        // Last generation doesn't spawn children
+        assert!(map.corridors.len() <= 2);
+        assert!(map.rooms.is_empty());
+    }
+
+    #[test]
+    fn test_wide_corridors() {
+        let params = TunnelerParams {
+            min_width: 3,
+            max_width: 3,
+            room_chance: 0.0,
+            ..Default::default()
+        };
+        let mut rng = Rng::with_seed(100);
+        let map = Tunneler::new(vec![params]).generate(40, 30, &mut rng);
+        // Every carved tile belongs to some corridor
+        let corridors: Vec<UVec2> = map.corridors.iter().flatten().copied().collect();
+        for (i, &walkable) in map.walkable_layer.tiles.iter().enumerate() {
+            if walkable {
+                let p = UVec2::new(i as u32 % 40, i as u32 / 40);
+                assert!(corridors.contains(&p), "{:?}", p);
+            }
+        }
+    }
+}
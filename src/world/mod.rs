@@ -0,0 +1,16 @@
+//! Generators for overworld maps.
+//! This process is done in few steps:
+//! * Generate terrain heights and biomes with [Overworld]
+//! * Add lakes and rivers with [Rivers]
+//! * Place settlements and connect them with [RoadNetwork]
+//!
+
+pub mod overworld;
+pub mod rivers;
+pub mod roads;
+pub mod tile_map;
+
+pub use overworld::{Falloff, Overworld};
+pub use rivers::Rivers;
+pub use roads::RoadNetwork;
+pub use tile_map::{Biome, Water, WorldMap};
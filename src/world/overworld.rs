@@ -0,0 +1,269 @@
+//! Overworld generator.
+//!
+//! Terrain height is created from the coherent noise shaped with the falloff mask
+//! (e.g. to create the island surrounded by the ocean).
+//! Moisture and temperature layers together with the height define the biome of each tile.
+//! Deep water and mountain peaks are not walkable.
+//!
+//! Example generator usage:
+//! ```
+//! use mapgen::world::{Biome, Falloff, Overworld};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let gen = Overworld::default().with_falloff(Falloff::Island);
+//! let map = gen.generate(80, 50, &mut rng);
+//!
+//! assert_eq!(map.width, 80);
+//! assert_eq!(map.biome(0, 0), Biome::Ocean);
+//! ```
+//!
+
+use fastrand::Rng;
+
+use super::{Biome, Water, WorldMap};
+use crate::layer::DataLayer;
+use crate::noise::Fbm;
+
+/// Mask which lowers the terrain near the map edges
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Falloff {
+    /// Use the raw noise
+    None,
+    /// Single island in the middle of the map
+    Island,
+    /// Land covers most of the map, with the ocean only along the edges
+    Continent,
+}
+
+pub struct Overworld {
+    height_noise: Fbm,
+    moisture_noise: Fbm,
+    temperature_noise: Fbm,
+    falloff: Falloff,
+    sea_level: f32,
+    deep_water_level: f32,
+    beach_width: f32,
+    mountain_level: f32,
+    snow_level: f32,
+    peak_level: f32,
+}
+
+impl Overworld {
+    pub fn new(height_noise: Fbm, falloff: Falloff) -> Self {
+        Self {
+            height_noise,
+            moisture_noise: Fbm::default().with_frequency(0.03),
+            temperature_noise: Fbm::default().with_frequency(0.02),
+            falloff,
+            sea_level: 0.35,
+            deep_water_level: 0.25,
+            beach_width: 0.03,
+            mountain_level: 0.7,
+            snow_level: 0.85,
+            peak_level: 0.92,
+        }
+    }
+
+    pub fn with_falloff(mut self, falloff: Falloff) -> Self {
+        self.falloff = falloff;
+        self
+    }
+
+    pub fn with_moisture_noise(mut self, noise: Fbm) -> Self {
+        self.moisture_noise = noise;
+        self
+    }
+
+    pub fn with_temperature_noise(mut self, noise: Fbm) -> Self {
+        self.temperature_noise = noise;
+        self
+    }
+
+    /// Height below which tiles are water. Water deeper than `deep_water_level` is not walkable
+    pub fn with_sea_level(mut self, sea_level: f32, deep_water_level: f32) -> Self {
+        self.sea_level = sea_level;
+        self.deep_water_level = f32::min(deep_water_level, sea_level);
+        self
+    }
+
+    /// Heights of the mountains and snow caps. Peaks above `peak_level` are not walkable
+    pub fn with_mountain_levels(mut self, mountain: f32, snow: f32, peak: f32) -> Self {
+        self.mountain_level = mountain;
+        self.snow_level = snow;
+        self.peak_level = peak;
+        self
+    }
+
+    pub fn generate(&self, map_width: u32, map_height: u32, rng: &mut Rng) -> WorldMap {
+        let mut map = WorldMap::new(map_width, map_height);
+        map.height_layer = self.height_layer(rng, map_width, map_height);
+        map.moisture_layer = self.moisture_noise.generate(rng, map_width, map_height);
+        map.temperature_layer = self.temperature_layer(rng, &map.height_layer);
+
+        for y in 0..map_height {
+            for x in 0..map_width {
+                let height = map.height_layer.get(x, y);
+                let biome = self.biome(
+                    height,
+                    map.moisture_layer.get(x, y),
+                    map.temperature_layer.get(x, y),
+                );
+                map.biome_layer.set(x, y, biome);
+                if biome == Biome::Ocean {
+                    map.water_layer.set(x, y, Water::Ocean);
+                }
+                let walkable = height >= self.deep_water_level && height < self.peak_level;
+                map.walkable_layer.set_walkable(x, y, walkable);
+            }
+        }
+
+        map
+    }
+
+    /// Noise shaped by the falloff mask and normalized to the range [0, 1]
+    fn height_layer(&self, rng: &mut Rng, width: u32, height: u32) -> DataLayer<f32> {
+        let mut layer = self.height_noise.generate(rng, width, height);
+        for y in 0..height {
+            for x in 0..width {
+                // Distance from the center, scaled to [0, 1] at the map edges
+                let dx = (2.0 * x as f32 + 1.0) / width as f32 - 1.0;
+                let dy = (2.0 * y as f32 + 1.0) / height as f32 - 1.0;
+                let mask = match self.falloff {
+                    Falloff::None => 1.0,
+                    Falloff::Island => 1.0 - (dx * dx + dy * dy).sqrt().min(1.0),
+                    Falloff::Continent => {
+                        let d = dx.abs().max(dy.abs());
+                        1.0 - smoothstep(0.7, 1.0, d)
+                    }
+                };
+                layer.set(x, y, layer.get(x, y) * mask);
+            }
+        }
+        normalize(&mut layer);
+        layer
+    }
+
+    /// Temperature drops towards the poles (top and bottom edge) and with the altitude
+    fn temperature_layer(&self, rng: &mut Rng, heights: &DataLayer<f32>) -> DataLayer<f32> {
+        let mut layer = self
+            .temperature_noise
+            .generate(rng, heights.width, heights.height);
+        for y in 0..heights.height {
+            let latitude = ((2.0 * y as f32 + 1.0) / heights.height as f32 - 1.0).abs();
+            for x in 0..heights.width {
+                let altitude = (heights.get(x, y) - self.sea_level).max(0.0);
+                let noise = layer.get(x, y) - 0.5;
+                let t = 1.0 - latitude * 0.8 + noise * 0.6 - altitude * 0.8;
+                layer.set(x, y, t.clamp(0.0, 1.0));
+            }
+        }
+        layer
+    }
+
+    fn biome(&self, height: f32, moisture: f32, temperature: f32) -> Biome {
+        if height < self.sea_level {
+            Biome::Ocean
+        } else if height < self.sea_level + self.beach_width {
+            Biome::Beach
+        } else if height >= self.snow_level || temperature < 0.15 {
+            Biome::Snow
+        } else if height >= self.mountain_level {
+            Biome::Mountain
+        } else if temperature > 0.6 && moisture < 0.45 {
+            Biome::Desert
+        } else if moisture > 0.55 {
+            Biome::Forest
+        } else {
+            Biome::Grassland
+        }
+    }
+}
+
+impl Default for Overworld {
+    fn default() -> Self {
+        Self::new(
+            Fbm::default().with_frequency(0.03).with_octaves(5),
+            Falloff::Continent,
+        )
+    }
+}
+
+fn smoothstep(edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+/// Stretch values to the range [0, 1]
+fn normalize(layer: &mut DataLayer<f32>) {
+    let min = layer.walkables.iter().cloned().fold(f32::MAX, f32::min);
+    let max = layer.walkables.iter().cloned().fold(f32::MIN, f32::max);
+    if max > min {
+        for v in layer.walkables.iter_mut() {
+            *v = (*v - min) / (max - min);
+        }
+    }
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_layers() {
+        let mut rng = Rng::with_seed(100);
+        let map = Overworld::default().generate(80, 50, &mut rng);
+        for layer in [
+            &map.height_layer,
+            &map.moisture_layer,
+            &map.temperature_layer,
+        ] {
+            assert_eq!(layer.walkables.len(), 80 * 50);
+            assert!(layer.walkables.iter().all(|v| (0.0..=1.0).contains(v)));
+        }
+        let biomes = &map.biome_layer.walkables;
+        assert!(biomes.contains(&Biome::Ocean));
+        assert!(biomes.contains(&Biome::Grassland) || biomes.contains(&Biome::Forest));
+    }
+
+    #[test]
+    fn test_island() {
+        let mut rng = Rng::with_seed(100);
+        let map = Overworld::default()
+            .with_falloff(Falloff::Island)
+            .generate(80, 50, &mut rng);
+        for x in 0..80 {
+            assert_eq!(map.biome(x, 0), Biome::Ocean);
+            assert_eq!(map.biome(x, 49), Biome::Ocean);
+        }
+        // The highest point is the mountain peak
+        let (idx, _) =
+            map.height_layer
+                .walkables
+                .iter()
+                .enumerate()
+                .fold(
+                    (0, 0.0),
+                    |best, (i, &h)| if h > best.1 { (i, h) } else { best },
+                );
+        assert_eq!(map.biome_layer.walkables[idx], Biome::Snow);
+        assert!(!map.walkable_layer.tiles[idx]);
+    }
+
+    #[test]
+    fn test_walkable() {
+        let mut rng = Rng::with_seed(100);
+        let map = Overworld::default()
+            .with_falloff(Falloff::Island)
+            .generate(80, 50, &mut rng);
+        // Map corner is the deep ocean
+        assert!(map.is_blocked(0, 0));
+        for (i, &h) in map.height_layer.walkables.iter().enumerate() {
+            let expected = (0.25..0.92).contains(&h);
+            assert_eq!(map.walkable_layer.tiles[i], expected);
+        }
+    }
+}
@@ -0,0 +1,415 @@
+//! Rivers and lakes generator.
+//!
+//! Local minima of the terrain are filled up to create lakes (priority flood algorithm).
+//! Then the water flows from each tile to its lowest neighbour. Rivers start at random
+//! high places and follow this flow to the sea, to lakes or until they join other river.
+//! River width grows with the amount of water flowing through the tile.
+//!
+//! Deep rivers and lakes are not walkable. Deep rivers can be crossed at bridges.
+//!
+//! Example generator usage:
+//! ```
+//! use mapgen::world::{Falloff, Overworld, Rivers};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let world = Overworld::default().with_falloff(Falloff::Island).generate(80, 50, &mut rng);
+//! let map = Rivers::default().generate(&world, &mut rng);
+//!
+//! assert_eq!(map.width, 80);
+//! assert!(!map.rivers.is_empty());
+//! ```
+//!
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+
+use fastrand::Rng;
+
+use super::{Water, WorldMap};
+
+/// Minimal slope added to the filled depressions, so water can always flow out
+const EPSILON: f32 = 1e-5;
+
+pub struct Rivers {
+    num_rivers: u32,
+    source_level: f32,
+    min_lake_depth: f32,
+    flow_per_width: f32,
+    max_width: u32,
+    deep_width: u32,
+    bridge_spacing: u32,
+}
+
+impl Rivers {
+    pub fn new(num_rivers: u32) -> Self {
+        Self {
+            num_rivers,
+            source_level: 0.6,
+            min_lake_depth: 0.01,
+            flow_per_width: 150.0,
+            max_width: 4,
+            deep_width: 2,
+            bridge_spacing: 20,
+        }
+    }
+
+    /// Minimum height of the river source
+    pub fn with_source_level(mut self, source_level: f32) -> Self {
+        self.source_level = source_level;
+        self
+    }
+
+    /// Depressions shallower than this are not turned into lakes
+    pub fn with_min_lake_depth(mut self, min_lake_depth: f32) -> Self {
+        self.min_lake_depth = min_lake_depth;
+        self
+    }
+
+    /// River gets 1 tile wider each time the flow grows by `flow_per_width`
+    pub fn with_width(mut self, flow_per_width: f32, max_width: u32) -> Self {
+        self.flow_per_width = flow_per_width.max(f32::EPSILON);
+        self.max_width = u32::max(max_width, 1);
+        self
+    }
+
+    /// Rivers at least this wide are not walkable
+    pub fn with_deep_width(mut self, deep_width: u32) -> Self {
+        self.deep_width = deep_width;
+        self
+    }
+
+    /// Distance between bridges on the deep rivers. 0 disables bridges
+    pub fn with_bridge_spacing(mut self, bridge_spacing: u32) -> Self {
+        self.bridge_spacing = bridge_spacing;
+        self
+    }
+
+    pub fn generate(&self, map: &WorldMap, rng: &mut Rng) -> WorldMap {
+        let mut new_map = map.clone();
+        if map.width == 0 || map.height == 0 {
+            return new_map;
+        }
+        let filled = fill_depressions(map);
+        let downstream = flow_directions(map, &filled);
+        let flow = flow_accumulation(map, &filled, &downstream);
+
+        // Lakes
+        for (idx, (&f, &h)) in filled.iter().zip(&map.height_layer.walkables).enumerate() {
+            if map.water_layer.walkables[idx] == Water::None && f - h >= self.min_lake_depth {
+                new_map.water_layer.walkables[idx] = Water::Lake;
+                new_map.walkable_layer.tiles[idx] = false;
+            }
+        }
+
+        // Rivers
+        let mut sources: Vec<usize> = (0..filled.len())
+            .filter(|&i| {
+                map.height_layer.walkables[i] >= self.source_level
+                    && new_map.water_layer.walkables[i] == Water::None
+            })
+            .collect();
+        rng.shuffle(&mut sources);
+        let mut widths = vec![0; filled.len()];
+        let mut num_rivers = 0;
+        for source in sources {
+            if num_rivers >= self.num_rivers {
+                break;
+            }
+            if widths[source] > 0 {
+                continue;
+            }
+            let path = self.trace(&new_map, &downstream, &widths, source);
+            if path.len() < 2 {
+                continue;
+            }
+            num_rivers += 1;
+            for &idx in path.iter() {
+                if new_map.water_layer.walkables[idx] == Water::None {
+                    let width = self.width(flow[idx]);
+                    self.carve(&mut new_map, &mut widths, idx, width);
+                }
+            }
+            // Split the path at the lakes
+            let mut river = Vec::new();
+            for &idx in path.iter() {
+                if new_map.water_layer.walkables[idx] == Water::Lake {
+                    if !river.is_empty() {
+                        river.push(new_map.walkable_layer.idx_point(idx));
+                        new_map.rivers.push(river);
+                    }
+                    river = Vec::new();
+                } else {
+                    river.push(new_map.walkable_layer.idx_point(idx));
+                }
+            }
+            if river.len() > 1 {
+                new_map.rivers.push(river);
+            }
+        }
+
+        self.place_bridges(&mut new_map, &widths);
+        new_map
+    }
+
+    /// Follow the flow until the river reaches the sea, other river or the map edge
+    fn trace(
+        &self,
+        map: &WorldMap,
+        downstream: &[usize],
+        widths: &[u32],
+        source: usize,
+    ) -> Vec<usize> {
+        let mut path = vec![source];
+        let mut idx = source;
+        while downstream[idx] != NO_TILE {
+            idx = downstream[idx];
+            if map.water_layer.walkables[idx] == Water::Ocean {
+                break;
+            }
+            path.push(idx);
+            if widths[idx] > 0 {
+                break;
+            }
+        }
+        path
+    }
+
+    fn width(&self, flow: f32) -> u32 {
+        u32::min(1 + (flow / self.flow_per_width) as u32, self.max_width)
+    }
+
+    /// Mark the river tiles around the given tile
+    fn carve(&self, map: &mut WorldMap, widths: &mut [u32], idx: usize, width: u32) {
+        let p = map.walkable_layer.idx_point(idx);
+        let from = -((width as i64 - 1) / 2);
+        let to = width as i64 / 2;
+        for dy in from..=to {
+            for dx in from..=to {
+                let (x, y) = (p.x as i64 + dx, p.y as i64 + dy);
+                if x < 0 || y < 0 || x >= map.width as i64 || y >= map.height as i64 {
+                    continue;
+                }
+                let n_idx = map.xy_idx(x as u32, y as u32);
+                if matches!(map.water_layer.walkables[n_idx], Water::None | Water::River) {
+                    map.water_layer.walkables[n_idx] = Water::River;
+                    widths[n_idx] = u32::max(widths[n_idx], width);
+                    if width >= self.deep_width {
+                        map.walkable_layer.tiles[n_idx] = false;
+                    }
+                }
+            }
+        }
+    }
+
+    /// Make deep rivers walkable at regular distances
+    fn place_bridges(&self, map: &mut WorldMap, widths: &[u32]) {
+        if self.bridge_spacing == 0 {
+            return;
+        }
+        let mut bridges = Vec::new();
+        for river in map.rivers.iter() {
+            let spacing = self.bridge_spacing as usize;
+            for &p in river.iter().skip(spacing / 2).step_by(spacing) {
+                if widths[map.xy_idx(p.x, p.y)] >= self.deep_width {
+                    bridges.push(p);
+                }
+            }
+        }
+
+        let radius = self.max_width as i64 / 2 + 1;
+        for p in bridges.iter() {
+            for dy in -radius..=radius {
+                for dx in -radius..=radius {
+                    let (x, y) = (p.x as i64 + dx, p.y as i64 + dy);
+                    if x < 0 || y < 0 || x >= map.width as i64 || y >= map.height as i64 {
+                        continue;
+                    }
+                    let idx = map.xy_idx(x as u32, y as u32);
+                    if map.water_layer.walkables[idx] == Water::River {
+                        map.walkable_layer.tiles[idx] = true;
+                    }
+                }
+            }
+        }
+        map.bridges.extend(bridges);
+    }
+}
+
+impl Default for Rivers {
+    fn default() -> Self {
+        Self::new(10)
+    }
+}
+
+const NO_TILE: usize = usize::MAX;
+
+const NEIGHBORS: [(i64, i64); 8] = [
+    (-1, 0),
+    (1, 0),
+    (0, -1),
+    (0, 1),
+    (-1, -1),
+    (1, -1),
+    (-1, 1),
+    (1, 1),
+];
+
+fn neighbors(map: &WorldMap, idx: usize) -> impl Iterator<Item = usize> + '_ {
+    let p = map.walkable_layer.idx_point(idx);
+    NEIGHBORS.iter().filter_map(move |(dx, dy)| {
+        let (x, y) = (p.x as i64 + dx, p.y as i64 + dy);
+        if x < 0 || y < 0 || x >= map.width as i64 || y >= map.height as i64 {
+            None
+        } else {
+            Some(map.xy_idx(x as u32, y as u32))
+        }
+    })
+}
+
+/// Raise the local minima until the water can flow out to the sea or the map edge
+fn fill_depressions(map: &WorldMap) -> Vec<f32> {
+    let heights = &map.height_layer.walkables;
+    let mut filled = vec![f32::MAX; heights.len()];
+    let mut queue = BinaryHeap::new();
+    for idx in 0..heights.len() {
+        let p = map.walkable_layer.idx_point(idx);
+        let is_edge = p.x == 0 || p.y == 0 || p.x == map.width - 1 || p.y == map.height - 1;
+        if is_edge || map.water_layer.walkables[idx] == Water::Ocean {
+            filled[idx] = heights[idx];
+            // Non negative floats keep their order when compared as bits
+            queue.push(Reverse((heights[idx].max(0.0).to_bits(), idx)));
+        }
+    }
+
+    while let Some(Reverse((_, idx))) = queue.pop() {
+        for n in neighbors(map, idx) {
+            if filled[n] == f32::MAX {
+                filled[n] = f32::max(heights[n], filled[idx] + EPSILON);
+                queue.push(Reverse((filled[n].max(0.0).to_bits(), n)));
+            }
+        }
+    }
+    filled
+}
+
+/// Lowest neighbour of each tile. Sea and map edges don't have downstream tile
+fn flow_directions(map: &WorldMap, filled: &[f32]) -> Vec<usize> {
+    (0..filled.len())
+        .map(|idx| {
+            if map.water_layer.walkables[idx] == Water::Ocean {
+                return NO_TILE;
+            }
+            neighbors(map, idx)
+                .filter(|&n| filled[n] < filled[idx])
+                .min_by(|&a, &b| filled[a].total_cmp(&filled[b]))
+                .unwrap_or(NO_TILE)
+        })
+        .collect()
+}
+
+/// Amount of the rain water flowing through each tile. Wet regions get more rain
+fn flow_accumulation(map: &WorldMap, filled: &[f32], downstream: &[usize]) -> Vec<f32> {
+    let mut flow: Vec<f32> = map
+        .moisture_layer
+        .walkables
+        .iter()
+        .map(|m| 0.5 + m)
+        .collect();
+    let mut order: Vec<usize> = (0..filled.len()).collect();
+    order.sort_by(|&a, &b| filled[b].total_cmp(&filled[a]));
+    for idx in order {
+        if downstream[idx] != NO_TILE {
+            flow[downstream[idx]] += flow[idx];
+        }
+    }
+    flow
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Falloff, Overworld};
+
+    fn world() -> WorldMap {
+        let mut rng = Rng::with_seed(100);
+        Overworld::default()
+            .with_falloff(Falloff::Island)
+            .generate(80, 50, &mut rng)
+    }
+
+    #[test]
+    fn test_rivers_flow_downhill() {
+        let world = world();
+        let mut rng = Rng::with_seed(100);
+        let map = Rivers::default().generate(&world, &mut rng);
+        assert!(!map.rivers.is_empty());
+        for river in map.rivers.iter() {
+            let first = river[0];
+            let last = river[river.len() - 1];
+            assert!(
+                world.height_layer.get(last.x, last.y) <= world.height_layer.get(first.x, first.y)
+            );
+            for w in river.windows(2) {
+                assert!(w[0].x.abs_diff(w[1].x) <= 1 && w[0].y.abs_diff(w[1].y) <= 1);
+            }
+            for p in river.iter() {
+                assert_ne!(map.water(p.x, p.y), Water::None);
+            }
+        }
+    }
+
+    #[test]
+    fn test_lake() {
+        // Bowl surrounded by the sea
+        let mut world = WorldMap::new(9, 9);
+        for y in 0..9 {
+            for x in 0..9 {
+                let d = i32::max((x as i32 - 4).abs(), (y as i32 - 4).abs());
+                let h = match d {
+                    4 => 0.0,
+                    3 => 0.8,
+                    _ => 0.2 + d as f32 * 0.1,
+                };
+                world.height_layer.set(x, y, h);
+                world.walkable_layer.set_walkable(x, y, true);
+                if d == 4 {
+                    world.water_layer.set(x, y, Water::Ocean);
+                }
+            }
+        }
+        let mut rng = Rng::with_seed(100);
+        let map = Rivers::new(0).generate(&world, &mut rng);
+        for y in 2..7 {
+            for x in 2..7 {
+                assert_eq!(map.water(x, y), Water::Lake);
+                assert!(map.is_blocked(x, y));
+            }
+        }
+        assert_eq!(map.water(1, 1), Water::None);
+    }
+
+    #[test]
+    fn test_bridges() {
+        let world = world();
+        let mut rng = Rng::with_seed(100);
+        let map = Rivers::new(20)
+            .with_width(20.0, 3)
+            .with_bridge_spacing(10)
+            .generate(&world, &mut rng);
+        assert!(!map.bridges.is_empty());
+        for b in map.bridges.iter() {
+            assert!(map.is_walkable(b.x, b.y));
+        }
+        let deep_river = map
+            .water_layer
+            .walkables
+            .iter()
+            .zip(map.walkable_layer.tiles.iter())
+            .any(|(&w, &walkable)| w == Water::River && !walkable);
+        assert!(deep_river);
+    }
+}
@@ -0,0 +1,233 @@
+//! Settlements connected with the road network.
+//!
+//! Settlements are placed with Poisson disk sampling, so they are not too close to each other.
+//! Good terrain (e.g. grassland) gets more settlements than mountains or deserts.
+//!
+//! Settlements are connected with the spanning tree of the shortest roads, and a few extra roads
+//! between close settlements. Each road follows the cheapest path over the terrain.
+//! Existing roads are cheaper to travel, so roads running close together merge.
+//!
+//! Example generator usage:
+//! ```
+//! use mapgen::world::{Falloff, Overworld, RoadNetwork};
+//! use fastrand::Rng;
+//!
+//! let mut rng = Rng::with_seed(100);
+//! let world = Overworld::default().with_falloff(Falloff::Island).generate(80, 50, &mut rng);
+//! let map = RoadNetwork::default().generate(&world, &mut rng);
+//!
+//! assert!(!map.settlements.is_empty());
+//! ```
+//!
+
+use fastrand::Rng;
+use glam::UVec2;
+
+use super::{Biome, Water, WorldMap};
+use crate::geometry::poisson_disk;
+use crate::graph::DisjointSet;
+use crate::layer::DataLayer;
+use crate::path::DijkstraMap;
+
+pub struct RoadNetwork {
+    min_distance: f32,
+    max_settlements: usize,
+    extra_roads: f32,
+    max_extra_road_length: f32,
+    road_discount: f32,
+}
+
+impl RoadNetwork {
+    /// Create generator. Settlements are at least `min_distance` tiles apart
+    pub fn new(min_distance: f32, max_settlements: usize) -> Self {
+        Self {
+            min_distance,
+            max_settlements,
+            extra_roads: 0.3,
+            max_extra_road_length: 2.0 * min_distance,
+            road_discount: 0.3,
+        }
+    }
+
+    /// Probability of building the extra road (creating loop) between settlements closer
+    /// than `max_length`
+    pub fn with_extra_roads(mut self, probability: f32, max_length: f32) -> Self {
+        self.extra_roads = probability;
+        self.max_extra_road_length = max_length;
+        self
+    }
+
+    /// Cost multiplier of the existing roads. Lower values merge roads more eagerly
+    pub fn with_road_discount(mut self, road_discount: f32) -> Self {
+        self.road_discount = road_discount.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn generate(&self, map: &WorldMap, rng: &mut Rng) -> WorldMap {
+        let mut new_map = map.clone();
+        let mut settlements = poisson_disk(rng, map.width, map.height, self.min_distance, |p| {
+            suitability(map, p)
+        });
+        // Samples grow out from the first one, so the first samples are clustered together
+        rng.shuffle(&mut settlements);
+        settlements.truncate(self.max_settlements);
+        let base_costs = terrain_costs(map);
+
+        // Candidate roads sorted from the shortest
+        let mut edges: Vec<(usize, usize, f32)> = Vec::new();
+        for (i, a) in settlements.iter().enumerate() {
+            for (j, b) in settlements.iter().enumerate().skip(i + 1) {
+                edges.push((i, j, a.as_vec2().distance(b.as_vec2())));
+            }
+        }
+        edges.sort_by(|a, b| a.2.total_cmp(&b.2));
+
+        let mut sets = DisjointSet::new(settlements.len());
+        for (a, b, distance) in edges {
+            let connected = sets.find(a) == sets.find(b);
+            if connected && (distance > self.max_extra_road_length || rng.f32() >= self.extra_roads)
+            {
+                continue;
+            }
+            let costs = self.costs_with_roads(&base_costs, &new_map.road_layer);
+            let path = DijkstraMap::with_costs(&costs, &settlements[a]).path_to(&settlements[b]);
+            if path.is_empty() {
+                // Not reachable e.g. on the other island
+                continue;
+            }
+            for p in path {
+                new_map.road_layer.set(p.x, p.y, true);
+            }
+            sets.union(a, b);
+            new_map.roads.push((a, b));
+        }
+
+        new_map.settlements.extend(settlements);
+        new_map
+    }
+
+    fn costs_with_roads(&self, costs: &DataLayer<f32>, roads: &DataLayer<bool>) -> DataLayer<f32> {
+        let mut costs = costs.clone();
+        for (cost, &road) in costs.walkables.iter_mut().zip(roads.walkables.iter()) {
+            if road {
+                *cost *= self.road_discount;
+            }
+        }
+        costs
+    }
+}
+
+impl Default for RoadNetwork {
+    fn default() -> Self {
+        Self::new(12.0, 30)
+    }
+}
+
+/// How good is the tile for the settlement (0..1)
+fn suitability(map: &WorldMap, p: UVec2) -> f32 {
+    if map.is_blocked(p.x, p.y) || map.water(p.x, p.y) != Water::None {
+        return 0.0;
+    }
+    match map.biome(p.x, p.y) {
+        Biome::Grassland => 1.0,
+        Biome::Forest => 0.5,
+        Biome::Beach => 0.4,
+        Biome::Desert => 0.2,
+        Biome::Mountain => 0.1,
+        Biome::Snow | Biome::Ocean => 0.0,
+    }
+}
+
+/// Cost of building the road through the tile
+fn terrain_costs(map: &WorldMap) -> DataLayer<f32> {
+    let mut costs = DataLayer::new(map.width, map.height, f32::INFINITY);
+    for y in 0..map.height {
+        for x in 0..map.width {
+            if map.is_blocked(x, y) {
+                continue;
+            }
+            let cost = match map.water(x, y) {
+                Water::Ocean | Water::Lake => f32::INFINITY,
+                // Shallow river or bridge
+                Water::River => 3.0,
+                Water::None => match map.biome(x, y) {
+                    Biome::Grassland => 1.0,
+                    Biome::Beach | Biome::Desert => 1.5,
+                    Biome::Forest => 2.0,
+                    Biome::Mountain => 5.0,
+                    Biome::Snow => 8.0,
+                    Biome::Ocean => f32::INFINITY,
+                },
+            };
+            costs.set(x, y, cost);
+        }
+    }
+    costs
+}
+
+/// ------------------------------------------------------------------------------------------------
+/// Module unit tests
+/// ------------------------------------------------------------------------------------------------
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::world::{Falloff, Overworld, Rivers};
+
+    fn world() -> WorldMap {
+        let mut rng = Rng::with_seed(100);
+        let world = Overworld::default()
+            .with_falloff(Falloff::Island)
+            .generate(80, 50, &mut rng);
+        Rivers::default().generate(&world, &mut rng)
+    }
+
+    #[test]
+    fn test_settlements() {
+        let world = world();
+        let mut rng = Rng::with_seed(100);
+        let map = RoadNetwork::new(10.0, 20).generate(&world, &mut rng);
+        assert!(map.settlements.len() > 1);
+        assert!(map.settlements.len() <= 20);
+        for s in map.settlements.iter() {
+            assert!(suitability(&map, *s) > 0.0);
+            assert!(map.is_road(s.x, s.y));
+        }
+    }
+
+    #[test]
+    fn test_roads() {
+        let world = world();
+        let mut rng = Rng::with_seed(100);
+        let map = RoadNetwork::new(10.0, 20).generate(&world, &mut rng);
+        assert!(map.roads.len() >= map.settlements.len() - 1);
+        for y in 0..map.height {
+            for x in 0..map.width {
+                if map.is_road(x, y) {
+                    assert!(map.is_walkable(x, y));
+                    assert_ne!(map.water(x, y), Water::Ocean);
+                }
+            }
+        }
+        // All settlements are connected
+        let mut sets = DisjointSet::new(map.settlements.len());
+        for &(a, b) in map.roads.iter() {
+            sets.union(a, b);
+        }
+        let root = sets.find(0);
+        assert!((0..map.settlements.len()).all(|i| sets.find(i) == root));
+    }
+
+    #[test]
+    fn test_road_merging() {
+        let world = world();
+        let count_roads = |discount: f32| {
+            let mut rng = Rng::with_seed(100);
+            let map = RoadNetwork::new(10.0, 20)
+                .with_extra_roads(1.0, 30.0)
+                .with_road_discount(discount)
+                .generate(&world, &mut rng);
+            map.road_layer.walkables.iter().filter(|&&r| r).count()
+        };
+        assert!(count_roads(0.1) < count_roads(1.0));
+    }
+}
@@ -0,0 +1,130 @@
+//! Overworld map with terrain layers.
+//!
+
+use std::fmt;
+
+use glam::UVec2;
+
+use crate::layer::{DataLayer, WalkableLayer};
+
+/// Terrain type of the world tile
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Biome {
+    #[default]
+    Ocean,
+    Beach,
+    Grassland,
+    Forest,
+    Desert,
+    Mountain,
+    Snow,
+}
+
+impl Biome {
+    /// Character used to print the map
+    pub fn symbol(&self) -> char {
+        match self {
+            Biome::Ocean => '~',
+            Biome::Beach => '.',
+            Biome::Grassland => '"',
+            Biome::Forest => 'T',
+            Biome::Desert => ':',
+            Biome::Mountain => '^',
+            Biome::Snow => '*',
+        }
+    }
+}
+
+/// Water covering the world tile
+#[derive(Default, Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum Water {
+    #[default]
+    None,
+    Ocean,
+    Lake,
+    River,
+}
+
+#[derive(Default, Clone)]
+pub struct WorldMap {
+    pub width: u32,
+    pub height: u32,
+    /// Terrain height in the range [0, 1]
+    pub height_layer: DataLayer<f32>,
+    /// Moisture in the range [0, 1]
+    pub moisture_layer: DataLayer<f32>,
+    /// Temperature in the range [0, 1]
+    pub temperature_layer: DataLayer<f32>,
+    pub biome_layer: DataLayer<Biome>,
+    pub water_layer: DataLayer<Water>,
+    pub walkable_layer: WalkableLayer,
+    /// River paths from the source to the mouth
+    pub rivers: Vec<Vec<UVec2>>,
+    /// Places where deep rivers can be crossed
+    pub bridges: Vec<UVec2>,
+    pub settlements: Vec<UVec2>,
+    pub road_layer: DataLayer<bool>,
+    /// Pairs of settlements (indexes) connected with the road
+    pub roads: Vec<(usize, usize)>,
+}
+
+impl WorldMap {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            height_layer: DataLayer::new(width, height, 0.0),
+            moisture_layer: DataLayer::new(width, height, 0.0),
+            temperature_layer: DataLayer::new(width, height, 0.0),
+            biome_layer: DataLayer::new(width, height, Biome::Ocean),
+            water_layer: DataLayer::new(width, height, Water::None),
+            walkable_layer: WalkableLayer::new(width, height),
+            rivers: Vec::new(),
+            bridges: Vec::new(),
+            settlements: Vec::new(),
+            road_layer: DataLayer::new(width, height, false),
+            roads: Vec::new(),
+        }
+    }
+
+    pub fn biome(&self, x: u32, y: u32) -> Biome {
+        self.biome_layer.get(x, y)
+    }
+
+    pub fn water(&self, x: u32, y: u32) -> Water {
+        self.water_layer.get(x, y)
+    }
+
+    pub fn is_road(&self, x: u32, y: u32) -> bool {
+        self.road_layer.get(x, y)
+    }
+
+    pub fn is_walkable(&self, x: u32, y: u32) -> bool {
+        self.walkable_layer.is_walkable(x, y)
+    }
+
+    pub fn is_blocked(&self, x: u32, y: u32) -> bool {
+        !self.is_walkable(x, y)
+    }
+
+    pub fn xy_idx(&self, x: u32, y: u32) -> usize {
+        self.walkable_layer.xy_idx(x, y)
+    }
+}
+
+impl fmt::Display for WorldMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for y in 0..self.height {
+            let line: String = (0..self.width)
+                .map(|x| match self.water(x, y) {
+                    _ if self.settlements.contains(&UVec2::new(x, y)) => '@',
+                    _ if self.is_road(x, y) => '+',
+                    Water::Lake | Water::River => '=',
+                    _ => self.biome(x, y).symbol(),
+                })
+                .collect();
+            let _ = writeln!(f, "{}", line);
+        }
+        Ok(())
+    }
+}